@@ -0,0 +1,10 @@
+//! Parser for DASH (.mpd) manifests.
+
+mod locator;
+mod playlist;
+mod template;
+
+use locator::DashUrl;
+use template::Template;
+
+pub use playlist::{parse_as_master, push_segments};
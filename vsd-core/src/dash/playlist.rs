@@ -12,13 +12,18 @@
 use super::{DashUrl, Template};
 use crate::playlist::{
     Key, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Range, Segment,
+    SplitOn,
 };
 use anyhow::{anyhow, bail, Result};
-use dash_mpd::MPD;
+use dash_mpd::{Period, MPD};
 use reqwest::Url;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
+/// Parse dash manifest into streams without segments.
+pub fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
     let mut streams = vec![];
 
     if let Some(period) = mpd.periods.get(0) {
@@ -63,6 +68,14 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                     }
                 }
 
+                // https://dashif.org/docs/DASH-IF-IOP-v4.3.pdf (forced-subtitle role)
+                let forced = adaptation_set.Role.iter().any(|x| {
+                    matches!(
+                        x.value.as_deref(),
+                        Some("forced-subtitle") | Some("forced_subtitle")
+                    )
+                });
+
                 // if let Some(role) = &representation.role {
                 //     if let Some(value) = &role.value {
                 //         if value == "subtitle" {
@@ -71,6 +84,14 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                 //     }
                 // }
 
+                // Same as segments, streams having protection scheme value are cenc encrypted.
+                let drm = representation
+                    .ContentProtection
+                    .iter()
+                    .chain(&adaptation_set.ContentProtection)
+                    .any(|x| x.value.is_some())
+                    .then_some(KeyMethod::Cenc);
+
                 streams.push(MediaPlaylist {
                     bandwidth: representation.bandwidth,
                     channels: representation
@@ -84,9 +105,11 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                             .and_then(|x| x.value.as_ref().map(|y| y.parse::<f32>().ok()))
                             .flatten()),
                     codecs,
+                    drm,
                     extension: mime_type
                         .as_ref()
                         .and_then(|x| x.split_once('/').map(|x| x.1.to_owned())),
+                    forced,
                     frame_rate: if representation.frameRate.is_some() {
                         parse_frame_rate(&representation.frameRate)
                     } else if adaptation_set.frameRate.is_some() {
@@ -101,6 +124,10 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
                     } else {
                         false
                     },
+                    locator: Some(
+                        DashUrl::new(period_index, adaptation_index, representation_index)
+                            .to_string(),
+                    ),
                     media_type,
                     playlist_type: PlaylistType::Dash,
                     resolution: if let (Some(width), Some(height)) =
@@ -125,10 +152,14 @@ pub(crate) fn parse_as_master(mpd: &MPD, uri: &str) -> MasterPlaylist {
     }
 }
 
-pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &str) -> Result<()> {
+/// Push segments of stream from dash manifest, stream uri should be its locator from
+/// [`parse_as_master`].
+pub fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &str) -> Result<()> {
     let location = playlist.uri.parse::<DashUrl>().map_err(|x| anyhow!(x))?;
 
     for (_period_index, period) in mpd.periods.iter().enumerate() {
+        let period_start = playlist.segments.len();
+
         for (adaptation_index, adaptation_set) in period.adaptations.iter().enumerate() {
             for (representation_index, representation) in
                 adaptation_set.representations.iter().enumerate()
@@ -293,6 +324,14 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                             if let Some(media) = &segment_template.media {
                                 let media = template.resolve(media);
                                 let timescale = segment_template.timescale.unwrap_or(1) as f32;
+                                let presentation_time_offset =
+                                    segment_template.presentationTimeOffset.unwrap_or(0);
+                                let program_date_time = |time: u64| {
+                                    live_start(mpd, period).map(|x| {
+                                        x + time.saturating_sub(presentation_time_offset) as f64
+                                            / timescale as f64
+                                    })
+                                };
                                 let mut segment_time = 0;
                                 let mut number = segment_template.startNumber.unwrap_or(1);
 
@@ -306,6 +345,7 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
 
                                     playlist.segments.push(Segment {
                                         duration: s.d as f32 / timescale,
+                                        program_date_time: program_date_time(segment_time),
                                         uri: base_url.join(&template.resolve(&media))?.to_string(),
                                         ..Default::default()
                                     });
@@ -345,6 +385,7 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
 
                                             playlist.segments.push(Segment {
                                                 duration: s.d as f32 / timescale,
+                                                program_date_time: program_date_time(segment_time),
                                                 uri: base_url
                                                     .join(&template.resolve(&media))?
                                                     .to_string(),
@@ -379,7 +420,8 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                                     bail!("Representation is missing SegmentTemplate @duration attribute.");
                                 }
 
-                                let mut number = segment_template.startNumber.unwrap_or(1) as i64;
+                                let start_number = segment_template.startNumber.unwrap_or(1) as i64;
+                                let mut number = start_number;
 
                                 let mut total_number =
                                     number + (period_duration_secs / duration).ceil() as i64;
@@ -388,11 +430,21 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                                     total_number -= 1;
                                 }
 
+                                // Segments of live streams are numbered from availability start
+                                // time, only ones inside time shift buffer can be fetched.
+                                if let Some((offset, count)) = live_numbers(mpd, period, duration) {
+                                    number += offset;
+                                    total_number = count;
+                                }
+
                                 for _ in 1..=total_number {
                                     template.insert("Number", number.to_string());
 
                                     playlist.segments.push(Segment {
                                         duration,
+                                        program_date_time: live_start(mpd, period).map(|x| {
+                                            x + (number - start_number) as f64 * duration as f64
+                                        }),
                                         uri: base_url.join(&template.resolve(&media))?.to_string(),
                                         ..Default::default()
                                     });
@@ -498,6 +550,14 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
                 }
             }
         }
+
+        // Each period is marked as a chapter when there are multiple periods.
+        if mpd.periods.len() > 1 {
+            if let Some(segment) = playlist.segments.get_mut(period_start) {
+                segment.boundaries.push(SplitOn::Period);
+                segment.chapter = Some(period.id.clone().unwrap_or_default());
+            }
+        }
     }
 
     // if let Some(segment_template) = representation.segment_template(adaptation_set) {
@@ -607,6 +667,39 @@ pub(crate) fn push_segments(mpd: &MPD, playlist: &mut MediaPlaylist, base_url: &
     Ok(())
 }
 
+/// Wall clock time at start of period of a live stream in seconds since unix epoch,
+/// `None` if manifest isn't dynamic.
+fn live_start(mpd: &MPD, period: &Period) -> Option<f64> {
+    if mpd.mpdtype.as_deref() != Some("dynamic") {
+        return None;
+    }
+
+    Some(
+        mpd.availabilityStartTime?.timestamp_millis() as f64 / 1000.0
+            + period.start.map(|x| x.as_secs_f64()).unwrap_or_default(),
+    )
+}
+
+/// Offset from start number and count of segments which are currently available in a live
+/// stream using simple addressing, `None` if manifest isn't dynamic.
+fn live_numbers(mpd: &MPD, period: &Period, duration: f32) -> Option<(i64, i64)> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs_f64();
+    let elapsed = now - live_start(mpd, period)?;
+    let duration = duration as f64;
+    let available = (elapsed / duration).floor() as i64;
+    let buffered = mpd
+        .timeShiftBufferDepth
+        .map(|x| (x.as_secs_f64() / duration).floor() as i64)
+        .unwrap_or(available)
+        .min(available)
+        .max(0);
+
+    Some(((available - buffered).max(0), buffered))
+}
+
 fn parse_frame_rate(frame_rate: &Option<String>) -> Option<f32> {
     frame_rate.as_ref().and_then(|frame_rate| {
         if frame_rate.contains('/') {
@@ -0,0 +1,103 @@
+//! Decryption of AES-128 and common encryption (cenc) segments.
+
+use crate::playlist::KeyMethod;
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+/// Keys used for decrypting segments of a stream.
+#[derive(Clone)]
+pub struct Keys {
+    bytes: Vec<u8>,
+    iv: Option<String>,
+    method: KeyMethod,
+}
+
+impl Keys {
+    /// Key for segments encrypted using AES-128 method, iv is a hex string.
+    pub fn from_aes_128(bytes: Vec<u8>, iv: Option<String>) -> Self {
+        Self {
+            bytes,
+            iv,
+            method: KeyMethod::Aes128,
+        }
+    }
+
+    /// Keys for segments encrypted using common encryption, a map of key ids to keys as
+    /// hex strings.
+    pub fn from_hex_keys(keys: HashMap<String, String>) -> Self {
+        let mut bytes = String::new();
+
+        for (kid, key) in keys {
+            bytes += &(kid + ":" + &key + ";");
+        }
+
+        Self {
+            bytes: bytes.get(..(bytes.len() - 1)).unwrap().as_bytes().to_vec(),
+            iv: None,
+            method: KeyMethod::Cenc,
+        }
+    }
+
+    fn as_hex_keys(&self) -> HashMap<String, String> {
+        String::from_utf8(self.bytes.clone())
+            .unwrap()
+            .split(';')
+            .map(|x| {
+                x.split_once(':')
+                    .map(|(x, y)| (x.to_owned(), y.to_owned()))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    /// Decrypt segment (prefixed with its init segment for common encryption).
+    pub fn decrypt(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        Ok(match self.method {
+            KeyMethod::Aes128 => {
+                let iv = if let Some(iv) = &self.iv {
+                    Some(hex::decode(iv.trim_start_matches("0x"))?)
+                } else {
+                    None
+                };
+
+                decrypt_aes_128_cbc(&mut data, &self.bytes, iv.as_ref())?
+            }
+            KeyMethod::Cenc => {
+                mp4decrypt::mp4decrypt(&data, self.as_hex_keys(), None).map_err(|x| anyhow!(x))?
+            }
+            _ => data,
+        })
+    }
+}
+
+/// Decrypt data encrypted using AES-128 in CBC mode with PKCS7 padding.
+pub fn decrypt_aes_128_cbc(input: &mut [u8], key: &[u8], iv: Option<&Vec<u8>>) -> Result<Vec<u8>> {
+    let key_length = key.len();
+
+    if key_length != 16 {
+        bail!("invalid key size i.e. {} but expected size 16.", key_length);
+    }
+
+    let mut key_c = [0_u8; 16];
+    key_c.copy_from_slice(key);
+
+    let mut iv_c = [0_u8; 16];
+
+    if let Some(iv) = iv {
+        let iv_length = key.len();
+
+        if iv_length != 16 {
+            bail!("invalid iv size i.e. {} but expected size 16.", iv_length);
+        }
+
+        iv_c.copy_from_slice(iv);
+    }
+
+    Aes128CbcDec::new(&key_c.into(), &iv_c.into())
+        .decrypt_padded_mut::<Pkcs7>(input)
+        .map(|x| x.to_vec())
+        .map_err(|x| anyhow!("{}", x))
+}
@@ -0,0 +1,544 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://rust-lang-nursery.github.io/rust-cookbook/web/clients/download.html#make-a-partial-download-with-http-range-headers
+
+*/
+
+use crate::{
+    decrypt::Keys,
+    limit::Limiter,
+    merger::Merger,
+    playlist::{KeyMethod, MediaPlaylist, Range, Segment},
+};
+use anyhow::{bail, Result};
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    header, StatusCode, Url,
+};
+use std::{
+    collections::HashMap,
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+/// Callbacks invoked by [`Downloader`] while downloading segments.
+/// Every method has an empty default implementation, so only required ones can be implemented.
+pub trait Progress: Send + Sync {
+    /// Decryption key which is going to be used for a stream.
+    fn key(&self, _kid: &str, _key: &str) {}
+
+    /// A segment request failed and is being retried.
+    fn retry(&self, _url: &str, _reason: &str) {}
+
+    /// A segment is downloaded, decrypted and written to its stream file.
+    fn segment(&self, _event: &SegmentEvent) {}
+}
+
+struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// Details of a downloaded segment passed to [`Progress::segment`].
+pub struct SegmentEvent {
+    /// Size of segment after decryption.
+    pub bytes: usize,
+    /// Estimated size of whole stream based upon segments downloaded so far.
+    pub estimate: usize,
+    /// Index of segment inside stream.
+    pub index: usize,
+    /// Total size of segments of stream downloaded so far.
+    pub stored: usize,
+}
+
+/// Error returned when a segment couldn't be fetched, even after retrying.
+/// It can be found in chain of errors returned by [`Downloader`], eg. using
+/// `error.chain().any(|x| x.is::<NetworkError>())`.
+#[derive(Debug)]
+pub struct NetworkError(String);
+
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+/// Downloads segments of streams in parallel, decrypting them if needed.
+///
+/// ```no_run
+/// use reqwest::blocking::Client;
+/// use vsd_core::Downloader;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// # let stream = vsd_core::playlist::MediaPlaylist::default();
+/// let downloader = Downloader::new(Client::new()).threads(8).retries(5);
+/// let size = downloader.download_stream(&stream, None, "video.ts")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Downloader {
+    all_keys: bool,
+    client: Client,
+    decrypt: bool,
+    keys: Vec<(Option<String>, String)>,
+    limiter: Option<Arc<Limiter>>,
+    progress: Arc<dyn Progress>,
+    retries: u8,
+    threads: u8,
+}
+
+impl Downloader {
+    /// Create a new downloader which uses 5 threads and retries a segment 15 times.
+    pub fn new(client: Client) -> Self {
+        Self {
+            all_keys: false,
+            client,
+            decrypt: true,
+            keys: vec![],
+            limiter: None,
+            progress: Arc::new(NoProgress),
+            retries: 15,
+            threads: 5,
+        }
+    }
+
+    /// Use all keys for decryption instead of only the ones matching default key id of stream.
+    pub fn all_keys(mut self, all_keys: bool) -> Self {
+        self.all_keys = all_keys;
+        self
+    }
+
+    /// Decrypt encrypted segments, enabled by default.
+    pub fn decrypt(mut self, decrypt: bool) -> Self {
+        self.decrypt = decrypt;
+        self
+    }
+
+    /// Keys for decrypting streams encrypted using common encryption, as pairs of optional
+    /// key id and key (hex strings). Keys without key id are used for default key id of stream.
+    pub fn keys(mut self, keys: Vec<(Option<String>, String)>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Connection and rate limits shared with other downloaders.
+    pub fn limiter(mut self, limiter: Arc<Limiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Callbacks to be invoked while downloading segments.
+    pub fn progress(mut self, progress: Arc<dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Maximum number of retries to download an individual segment.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Maximum number of threads for parallel downloading of segments.
+    pub fn threads(mut self, threads: u8) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Estimate size of stream from size of its first segment.
+    /// Streams containing a single segment are split into multiple byte ranges,
+    /// so that they can be downloaded in parallel.
+    pub fn estimate(&self, stream: &mut MediaPlaylist, base_url: Option<&Url>) -> Result<usize> {
+        let stream_base_url = base_url
+            .cloned()
+            .unwrap_or(stream.uri.parse::<Url>().unwrap());
+
+        let total_segments = stream.segments.len();
+        let buffer_size = 1024 * 1024 * 2; // 2 MiB
+        let mut ranges = None;
+        let mut size = 0;
+
+        if let Some(segment) = stream.segments.get(0) {
+            let url = stream_base_url.join(&segment.uri)?;
+            let mut request = self.client.head(url.clone());
+
+            if total_segments == 1 {
+                let response = request.send()?;
+                let content_length = response
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .map(|x| x.to_str().unwrap().parse::<usize>().unwrap())
+                    .unwrap_or(0);
+
+                if content_length == 0 {
+                    bail!(
+                        "cannot download a single segment ({}) of unknown content length.",
+                        url
+                    );
+                } else {
+                    ranges = Some(PartialRangeIter {
+                        start: 0,
+                        end: content_length as u64 - 1,
+                        buffer_size,
+                    });
+                    size = content_length;
+                }
+            } else {
+                if let Some(range) = &segment.range {
+                    request = request.header(header::RANGE, range.as_header_value());
+                }
+
+                let response = request.send()?;
+                let content_length = response
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .map(|x| x.to_str().unwrap().parse::<usize>().unwrap())
+                    .unwrap_or(0);
+
+                size = total_segments * content_length;
+            }
+        }
+
+        if let Some(ranges) = ranges {
+            let segment = stream.segments.remove(0);
+
+            for (i, range) in ranges.enumerate() {
+                if i == 0 {
+                    let mut segment_copy = segment.clone();
+                    segment_copy.range = Some(range);
+                    stream.segments.push(segment_copy);
+                } else {
+                    stream.segments.push(Segment {
+                        range: Some(range),
+                        duration: segment.duration,
+                        uri: segment.uri.clone(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// Prepare tasks for downloading segments of stream into merger.
+    /// Init segments and AES-128 keys are fetched while preparing.
+    pub fn segments(
+        &self,
+        stream: &MediaPlaylist,
+        base_url: Option<&Url>,
+        merger: &Arc<Mutex<Merger>>,
+    ) -> Result<Vec<SegmentTask>> {
+        let stream_base_url = base_url
+            .cloned()
+            .unwrap_or(stream.uri.parse::<Url>().unwrap());
+
+        let mut tasks = Vec::with_capacity(stream.segments.len());
+        let mut previous_map = None;
+        let mut previous_key = None;
+        let mut start_time = 0.0;
+
+        for (i, segment) in stream.segments.iter().enumerate() {
+            if let Some(map) = &segment.map {
+                let url = stream_base_url.join(&map.uri)?;
+                let mut request = self.client.get(url);
+
+                if let Some(range) = &map.range {
+                    request = request.header(header::RANGE, range.as_header_value());
+                }
+
+                let response = request.send()?;
+                let bytes = response.bytes()?;
+                previous_map = Some(bytes.to_vec())
+            }
+
+            if self.decrypt {
+                if let Some(key) = &segment.key {
+                    match key.method {
+                        KeyMethod::Aes128 => {
+                            if !self.keys.is_empty() {
+                                bail!("custom keys with AES-128 encryption is not supported");
+                            }
+
+                            if let Some(uri) = &key.uri {
+                                previous_key = Some(Keys::from_aes_128(
+                                    if key.key_format.is_none() {
+                                        let url = stream_base_url.join(uri)?;
+                                        let request = self.client.get(url);
+                                        let response = request.send()?;
+                                        response.bytes()?.to_vec()
+                                    } else {
+                                        vec![]
+                                    },
+                                    key.iv.clone(),
+                                ));
+                            } else {
+                                bail!("uri cannot be none when key method is AES-128");
+                            }
+                        }
+                        KeyMethod::Cenc => {
+                            let decryption_keys = self.decryption_keys(stream.default_kid());
+
+                            if decryption_keys.is_empty() {
+                                bail!(
+                                    "cannot determine keys to use, bypass this error using all keys (--all-keys)."
+                                );
+                            }
+
+                            for key in &decryption_keys {
+                                log::debug!("decrypting segments with key of kid {}", key.0);
+                                self.progress.key(key.0, key.1);
+                            }
+
+                            previous_key = Some(Keys::from_hex_keys(decryption_keys));
+                        }
+                        _ => previous_key = None,
+                    }
+                }
+            }
+
+            let url = stream_base_url.join(&segment.uri)?;
+            let mut request = self.client.get(url);
+
+            if let Some(range) = &segment.range {
+                request = request.header(header::RANGE, range.as_header_value());
+            }
+
+            tasks.push(SegmentTask {
+                index: i,
+                keys: previous_key.clone(),
+                limiter: self.limiter.clone(),
+                map: previous_map.clone(),
+                merger: merger.clone(),
+                progress: self.progress.clone(),
+                request,
+                retries: self.retries,
+                start_time,
+            });
+
+            start_time += segment.duration;
+
+            if previous_key.is_none() {
+                previous_map = None;
+            }
+        }
+
+        Ok(tasks)
+    }
+
+    fn decryption_keys(&self, default_kid: Option<String>) -> HashMap<String, String> {
+        let mut decryption_keys = HashMap::new();
+
+        if self.all_keys {
+            for key in &self.keys {
+                if let Some(kid) = &key.0 {
+                    decryption_keys.insert(kid.to_owned(), key.1.to_owned());
+                } else if let Some(default_kid) = &default_kid {
+                    decryption_keys.insert(default_kid.to_owned(), key.1.to_owned());
+                }
+            }
+        } else {
+            for key in &self.keys {
+                if let Some(default_kid) = &default_kid {
+                    if let Some(kid) = &key.0 {
+                        if default_kid == kid {
+                            decryption_keys.insert(kid.to_owned(), key.1.to_owned());
+                        }
+                    } else {
+                        decryption_keys.insert(default_kid.to_owned(), key.1.to_owned());
+                    }
+                }
+            }
+        }
+
+        decryption_keys
+    }
+
+    /// Download segment tasks in parallel, tasks are started in the order they are passed.
+    /// Remaining tasks are skipped as soon as a task fails and its error is returned.
+    pub fn download(&self, tasks: Vec<SegmentTask>) -> Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads as usize)
+            .build()?;
+        let error = Mutex::new(None);
+
+        pool.scope_fifo(|s| {
+            for task in tasks {
+                let error = &error;
+
+                s.spawn_fifo(move |_| {
+                    if error.lock().unwrap().is_some() {
+                        return;
+                    }
+
+                    if let Err(e) = task.execute() {
+                        error.lock().unwrap().get_or_insert(e);
+                    }
+                });
+            }
+        });
+
+        match error.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Download all segments of stream into a single file and return its size.
+    pub fn download_stream(
+        &self,
+        stream: &MediaPlaylist,
+        base_url: Option<&Url>,
+        path: &str,
+    ) -> Result<usize> {
+        let merger = Arc::new(Mutex::new(Merger::new(stream.segments.len(), path)?));
+        self.download(self.segments(stream, base_url, &merger)?)?;
+
+        let mut merger = merger.lock().unwrap();
+        merger.flush()?;
+
+        if !merger.buffered() {
+            bail!("failed to download stream to {}", path);
+        }
+
+        Ok(merger.stored())
+    }
+}
+
+/// A single segment waiting to be downloaded, created using [`Downloader::segments`].
+pub struct SegmentTask {
+    index: usize,
+    keys: Option<Keys>,
+    limiter: Option<Arc<Limiter>>,
+    map: Option<Vec<u8>>,
+    merger: Arc<Mutex<Merger>>,
+    progress: Arc<dyn Progress>,
+    request: RequestBuilder,
+    retries: u8,
+    start_time: f32,
+}
+
+impl SegmentTask {
+    /// Start time of segment inside stream, useful for interleaving segments of multiple streams.
+    pub fn start_time(&self) -> f32 {
+        self.start_time
+    }
+
+    fn execute(&self) -> Result<()> {
+        let mut segment = self.map.clone().unwrap_or_default();
+        segment.append(&mut self.download_segment()?);
+
+        if let Some(keys) = &self.keys {
+            segment = keys.decrypt(segment)?;
+        }
+
+        let mut merger = self.merger.lock().unwrap();
+        merger.write(self.index, &segment)?;
+        merger.flush()?;
+
+        self.progress.segment(&SegmentEvent {
+            bytes: segment.len(),
+            estimate: merger.estimate(),
+            index: self.index,
+            stored: merger.stored(),
+        });
+        Ok(())
+    }
+
+    fn download_segment(&self) -> Result<Vec<u8>> {
+        for _ in 0..self.retries {
+            let _connection = self.limiter.as_ref().map(|x| x.connection());
+            let response = match self.request.try_clone().unwrap().send() {
+                Ok(response) => response,
+                Err(error) => {
+                    let url = error.url().unwrap().as_str();
+                    let reason = retry_reason(&error)?;
+                    log::warn!("retrying segment {} {} ({})", self.index, url, reason);
+                    self.progress.retry(url, reason);
+                    continue;
+                }
+            };
+
+            let status = response.status();
+            log::trace!("segment {} {} {}", self.index, status, response.url());
+
+            if status.is_client_error() || status.is_server_error() {
+                return Err(NetworkError(format!(
+                    "failed to fetch segment {} (HTTP {})",
+                    response.url(),
+                    status
+                ))
+                .into());
+            }
+
+            let bytes = match &self.limiter {
+                Some(limiter) => {
+                    let mut bytes = vec![];
+                    limiter.reader(response).read_to_end(&mut bytes)?;
+                    bytes
+                }
+                None => response.bytes()?.to_vec(),
+            };
+
+            return Ok(bytes);
+        }
+
+        Err(
+            NetworkError("reached maximum number of retries to download a segment".to_owned())
+                .into(),
+        )
+    }
+}
+
+/// Reason of request error if it is worth retrying.
+fn retry_reason(error: &reqwest::Error) -> Result<&'static str, NetworkError> {
+    let url = error.url().unwrap();
+
+    if error.is_timeout() {
+        return Ok("timeout");
+    } else if error.is_connect() {
+        return Ok("connection error");
+    }
+
+    if let Some(status) = error.status() {
+        match status {
+            StatusCode::REQUEST_TIMEOUT => Ok("timeout"),
+            StatusCode::TOO_MANY_REQUESTS => Ok("too many requests"),
+            StatusCode::SERVICE_UNAVAILABLE => Ok("service unavailable"),
+            StatusCode::GATEWAY_TIMEOUT => Ok("gateway timeout"),
+            _ => Err(NetworkError(format!(
+                "download failed {} (HTTP {})",
+                url, status
+            ))),
+        }
+    } else {
+        Err(NetworkError(format!("download failed {}", url)))
+    }
+}
+
+struct PartialRangeIter {
+    start: u64,
+    end: u64,
+    buffer_size: u32,
+}
+
+impl Iterator for PartialRangeIter {
+    type Item = Range;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start > self.end {
+            None
+        } else {
+            let prev_start = self.start;
+            self.start += std::cmp::min(self.buffer_size as u64, self.end - self.start + 1);
+            Some(Range {
+                start: prev_start,
+                end: self.start - 1,
+            })
+        }
+    }
+}
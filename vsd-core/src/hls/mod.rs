@@ -0,0 +1,5 @@
+//! Parser for HLS (.m3u8) playlists.
+
+mod playlist;
+
+pub use playlist::{parse_as_master, push_segments};
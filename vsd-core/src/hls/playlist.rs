@@ -1,21 +1,28 @@
-use crate::playlist;
+use crate::playlist::{self, SplitOn};
 
-pub(crate) fn parse_as_master(
-    m3u8: &m3u8_rs::MasterPlaylist,
-    uri: &str,
-) -> playlist::MasterPlaylist {
+/// Parse hls master playlist into streams without segments.
+pub fn parse_as_master(m3u8: &m3u8_rs::MasterPlaylist, uri: &str) -> playlist::MasterPlaylist {
     let mut streams = vec![];
+    // Session keys are shared by all streams, so drm is known before parsing their segments.
+    let drm = m3u8
+        .session_key
+        .iter()
+        .map(|x| key_method(&x.0.method, &x.0.keyformat))
+        .find(|x| *x != playlist::KeyMethod::None);
 
     for video_stream in &m3u8.variants {
         streams.push(playlist::MediaPlaylist {
             bandwidth: Some(video_stream.bandwidth),
             channels: None,
             codecs: video_stream.codecs.to_owned(),
+            drm: drm.clone(),
             extension: Some("ts".to_owned()), // Cannot be comment here
+            forced: false,
             frame_rate: video_stream.frame_rate.map(|x| x as f32),
             i_frame: video_stream.is_i_frame,
             language: None,
             live: false, // Cannot be comment here
+            locator: None,
             media_type: playlist::MediaType::Video,
             playlist_type: playlist::PlaylistType::Hls,
             resolution: if let Some(m3u8_rs::Resolution { width, height }) = video_stream.resolution
@@ -35,12 +42,15 @@ pub(crate) fn parse_as_master(
                 m3u8_rs::AlternativeMediaType::Video => streams.push(playlist::MediaPlaylist {
                     bandwidth: None, // Cannot be comment here
                     channels: None,
-                    codecs: None,                     // Cannot be comment here
+                    codecs: None, // Cannot be comment here
+                    drm: drm.clone(),
                     extension: Some("ts".to_owned()), // Cannot be comment here
-                    frame_rate: None,                 // Cannot be comment here
-                    i_frame: false,                   // Cannot be comment here
+                    forced: false,
+                    frame_rate: None, // Cannot be comment here
+                    i_frame: false,   // Cannot be comment here
                     language: None,
                     live: false, // Cannot be comment here
+                    locator: None,
                     media_type: playlist::MediaType::Video,
                     playlist_type: playlist::PlaylistType::Hls,
                     resolution: None, // Cannot be comment here
@@ -54,8 +64,10 @@ pub(crate) fn parse_as_master(
                         .channels
                         .as_ref()
                         .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,                     // Cannot be comment here
+                    codecs: None, // Cannot be comment here
+                    drm: drm.clone(),
                     extension: Some("ts".to_owned()), // Cannot be comment here
+                    forced: false,
                     frame_rate: None,
                     i_frame: false,
                     language: alternative_stream
@@ -63,6 +75,7 @@ pub(crate) fn parse_as_master(
                         .to_owned()
                         .or(alternative_stream.assoc_language.to_owned()),
                     live: false, // Cannot be comment here
+                    locator: None,
                     media_type: playlist::MediaType::Audio,
                     playlist_type: playlist::PlaylistType::Hls,
                     resolution: None,
@@ -75,8 +88,10 @@ pub(crate) fn parse_as_master(
                     streams.push(playlist::MediaPlaylist {
                         bandwidth: None,
                         channels: None,
-                        codecs: None,                      // Cannot be comment here
+                        codecs: None, // Cannot be comment here
+                        drm: drm.clone(),
                         extension: Some("vtt".to_owned()), // Cannot be comment here
+                        forced: alternative_stream.forced,
                         frame_rate: None,
                         i_frame: false,
                         language: alternative_stream
@@ -84,6 +99,7 @@ pub(crate) fn parse_as_master(
                             .to_owned()
                             .or(alternative_stream.assoc_language.to_owned()),
                         live: false, // Cannot be comment here
+                        locator: None,
                         media_type: playlist::MediaType::Subtitles,
                         playlist_type: playlist::PlaylistType::Hls,
                         resolution: None,
@@ -98,8 +114,10 @@ pub(crate) fn parse_as_master(
                         .channels
                         .as_ref()
                         .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,     // Cannot be comment here
-                    extension: None,  // Cannot be comment here
+                    codecs: None, // Cannot be comment here
+                    drm: drm.clone(),
+                    extension: None, // Cannot be comment here
+                    forced: false,
                     frame_rate: None, // Cannot be comment here
                     i_frame: false,   // Cannot be comment here
                     language: alternative_stream
@@ -107,6 +125,7 @@ pub(crate) fn parse_as_master(
                         .to_owned()
                         .or(alternative_stream.assoc_language.to_owned()),
                     live: false, // Cannot be comment here
+                    locator: None,
                     media_type: playlist::MediaType::Undefined,
                     playlist_type: playlist::PlaylistType::Hls,
                     resolution: None, // Cannot be comment here
@@ -124,11 +143,37 @@ pub(crate) fn parse_as_master(
     }
 }
 
-pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playlist::MediaPlaylist) {
+/// Encryption method of hls key, keys of drm systems are always cenc.
+fn key_method(method: &m3u8_rs::KeyMethod, keyformat: &Option<String>) -> playlist::KeyMethod {
+    let method = match method {
+        m3u8_rs::KeyMethod::AES128 => playlist::KeyMethod::Aes128,
+        m3u8_rs::KeyMethod::None => playlist::KeyMethod::None, // This should never match according to hls specifications.
+        m3u8_rs::KeyMethod::SampleAES => playlist::KeyMethod::SampleAes,
+        m3u8_rs::KeyMethod::Other(x) if x == "SAMPLE-AES-CTR" || x == "SAMPLE-AES-CENC" => {
+            // cenc | cbc1 (pattern-based)
+            playlist::KeyMethod::Cenc
+        }
+        m3u8_rs::KeyMethod::Other(x) => playlist::KeyMethod::Other(x.to_owned()),
+    };
+
+    match keyformat.as_deref() {
+        Some(
+            "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"
+            | "com.apple.streamingkeydelivery"
+            | "com.microsoft.playready",
+        ) => playlist::KeyMethod::Cenc, // cbcs (pattern-based) | cbc1
+        _ => method,
+    }
+}
+
+/// Push segments of hls media playlist into stream.
+pub fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playlist::MediaPlaylist) {
     playlist.i_frame = m3u8.i_frames_only;
     playlist.live = !m3u8.end_list;
 
     let mut previous_byterange_end = 0;
+    // Program date time is only present on some segments, it is extrapolated for others.
+    let mut program_date_time = None;
 
     for segment in &m3u8.segments {
         let map = segment.map.as_ref().map(|x| playlist::Map {
@@ -166,7 +211,37 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
             playlist::Range { start, end }
         });
 
+        if let Some(x) = &segment.program_date_time {
+            program_date_time = Some(x.timestamp_millis() as f64 / 1000.0);
+        }
+
+        let mut boundaries = vec![];
+
+        if segment.daterange.is_some()
+            || segment
+                .unknown_tags
+                .iter()
+                .any(|x| x.tag == "X-CUE-OUT" || x.tag == "X-CUE-IN")
+        {
+            boundaries.push(SplitOn::Daterange);
+        }
+
+        if segment.discontinuity {
+            boundaries.push(SplitOn::Discontinuity);
+        }
+
+        // Discontinuities, date ranges and cue tags (SCTE-35 ad markers) are used as chapter marks.
+        let chapter = if let Some(daterange) = &segment.daterange {
+            Some(daterange.id.to_owned())
+        } else if !boundaries.is_empty() {
+            Some(String::new())
+        } else {
+            None
+        };
+
         playlist.segments.push(playlist::Segment {
+            boundaries,
+            chapter,
             duration: segment.duration,
             key: if let Some(m3u8_rs::Key {
                 iv,
@@ -176,27 +251,7 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
                 ..
             }) = &segment.key
             {
-                let mut method = match method {
-                    m3u8_rs::KeyMethod::AES128 => playlist::KeyMethod::Aes128,
-                    m3u8_rs::KeyMethod::None => playlist::KeyMethod::None, // This should never match according to hls specifications.
-                    m3u8_rs::KeyMethod::SampleAES => playlist::KeyMethod::SampleAes,
-                    m3u8_rs::KeyMethod::Other(x)
-                        if x == "SAMPLE-AES-CTR" || x == "SAMPLE-AES-CENC" =>
-                    {
-                        // cenc | cbc1 (pattern-based)
-                        playlist::KeyMethod::Cenc
-                    }
-                    m3u8_rs::KeyMethod::Other(x) => playlist::KeyMethod::Other(x.to_owned()),
-                };
-
-                if let Some(keyformat) = keyformat {
-                    method = match keyformat.as_str() {
-                        "urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed"
-                        | "com.apple.streamingkeydelivery"
-                        | "com.microsoft.playready" => playlist::KeyMethod::Cenc, // cbcs (pattern-based) | cbc1
-                        _ => method,
-                    };
-                }
+                let method = key_method(method, keyformat);
 
                 Some(playlist::Key {
                     default_kid: None,
@@ -209,9 +264,12 @@ pub(crate) fn push_segments(m3u8: &m3u8_rs::MediaPlaylist, playlist: &mut playli
                 None
             },
             map,
+            program_date_time,
             range,
             uri: segment.uri.to_owned(),
         });
+
+        program_date_time = program_date_time.map(|x| x + segment.duration as f64);
     }
 
     if let Some(segment) = playlist.segments.get(0) {
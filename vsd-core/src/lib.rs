@@ -0,0 +1,53 @@
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+//! This crate contains the core of [vsd](https://github.com/clitic/vsd) i.e. DASH and HLS playlist
+//! parsers, a common playlist model and a parallel segment downloader which can also decrypt
+//! segments. It can be used to embed vsd in other projects instead of running its command line
+//! interface.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use reqwest::blocking::Client;
+//! use vsd_core::{hls, playlist::Quality, Downloader};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let client = Client::new();
+//! let url = "https://example.com/master.m3u8";
+//! let text = client.get(url).send()?.text()?;
+//!
+//! let master = match m3u8_rs::parse_playlist_res(text.as_bytes()) {
+//!     Ok(m3u8_rs::Playlist::MasterPlaylist(m3u8)) => hls::parse_as_master(&m3u8, url),
+//!     _ => anyhow::bail!("not a master playlist"),
+//! }
+//! .sort_streams(Some("en".to_owned()), None);
+//!
+//! let index = master.select_video_stream(&Quality::Highest).unwrap();
+//! let mut stream = master.streams[index].clone();
+//! stream.uri = reqwest::Url::parse(url)?.join(&stream.uri)?.to_string();
+//!
+//! let text = client.get(&stream.uri).send()?.text()?;
+//! let m3u8 = m3u8_rs::parse_media_playlist_res(text.as_bytes()).unwrap();
+//! hls::push_segments(&m3u8, &mut stream);
+//!
+//! Downloader::new(client).download_stream(&stream, None, "video.ts")?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod download;
+mod limit;
+
+pub mod dash;
+pub mod decrypt;
+pub mod hls;
+pub mod merger;
+pub mod playlist;
+pub mod utils;
+
+pub use download::{Downloader, NetworkError, Progress, SegmentEvent, SegmentTask};
+pub use limit::Limiter;
+pub use merger::Merger;
+
+/// Re-export of mp4 parser used by vsd.
+pub use vsd_mp4;
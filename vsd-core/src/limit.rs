@@ -0,0 +1,134 @@
+use std::{
+    io::Read,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Bytes read at once by [`Throttled`] readers, so that downloads are paced evenly.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Connection and download rate limits which can be shared by multiple [`Downloader`](crate::Downloader)s,
+/// eg. when multiple playlists are downloaded together.
+///
+/// ```no_run
+/// use reqwest::blocking::Client;
+/// use std::sync::Arc;
+/// use vsd_core::{Downloader, Limiter};
+///
+/// // At most 8 segment requests at a time and 2 MiB/s across both downloaders.
+/// let limiter = Arc::new(Limiter::new(Some(8), Some(2 * 1024 * 1024)));
+/// let first = Downloader::new(Client::new()).limiter(limiter.clone());
+/// let second = Downloader::new(Client::new()).limiter(limiter);
+/// ```
+#[derive(Debug)]
+pub struct Limiter {
+    active: Mutex<usize>,
+    connections: Option<usize>,
+    /// Time till which already downloaded bytes exhaust the rate.
+    next: Mutex<Option<Instant>>,
+    rate: Option<u64>,
+    released: Condvar,
+}
+
+impl Limiter {
+    /// Create a new limiter allowing at most `connections` segment requests at a time and
+    /// `rate` bytes per second in total. `None` means unlimited.
+    pub fn new(connections: Option<usize>, rate: Option<u64>) -> Self {
+        Self {
+            active: Mutex::new(0),
+            connections: connections.map(|x| x.max(1)),
+            next: Mutex::new(None),
+            rate: rate.filter(|x| *x > 0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait for a free connection, it is released when returned value is dropped.
+    pub(crate) fn connection(&self) -> Connection<'_> {
+        if let Some(connections) = self.connections {
+            let mut active = self.active.lock().unwrap();
+
+            while *active >= connections {
+                active = self.released.wait(active).unwrap();
+            }
+
+            *active += 1;
+        }
+
+        Connection(self)
+    }
+
+    /// Account downloaded bytes, sleeping as long as needed to stay within rate.
+    pub(crate) fn consume(&self, bytes: usize) {
+        let rate = match self.rate {
+            Some(x) => x,
+            None => return,
+        };
+
+        let until = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let until = next.filter(|x| *x > now).unwrap_or(now)
+                + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            *next = Some(until);
+            until
+        };
+
+        std::thread::sleep(until.saturating_duration_since(Instant::now()));
+    }
+
+    /// Wrap reader so that every chunk read from it is accounted within rate.
+    pub(crate) fn reader<R: Read>(&self, reader: R) -> Throttled<'_, R> {
+        Throttled {
+            limiter: self,
+            reader,
+        }
+    }
+}
+
+pub(crate) struct Connection<'a>(&'a Limiter);
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        if self.0.connections.is_some() {
+            *self.0.active.lock().unwrap() -= 1;
+            self.0.released.notify_one();
+        }
+    }
+}
+
+pub(crate) struct Throttled<'a, R> {
+    limiter: &'a Limiter,
+    reader: R,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE);
+        let read = self.reader.read(&mut buf[..len])?;
+        self.limiter.consume(read);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_every_chunk() {
+        let limiter = Limiter::new(None, Some(4 * CHUNK_SIZE as u64));
+        let data = vec![0; 2 * CHUNK_SIZE];
+        let mut reader = limiter.reader(data.as_slice());
+        let mut buf = vec![0; data.len()];
+        let start = Instant::now();
+
+        assert_eq!(reader.read(&mut buf).unwrap(), CHUNK_SIZE);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), CHUNK_SIZE);
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+}
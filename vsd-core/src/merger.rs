@@ -0,0 +1,155 @@
+//! Ordered writing of downloaded segments.
+
+use anyhow::Result;
+use std::{collections::HashMap, fs, fs::File, io::Write, path::PathBuf};
+
+/// Writes downloaded segments of a stream in order to a single file (or a directory when
+/// segments are kept separate), buffering segments which arrive out of order.
+pub struct Merger {
+    size: usize,
+    file: Box<dyn Write + Send>,
+    pos: usize,
+    buffers: HashMap<usize, Vec<u8>>,
+    stored_bytes: usize,
+    flushed_bytes: usize,
+    indexed: usize,
+
+    directory: Option<PathBuf>,
+}
+
+impl Merger {
+    /// Merge `size` segments into `filename`.
+    /// Use `-` as filename to write to stdout.
+    pub fn new(size: usize, filename: &str) -> Result<Self> {
+        Ok(Self {
+            size: size - 1,
+            file: if filename == "-" {
+                Box::new(std::io::stdout())
+            } else {
+                Box::new(File::create(filename)?)
+            },
+            pos: 0,
+            buffers: HashMap::new(),
+            stored_bytes: 0,
+            flushed_bytes: 0,
+            indexed: 0,
+            directory: None,
+        })
+    }
+
+    /// Merge `size` segments at end of `filename`, it is created if it doesn't exist.
+    /// Useful for live streams where segments are downloaded in batches.
+    /// Use `-` as filename to write to stdout.
+    pub fn append(size: usize, filename: &str) -> Result<Self> {
+        Ok(Self {
+            size: size - 1,
+            file: if filename == "-" {
+                Box::new(std::io::stdout())
+            } else {
+                Box::new(
+                    fs::OpenOptions::new()
+                        .append(true)
+                        .create(true)
+                        .open(filename)?,
+                )
+            },
+            pos: 0,
+            buffers: HashMap::new(),
+            stored_bytes: 0,
+            flushed_bytes: 0,
+            indexed: 0,
+            directory: None,
+        })
+    }
+
+    /// Write `size` segments as separate files inside `directory`, named by their index.
+    pub fn with_directory(size: usize, directory: &str) -> Result<Self> {
+        let directory = PathBuf::from(directory);
+
+        if !directory.exists() {
+            fs::create_dir_all(&directory)?;
+        }
+
+        Ok(Self {
+            size: size - 1,
+            file: Box::new(File::create(directory.join(format!(
+                "0.{}",
+                directory.extension().unwrap().to_string_lossy()
+            )))?),
+            pos: 0,
+            buffers: HashMap::new(),
+            stored_bytes: 0,
+            flushed_bytes: 0,
+            indexed: 0,
+            directory: Some(directory),
+        })
+    }
+
+    /// Write segment at `pos`, it is buffered until all previous segments are written.
+    pub fn write(&mut self, pos: usize, buf: &[u8]) -> Result<()> {
+        if let Some(directory) = &self.directory {
+            self.file = Box::new(File::create(directory.join(format!(
+                "{}.{}",
+                pos,
+                directory.extension().unwrap().to_string_lossy()
+            )))?);
+        }
+
+        if self.directory.is_some() || (pos == 0 || (self.pos != 0 && self.pos == pos)) {
+            self.file.write_all(buf)?;
+            self.file.flush()?;
+            self.pos += 1;
+            let size = buf.len();
+            self.stored_bytes += size;
+            self.flushed_bytes += size;
+        } else {
+            self.buffers.insert(pos, buf.to_vec());
+            self.stored_bytes += buf.len();
+        }
+
+        self.indexed += 1;
+        Ok(())
+    }
+
+    /// Write buffered segments which are next in order.
+    pub fn flush(&mut self) -> Result<()> {
+        while self.pos <= self.size {
+            let op_buf = self.buffers.remove(&self.pos);
+
+            if let Some(buf) = op_buf {
+                self.file.write_all(&buf)?;
+                self.file.flush()?;
+                self.pos += 1;
+                self.flushed_bytes += buf.len();
+                // self.update()?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // pub fn position(&self) -> usize {
+    //     self.pos
+    // }
+
+    /// Whether all segments are written.
+    pub fn buffered(&self) -> bool {
+        self.buffers.is_empty() && self.pos >= (self.size + 1)
+    }
+
+    /// Total bytes of segments received so far.
+    pub fn stored(&self) -> usize {
+        self.stored_bytes
+    }
+
+    /// Estimated size of merged file based upon average size of received segments.
+    pub fn estimate(&self) -> usize {
+        if self.indexed == 0 {
+            0
+        } else {
+            (self.stored_bytes / self.indexed) * (self.size + 1)
+        }
+    }
+}
@@ -1,3 +1,5 @@
+//! Common model of DASH and HLS playlists.
+
 /*
     REFERENCES
     ----------
@@ -7,23 +9,23 @@
 
 */
 
-use crate::commands::Quality;
-use anyhow::{bail, Result};
-use kdam::term::Colorizer;
-use requestty::prompt::style::Stylize;
+use crate::utils;
 use reqwest::header::HeaderValue;
 use serde::Serialize;
-use std::{fmt::Display, io::Write, path::PathBuf};
+use std::{fmt::Display, path::PathBuf};
 
+/// Collection of all streams (variants, renditions or representations) of a playlist.
 #[derive(Serialize)]
-pub(crate) struct MasterPlaylist {
-    pub(crate) playlist_type: PlaylistType,
-    pub(crate) uri: String,
-    pub(crate) streams: Vec<MediaPlaylist>,
+pub struct MasterPlaylist {
+    pub playlist_type: PlaylistType,
+    pub uri: String,
+    pub streams: Vec<MediaPlaylist>,
 }
 
 impl MasterPlaylist {
-    pub(crate) fn sort_streams(
+    /// Sort streams in order of their preference. Video streams are sorted by resolution and
+    /// bandwidth, audio streams by language, channels and bandwidth and subtitles by language.
+    pub fn sort_streams(
         mut self,
         prefer_audio_lang: Option<String>,
         prefer_subs_lang: Option<String>,
@@ -71,7 +73,10 @@ impl MasterPlaylist {
                         }
                     }
 
-                    subtitle_streams.push((stream, language_factor));
+                    // Forced subtitles only cover foreign dialogues, so prefer full subtitles.
+                    let forced_factor = if stream.forced { 0 } else { 1 };
+
+                    subtitle_streams.push((stream, language_factor, forced_factor));
                 }
                 MediaType::Undefined => undefined_streams.push(stream),
                 MediaType::Video => {
@@ -93,6 +98,7 @@ impl MasterPlaylist {
         audio_streams.sort_by(|x, y| y.3.cmp(&x.3));
         audio_streams.sort_by(|x, y| y.2.total_cmp(&x.2));
         audio_streams.sort_by(|x, y| y.1.cmp(&x.1));
+        subtitle_streams.sort_by_key(|x| std::cmp::Reverse(x.2));
         subtitle_streams.sort_by(|x, y| y.1.cmp(&x.1));
 
         self.streams = video_streams
@@ -106,7 +112,8 @@ impl MasterPlaylist {
         self
     }
 
-    fn select_video_stream(&self, quality: &Quality) -> Option<usize> {
+    /// Index of video stream (among video streams) matching given quality.
+    pub fn select_video_stream(&self, quality: &Quality) -> Option<usize> {
         let video_streams = self
             .streams
             .iter()
@@ -150,239 +157,41 @@ impl MasterPlaylist {
 
         has_resolution.or(has_height)
     }
-
-    pub(crate) fn select_streams(
-        self,
-        quality: Quality,
-        skip_prompts: bool,
-        raw_prompts: bool,
-    ) -> Result<(Vec<MediaPlaylist>, Vec<MediaPlaylist>)> {
-        let default_video_stream_index = self.select_video_stream(&quality);
-
-        if let Some(default_video_stream_index) = default_video_stream_index {
-            let mut video_streams = vec![];
-            let mut audio_streams = vec![];
-            let mut subtitle_streams = vec![];
-            // TODO - Add support for downloading undefined streams
-            let mut undefined_streams = vec![];
-
-            for stream in self.streams {
-                match stream.media_type {
-                    MediaType::Audio => audio_streams.push(stream),
-                    MediaType::Subtitles => subtitle_streams.push(stream),
-                    MediaType::Undefined => undefined_streams.push(stream),
-                    MediaType::Video => video_streams.push(stream),
-                }
-            }
-
-            let mut choices_with_default = vec![];
-            let mut choices_with_default_ranges: [std::ops::Range<usize>; 4] =
-                [(0..0), (0..0), (0..0), (0..0)];
-
-            choices_with_default.push(requestty::Separator(
-                "─────── Video Streams ────────".to_owned(),
-            ));
-            choices_with_default.extend(video_streams.iter().enumerate().map(|(i, x)| {
-                requestty::Choice((x.display_video_stream(), i == default_video_stream_index))
-            }));
-            choices_with_default_ranges[0] = 1..choices_with_default.len();
-            choices_with_default.push(requestty::Separator(
-                "─────── Audio Streams ────────".to_owned(),
-            ));
-            choices_with_default.extend(
-                audio_streams
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| requestty::Choice((x.display_audio_stream(), i == 0))),
-            );
-
-            if skip_prompts || raw_prompts {
-                choices_with_default_ranges[1] =
-                    choices_with_default_ranges[0].end..(choices_with_default.len() - 1);
-            } else {
-                choices_with_default_ranges[1] =
-                    (choices_with_default_ranges[0].end + 1)..choices_with_default.len();
-            }
-
-            choices_with_default.push(requestty::Separator(
-                "────── Subtitle Streams ──────".to_owned(),
-            ));
-            choices_with_default.extend(
-                subtitle_streams
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| requestty::Choice((x.display_subtitle_stream(), i == 0))),
-            );
-
-            if skip_prompts || raw_prompts {
-                choices_with_default_ranges[2] =
-                    choices_with_default_ranges[1].end..(choices_with_default.len() - 2);
-            } else {
-                choices_with_default_ranges[2] =
-                    (choices_with_default_ranges[1].end + 1)..choices_with_default.len();
-            }
-
-            // println!("{:?}", choices_with_default_ranges);
-
-            if skip_prompts || raw_prompts {
-                println!("Select streams to download:");
-                let mut selected_choices_index = vec![];
-                let mut index = 1;
-
-                for choice in choices_with_default {
-                    if let requestty::Separator(seperator) = choice {
-                        println!("{}", seperator.replace('─', "-"));
-                    } else {
-                        let (message, selected) = choice.unwrap_choice();
-
-                        if selected {
-                            selected_choices_index.push(index);
-                        }
-
-                        println!(
-                            "{:2}) [{}] {}",
-                            index,
-                            if selected { 'x' } else { ' ' },
-                            message
-                        );
-                        index += 1;
-                    }
-                }
-
-                println!("------------------------------");
-
-                if raw_prompts && !skip_prompts {
-                    print!(
-                        "Press enter to proceed with defaults.\n\
-                        Or select streams to download (1, 2, etc.): "
-                    );
-                    std::io::stdout().flush()?;
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input)?;
-
-                    println!("------------------------------");
-
-                    let input = input.trim();
-
-                    if !input.is_empty() {
-                        selected_choices_index = input
-                            .split(',')
-                            .filter_map(|x| x.trim().parse::<usize>().ok())
-                            .collect::<Vec<usize>>();
-                    }
-                }
-
-                let mut selected_streams = vec![];
-                let mut selected_subtitle_streams = vec![];
-                let mut video_streams_offset = 1;
-                let mut audio_streams_offset = video_streams_offset + video_streams.len();
-                let mut subtitle_streams_offset = audio_streams_offset + audio_streams.len();
-
-                for i in selected_choices_index {
-                    if choices_with_default_ranges[0].contains(&i) {
-                        let stream = video_streams.remove(i - video_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_streams.push(stream);
-                        video_streams_offset += 1;
-                    } else if choices_with_default_ranges[1].contains(&i) {
-                        let stream = audio_streams.remove(i - audio_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_streams.push(stream);
-                        audio_streams_offset += 1;
-                    } else if choices_with_default_ranges[2].contains(&i) {
-                        let stream = subtitle_streams.remove(i - subtitle_streams_offset);
-                        println!(
-                            "   {} {}",
-                            "Selected".colorize("bold green"),
-                            stream.display_stream()
-                        );
-                        selected_subtitle_streams.push(stream);
-                        subtitle_streams_offset += 1;
-                    }
-                }
-
-                Ok((selected_streams, selected_subtitle_streams))
-            } else {
-                let question = requestty::Question::multi_select("streams")
-                    .should_loop(false)
-                    .message("Select streams to download")
-                    .choices_with_default(choices_with_default)
-                    .transform(|choices, _, backend| {
-                        backend.write_styled(
-                            &choices
-                                .iter()
-                                .map(|x| x.text.split_whitespace().collect::<Vec<_>>().join(" "))
-                                .collect::<Vec<_>>()
-                                .join(" | ")
-                                .cyan(),
-                        )
-                    })
-                    .build();
-
-                let answer = requestty::prompt_one(question)?;
-
-                let mut selected_streams = vec![];
-                let mut selected_subtitle_streams = vec![];
-                let mut video_streams_offset = 1;
-                let mut audio_streams_offset = video_streams_offset + video_streams.len() + 1;
-                let mut subtitle_streams_offset = audio_streams_offset + audio_streams.len() + 1;
-
-                for selected_item in answer.as_list_items().unwrap() {
-                    if choices_with_default_ranges[0].contains(&selected_item.index) {
-                        selected_streams
-                            .push(video_streams.remove(selected_item.index - video_streams_offset));
-                        video_streams_offset += 1;
-                    } else if choices_with_default_ranges[1].contains(&selected_item.index) {
-                        selected_streams
-                            .push(audio_streams.remove(selected_item.index - audio_streams_offset));
-                        audio_streams_offset += 1;
-                    } else if choices_with_default_ranges[2].contains(&selected_item.index) {
-                        selected_subtitle_streams.push(
-                            subtitle_streams.remove(selected_item.index - subtitle_streams_offset),
-                        );
-                        subtitle_streams_offset += 1;
-                    }
-                }
-
-                Ok((selected_streams, selected_subtitle_streams))
-            }
-        } else {
-            bail!("playlist doesn't contain pre-selected video quality stream.")
-        }
-    }
 }
 
-#[derive(Default, Serialize)]
-pub(crate) struct MediaPlaylist {
-    pub(crate) bandwidth: Option<u64>,
-    pub(crate) channels: Option<f32>,
-    pub(crate) codecs: Option<String>,
-    pub(crate) extension: Option<String>,
-    pub(crate) frame_rate: Option<f32>,
-    pub(crate) i_frame: bool,
-    pub(crate) language: Option<String>,
-    pub(crate) live: bool,
-    pub(crate) media_type: MediaType,
-    pub(crate) playlist_type: PlaylistType,
-    pub(crate) resolution: Option<(u64, u64)>,
-    pub(crate) segments: Vec<Segment>,
-    pub(crate) uri: String,
+/// A single stream along with its segments.
+#[derive(Clone, Default, Serialize)]
+pub struct MediaPlaylist {
+    pub bandwidth: Option<u64>,
+    pub channels: Option<f32>,
+    pub codecs: Option<String>,
+    /// Encryption method of stream known from master playlist (hls session keys or dash content
+    /// protection), before its segments are parsed.
+    pub drm: Option<KeyMethod>,
+    pub extension: Option<String>,
+    pub forced: bool,
+    pub frame_rate: Option<f32>,
+    pub i_frame: bool,
+    pub language: Option<String>,
+    pub live: bool,
+    /// Locator of dash stream inside its manifest, used for refreshing live streams once
+    /// [`MediaPlaylist::uri`] is replaced with manifest url.
+    pub locator: Option<String>,
+    pub media_type: MediaType,
+    pub playlist_type: PlaylistType,
+    pub resolution: Option<(u64, u64)>,
+    pub segments: Vec<Segment>,
+    pub uri: String,
 }
 
 impl MediaPlaylist {
-    pub(crate) fn is_hls(&self) -> bool {
+    /// Whether stream was parsed from a hls playlist.
+    pub fn is_hls(&self) -> bool {
         matches!(&self.playlist_type, PlaylistType::Hls)
     }
 
-    pub(crate) fn default_kid(&self) -> Option<String> {
+    /// Default key id of encrypted stream, in lowercase hex without dashes.
+    pub fn default_kid(&self) -> Option<String> {
         if let Some(segment) = self.segments.get(0) {
             if let Some(Key {
                 default_kid: Some(x),
@@ -396,7 +205,8 @@ impl MediaPlaylist {
         None
     }
 
-    pub(crate) fn extension(&self) -> String {
+    /// Extension of stream file, guessed from segment uris when unknown.
+    pub fn extension(&self) -> String {
         if let Some(ext) = &self.extension {
             return ext.to_owned();
         }
@@ -421,31 +231,28 @@ impl MediaPlaylist {
         ext.to_owned()
     }
 
-    pub(crate) fn file_path(&self, directory: &Option<PathBuf>, ext: &str) -> PathBuf {
-        let mut filename = self
+    /// Unique path for stream file inside directory.
+    pub fn file_path(&self, directory: &Option<PathBuf>, ext: &str) -> PathBuf {
+        let filename = self
             .uri
             .split('?')
             .next()
             .unwrap()
             .split('/')
             .last()
-            .unwrap_or("undefined")
-            .chars()
-            .map(|x| match x {
-                '/' | '\\' | '?' | '%' | '*' | ':' | '|' | '"' | '<' | '>' | '.' | ';' | '='
-                | ' ' => '_',
-                _ => x,
-            })
-            .collect::<String>();
+            .unwrap_or("undefined");
+        let mut filename = utils::sanitize_filename(filename, true).replace('.', "_");
 
+        // Sanitized file name is ascii only.
         if filename.len() > 128 {
-            filename = filename[..128].to_owned();
+            filename.truncate(128);
         }
 
         let filename = PathBuf::from(filename).with_extension("");
 
         let prefix = match &self.media_type {
             MediaType::Audio => "vsd_audio",
+            MediaType::Subtitles if self.forced => "vsd_subtitles_forced",
             MediaType::Subtitles => "vsd_subtitles",
             MediaType::Undefined => "vsd_undefined",
             MediaType::Video => "vsd_video",
@@ -476,7 +283,25 @@ impl MediaPlaylist {
         path
     }
 
-    pub(crate) fn display_stream(&self) -> String {
+    /// Encryption method of stream (eg. aes-128 or cenc), taken from its first segment
+    /// or from master playlist when segments aren't parsed yet.
+    pub fn display_drm(&self) -> Option<String> {
+        let method = match self.segments.first() {
+            Some(segment) => &segment.key.as_ref()?.method,
+            None => self.drm.as_ref()?,
+        };
+
+        match method {
+            KeyMethod::Aes128 => Some("aes-128".to_owned()),
+            KeyMethod::Cenc => Some("cenc".to_owned()),
+            KeyMethod::None => None,
+            KeyMethod::Other(x) => Some(x.to_lowercase()),
+            KeyMethod::SampleAes => Some("sample-aes".to_owned()),
+        }
+    }
+
+    /// Single line description of stream.
+    pub fn display_stream(&self) -> String {
         match self.media_type {
             MediaType::Audio => self.display_audio_stream(),
             MediaType::Subtitles => self.display_subtitle_stream(),
@@ -488,7 +313,7 @@ impl MediaPlaylist {
         .join(" ")
     }
 
-    fn display_video_stream(&self) -> String {
+    pub fn display_video_stream(&self) -> String {
         let resolution = if let Some((w, h)) = self.resolution {
             match (w, h) {
                 (256, 144) => "144p".to_owned(),
@@ -508,7 +333,7 @@ impl MediaPlaylist {
         };
 
         let bandwidth = if let Some(bandwidth) = self.bandwidth {
-            crate::utils::format_bytes(bandwidth as usize, 2)
+            utils::format_bytes(bandwidth as usize, 2)
         } else {
             ("?".to_owned(), "?".to_owned(), "?".to_owned())
         };
@@ -526,6 +351,10 @@ impl MediaPlaylist {
             extra += ", iframe";
         }
 
+        if let Some(drm) = self.display_drm() {
+            extra += &format!(", drm: {}", drm);
+        }
+
         if self.live {
             extra += ", live";
         }
@@ -538,7 +367,7 @@ impl MediaPlaylist {
         )
     }
 
-    fn display_audio_stream(&self) -> String {
+    pub fn display_audio_stream(&self) -> String {
         let mut extra = format!(
             "language: {}",
             self.language.as_ref().unwrap_or(&"?".to_owned())
@@ -551,7 +380,7 @@ impl MediaPlaylist {
         if let Some(bandwidth) = self.bandwidth {
             extra += &format!(
                 ", bandwidth: {}/s",
-                crate::utils::format_bytes(bandwidth as usize, 2).2
+                utils::format_bytes(bandwidth as usize, 2).2
             );
         }
 
@@ -559,6 +388,10 @@ impl MediaPlaylist {
             extra += &format!(", channels: {}", channels);
         }
 
+        if let Some(drm) = self.display_drm() {
+            extra += &format!(", drm: {}", drm);
+        }
+
         if self.live {
             extra += ", live";
         }
@@ -566,7 +399,7 @@ impl MediaPlaylist {
         extra
     }
 
-    pub(crate) fn display_subtitle_stream(&self) -> String {
+    pub fn display_subtitle_stream(&self) -> String {
         let mut extra = format!(
             "language: {}",
             self.language.as_ref().unwrap_or(&"?".to_owned())
@@ -576,10 +409,19 @@ impl MediaPlaylist {
             extra += &format!(", codecs: {}", codecs);
         }
 
+        if self.forced {
+            extra += ", forced";
+        }
+
+        if let Some(drm) = self.display_drm() {
+            extra += &format!(", drm: {}", drm);
+        }
+
         extra
     }
 
-    pub(crate) fn add_query(&mut self, query: &str) {
+    /// Append query parameters to uri of every segment and its init segment.
+    pub fn add_query(&mut self, query: &str) {
         for segment in &mut self.segments {
             if let Some(map) = &mut segment.map {
                 let mut uri = map.uri.clone();
@@ -608,15 +450,93 @@ impl MediaPlaylist {
     }
 }
 
-#[derive(Default, Serialize)]
-pub(crate) enum PlaylistType {
+/// Video quality which is preferred while selecting streams.
+#[derive(Debug, Clone)]
+pub enum Quality {
+    Lowest,
+    Highest,
+    Resolution(u16, u16),
+    Youtube144p,
+    Youtube240p,
+    Youtube360p,
+    Youtube480p,
+    Youtube720p,
+    Youtube1080p,
+    Youtube2k,
+    Youtube1440p,
+    Youtube4k,
+    Youtube8k,
+}
+
+/// Non-interactive selection of streams of a single kind (video, audio or subtitles).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamSelector {
+    /// Every stream.
+    All,
+    /// Streams whose codecs start with this value, eg. avc1 or ec-3.
+    Codec(String),
+    /// Stream at this position (starting from 1) among sorted streams of its kind.
+    Id(usize),
+    /// Streams of this language, primary language subtag is also matched (eg. en matches en-US).
+    Lang(String),
+    /// No stream.
+    None,
+}
+
+impl StreamSelector {
+    /// Whether `stream` at position `id` (starting from 1) among streams of its kind matches.
+    pub fn matches(&self, id: usize, stream: &MediaPlaylist) -> bool {
+        match self {
+            Self::All => true,
+            Self::Codec(codec) => stream.codecs.as_ref().is_some_and(|x| {
+                x.split(',')
+                    .any(|x| x.trim().to_lowercase().starts_with(&codec.to_lowercase()))
+            }),
+            Self::Id(x) => *x == id,
+            Self::Lang(lang) => stream.language.as_ref().is_some_and(|x| {
+                let (x, lang) = (x.to_lowercase(), lang.to_lowercase());
+                x == lang || x.split('-').next() == Some(lang.as_str())
+            }),
+            Self::None => false,
+        }
+    }
+}
+
+impl Display for StreamSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Codec(x) => write!(f, "codec={}", x),
+            Self::Id(x) => write!(f, "id={}", x),
+            Self::Lang(x) => write!(f, "lang={}", x),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+/// Kinds of boundaries at which a stream can be split.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitOn {
+    /// HLS EXT-X-DATERANGE tags and SCTE-35 cue tags.
+    Daterange,
+    /// HLS EXT-X-DISCONTINUITY tags.
+    Discontinuity,
+    /// DASH periods.
+    Period,
+}
+
+/// Type of playlist from which streams are parsed.
+#[derive(Clone, Default, Serialize)]
+pub enum PlaylistType {
     Dash,
     #[default]
     Hls,
 }
 
+/// Type of media contained in a stream.
 #[derive(Clone, Default, PartialEq, Serialize)]
-pub(crate) enum MediaType {
+pub enum MediaType {
     Audio,
     Subtitles,
     #[default]
@@ -639,8 +559,9 @@ impl Display for MediaType {
     }
 }
 
+/// Encryption method of segments.
 #[derive(Clone, PartialEq, Serialize)]
-pub(crate) enum KeyMethod {
+pub enum KeyMethod {
     Aes128,
     Cenc,
     None,
@@ -648,22 +569,25 @@ pub(crate) enum KeyMethod {
     SampleAes,
 }
 
+/// Inclusive byte range of a segment.
 #[derive(Clone, Serialize)]
-pub(crate) struct Range {
-    pub(crate) start: u64,
-    pub(crate) end: u64,
+pub struct Range {
+    pub start: u64,
+    pub end: u64,
 }
 
 impl Range {
-    pub(crate) fn as_header_value(&self) -> HeaderValue {
+    /// Value of http range header.
+    pub fn as_header_value(&self) -> HeaderValue {
         HeaderValue::from_str(&format!("bytes={}-{}", self.start, self.end)).unwrap()
     }
 }
 
+/// Initialization segment.
 #[derive(Clone, Serialize)]
-pub(crate) struct Map {
-    pub(crate) uri: String,
-    pub(crate) range: Option<Range>,
+pub struct Map {
+    pub uri: String,
+    pub range: Option<Range>,
 }
 
 /*
@@ -675,20 +599,29 @@ pub(crate) struct Map {
 #EXT-X-KEY:METHOD=SAMPLE-AES-CTR,KEYFORMAT="com.microsoft.playready",KEYFORMATVERSIONS="1",URI="data:text/plain;charset=UTF-16;base64,xAEAAAEAAQC6ATwAVwBSAE0ASABFAEEARABFAFIAIAB4AG0AbABuAHMAPQAiAGgAdAB0AHAAOgAvAC8AcwBjAGgAZQBtAGEAcwAuAG0AaQBjAHIAbwBzAG8AZgB0AC4AYwBvAG0ALwBEAFIATQAvADIAMAAwADcALwAwADMALwBQAGwAYQB5AFIAZQBhAGQAeQBIAGUAYQBkAGUAcgAiACAAdgBlAHIAcwBpAG8AbgA9ACIANAAuADAALgAwAC4AMAAiAD4APABEAEEAVABBAD4APABQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsARQBZAEwARQBOAD4AMQA2ADwALwBLAEUAWQBMAEUATgA+ADwAQQBMAEcASQBEAD4AQQBFAFMAQwBUAFIAPAAvAEEATABHAEkARAA+ADwALwBQAFIATwBUAEUAQwBUAEkATgBGAE8APgA8AEsASQBEAD4AOQBmAEIAMQAxAEsAMQB0AC8ARQBtAFEANABYAEMATQBjAEoANgBnAEkAZwA9AD0APAAvAEsASQBEAD4APAAvAEQAQQBUAEEAPgA8AC8AVwBSAE0ASABFAEEARABFAFIAPgA="
 
 */
+/// Encryption key of segments.
 #[derive(Clone, Serialize)]
-pub(crate) struct Key {
-    pub(crate) default_kid: Option<String>,
-    pub(crate) iv: Option<String>,
-    pub(crate) key_format: Option<String>,
-    pub(crate) method: KeyMethod,
-    pub(crate) uri: Option<String>,
+pub struct Key {
+    pub default_kid: Option<String>,
+    pub iv: Option<String>,
+    pub key_format: Option<String>,
+    pub method: KeyMethod,
+    pub uri: Option<String>,
 }
 
+/// A single media segment of stream.
 #[derive(Clone, Default, Serialize)]
-pub(crate) struct Segment {
-    pub(crate) range: Option<Range>,
-    pub(crate) duration: f32, // consider changing it to f64
-    pub(crate) key: Option<Key>,
-    pub(crate) map: Option<Map>,
-    pub(crate) uri: String,
+pub struct Segment {
+    /// Kinds of boundaries (discontinuity, date range, period) present at start of this segment.
+    pub boundaries: Vec<SplitOn>,
+    /// Chapter starting from this segment, an empty title means untitled chapter.
+    pub chapter: Option<String>,
+    pub range: Option<Range>,
+    pub duration: f32, // consider changing it to f64
+    pub key: Option<Key>,
+    pub map: Option<Map>,
+    /// Wall clock time at start of segment in seconds since unix epoch, known from
+    /// `EXT-X-PROGRAM-DATE-TIME` in hls and from availability start time in live dash streams.
+    pub program_date_time: Option<f64>,
+    pub uri: String,
 }
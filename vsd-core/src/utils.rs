@@ -0,0 +1,111 @@
+//! Helper functions.
+
+/// Format bytes in binary units, returns value, unit and both of them joined together.
+pub fn format_bytes(bytesval: usize, precision: usize) -> (String, String, String) {
+    let mut val = bytesval as f32;
+
+    for unit in ["bytes", "KiB", "MiB", "GiB", "TiB"] {
+        if val < 1024.0 {
+            return (
+                format!("{:.precision$}", val, precision = precision),
+                unit.to_owned(),
+                format!("{:.precision$} {}", val, unit, precision = precision),
+            );
+        }
+
+        val /= 1024.0;
+    }
+
+    (
+        format!("{:.precision$}", bytesval, precision = precision),
+        "".to_owned(),
+        format!("{:.precision$}", bytesval, precision = precision),
+    )
+}
+
+/// Maximum length of a sanitized file name in bytes, leaving room for suffixes and extensions
+/// within the 255 bytes limit of most file systems.
+const MAX_FILENAME_BYTES: usize = 200;
+
+/// Names which cannot be used as a file name on windows, even with an extension.
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Make a string (eg. media title) safe to be used as a file name on every platform.
+///
+/// Value is normalized to unicode NFC form, reserved and control characters are replaced with `_`,
+/// trailing dots and spaces are removed, windows reserved names (eg. `CON`) are suffixed with `_`
+/// and the name is truncated to 200 bytes. When `restrict` is true, accents are stripped and
+/// every character except ascii letters, digits and `-_.,+()[]` is replaced with `_`.
+///
+/// ```
+/// use vsd_core::utils::sanitize_filename;
+///
+/// assert_eq!(sanitize_filename("AC/DC: Live?", false), "AC_DC_ Live_");
+/// assert_eq!(sanitize_filename("Café del Mar", true), "Cafe_del_Mar");
+/// assert_eq!(sanitize_filename("con.mkv", false), "con_.mkv");
+/// ```
+pub fn sanitize_filename(name: &str, restrict: bool) -> String {
+    use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+    let name = if restrict {
+        name.nfkd()
+            .filter(|x| !is_combining_mark(*x))
+            .map(|x| {
+                if x.is_ascii_alphanumeric() || "-_.,+()[]".contains(x) {
+                    x
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>()
+    } else {
+        name.nfc()
+            .map(|x| match x {
+                '/' | '\\' | '?' | '%' | '*' | ':' | '|' | '"' | '<' | '>' => '_',
+                _ if x.is_control() => '_',
+                _ => x,
+            })
+            .collect::<String>()
+    };
+
+    let mut name = if restrict {
+        // Collapse runs of replaced characters, eg. `a & b` becomes `a_b` instead of `a___b`.
+        let mut collapsed = String::with_capacity(name.len());
+
+        for c in name.chars() {
+            if !(c == '_' && collapsed.ends_with('_')) {
+                collapsed.push(c);
+            }
+        }
+
+        collapsed.trim_matches('_').to_owned()
+    } else {
+        name.trim().to_owned()
+    };
+
+    if name.len() > MAX_FILENAME_BYTES {
+        let mut end = MAX_FILENAME_BYTES;
+
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        name.truncate(end);
+    }
+
+    let mut name = name.trim_end_matches(['.', ' ']).to_owned();
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(stem))
+    {
+        let stem_len = stem.len();
+        name.insert(stem_len, '_');
+    }
+
+    name
+}
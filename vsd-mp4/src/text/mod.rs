@@ -11,9 +11,7 @@
 mod boxes;
 mod subtitles;
 
-use subtitles::Cue;
-
-pub use subtitles::Subtitles;
+pub use subtitles::{Cue, Subtitles};
 
 #[cfg(feature = "text-ttml")]
 #[cfg_attr(docsrs, doc(cfg(feature = "text-ttml")))]
@@ -32,3 +30,7 @@ mod mp4_vtt_parser;
 #[cfg(feature = "text-vtt")]
 #[cfg_attr(docsrs, doc(cfg(feature = "text-vtt")))]
 pub use mp4_vtt_parser::Mp4VttParser;
+
+#[cfg(feature = "text-vtt")]
+#[cfg_attr(docsrs, doc(cfg(feature = "text-vtt")))]
+pub mod vtt_text_parser;
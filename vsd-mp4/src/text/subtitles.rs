@@ -5,23 +5,46 @@
     1. https://github.com/shaka-project/shaka-player/blob/9ce2f675d88d5de6f779f2a62a4f4af2bcc14611/lib/text/cue.js
     2. https://w3c.github.io/webvtt
     3. https://developer.mozilla.org/en-US/docs/Web/API/WebVTT_API
+    4. https://github.com/libass/libass/wiki/ASS-File-Format-Guide
 
 */
 
-use std::fmt::Write;
+use std::{collections::HashMap, fmt::Write};
 
+const ASS_PLAY_RES_X: f32 = 1920.0;
+const ASS_PLAY_RES_Y: f32 = 1080.0;
+
+/// Subtitles cue.
 #[derive(Clone)]
-pub(super) struct Cue {
-    pub(super) end_time: f32,
+pub struct Cue {
+    /// End time of cue in seconds.
+    pub end_time: f32,
     pub(super) _id: String,
-    pub(super) payload: String,
-    pub(super) settings: String,
-    pub(super) start_time: f32,
+    /// Text of cue, which may contain html like tags.
+    pub payload: String,
+    /// Webvtt cue settings.
+    pub settings: String,
+    /// Start time of cue in seconds.
+    pub start_time: f32,
+}
+
+impl Cue {
+    fn key(&self) -> (u64, String) {
+        (
+            (self.start_time * 1000.0).round() as u64,
+            self.payload.clone(),
+        )
+    }
 }
 
 /// Subtitles builder.
+#[derive(Default)]
 pub struct Subtitles {
     cues: Vec<Cue>,
+    /// Number of cues which are indexed in `keys`.
+    indexed: usize,
+    /// Position of cues keyed on their start time and payload, used for merging.
+    keys: HashMap<(u64, String), usize>,
 }
 
 impl Subtitles {
@@ -47,7 +70,10 @@ impl Subtitles {
             }
         }
 
-        Self { cues: trimmed_cues }
+        Self {
+            cues: trimmed_cues,
+            ..Default::default()
+        }
     }
 
     /// Extend these subtitles with another subtitles.
@@ -55,6 +81,67 @@ impl Subtitles {
         self.cues.extend(other.cues.into_iter());
     }
 
+    /// Merge another subtitles into these subtitles.
+    ///
+    /// Cues are keyed on their start time and payload, so cues which are repeated
+    /// (for example across live playlist refreshes or segment boundaries) are only kept once.
+    /// If a repeated cue has a later end time, then the existing cue is extended.
+    /// Merged cues are appended as they are, call [`Subtitles::sort`] once all subtitles are merged.
+    pub fn merge(&mut self, other: Self) {
+        // Cues added without merging (eg. by extend) are indexed first.
+        for (i, cue) in self.cues.iter().enumerate().skip(self.indexed) {
+            self.keys.entry(cue.key()).or_insert(i);
+        }
+
+        for cue in other.cues {
+            let key = cue.key();
+
+            if let Some(i) = self.keys.get(&key) {
+                let existing_cue = &mut self.cues[*i];
+
+                if cue.end_time > existing_cue.end_time {
+                    existing_cue.end_time = cue.end_time;
+                }
+            } else {
+                self.keys.insert(key, self.cues.len());
+                self.cues.push(cue);
+            }
+        }
+
+        self.indexed = self.cues.len();
+    }
+
+    /// Sort cues by their start time.
+    pub fn sort(&mut self) {
+        self.cues
+            .sort_by(|x, y| x.start_time.total_cmp(&y.start_time));
+        // Positions of cues are changed, so they are indexed again on next merge.
+        self.keys.clear();
+        self.indexed = 0;
+    }
+
+    /// Shift start and end time of every cue by `seconds`.
+    pub fn shift(&mut self, seconds: f32) {
+        for cue in &mut self.cues {
+            cue.start_time += seconds;
+            cue.end_time += seconds;
+        }
+
+        // Cues are keyed on their start time, so they are indexed again on next merge.
+        self.keys.clear();
+        self.indexed = 0;
+    }
+
+    /// Cues of these subtitles.
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    /// Returns true if there are no cues.
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
     /// Build subtitles in webvtt format.
     pub fn as_vtt(&self) -> String {
         let mut subtitles = "WEBVTT\n\n".to_owned();
@@ -90,6 +177,43 @@ impl Subtitles {
 
         subtitles
     }
+
+    /// Build subtitles in advanced substation alpha format.
+    ///
+    /// Cue settings (`line`, `position` and `align`) are mapped to positioning tags
+    /// and `<b>`, `<i>`, `<u>` and `<font color>` payload tags are mapped to override tags.
+    pub fn as_ass(&self) -> String {
+        let mut subtitles = format!(
+            "[Script Info]\n\
+            ScriptType: v4.00+\n\
+            PlayResX: {}\n\
+            PlayResY: {}\n\
+            WrapStyle: 0\n\
+            ScaledBorderAndShadow: yes\n\n\
+            [V4+ Styles]\n\
+            Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, \
+            Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, \
+            Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+            Style: Default,Arial,52,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,\
+            0,0,0,0,100,100,0,0,1,2.5,0,2,60,60,40,1\n\n\
+            [Events]\n\
+            Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+            ASS_PLAY_RES_X, ASS_PLAY_RES_Y
+        );
+
+        for cue in &self.cues {
+            let _ = writeln!(
+                subtitles,
+                "Dialogue: 0,{},{},Default,,0,0,0,,{}{}",
+                seconds_to_ass_timestamp(cue.start_time),
+                seconds_to_ass_timestamp(cue.end_time),
+                ass_position_tags(&cue.settings),
+                ass_text(&cue.payload)
+            );
+        }
+
+        subtitles
+    }
 }
 
 fn divmod(x: usize, y: usize) -> (usize, usize) {
@@ -105,3 +229,242 @@ fn seconds_to_timestamp(seconds: f32, millisecond_sep: &str) -> String {
         hours, minutes, seconds, millisecond_sep, milliseconds
     )
 }
+
+fn seconds_to_ass_timestamp(seconds: f32) -> String {
+    let (seconds, centiseconds) = divmod((seconds * 100.0) as usize, 100);
+    let (minutes, seconds) = divmod(seconds, 60);
+    let (hours, minutes) = divmod(minutes, 60);
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        hours, minutes, seconds, centiseconds
+    )
+}
+
+/// Convert webvtt cue settings into ass alignment (`\an`) and position (`\pos`) tags.
+fn ass_position_tags(settings: &str) -> String {
+    let mut line = None;
+    let mut position = None;
+    let mut align = "center";
+
+    for setting in settings.split_whitespace() {
+        if let Some((name, value)) = setting.split_once(':') {
+            // Values like "10%,start" contain an extra alignment which is ignored.
+            let value = value.split(',').next().unwrap_or(value);
+
+            match name {
+                "line" => line = Some(value.to_owned()),
+                "position" => position = value.trim_end_matches('%').parse::<f32>().ok(),
+                "align" => align = value,
+                _ => (),
+            }
+        }
+    }
+
+    // ass numpad alignment, 1-3 is bottom row, 4-6 is middle row and 7-9 is top row.
+    let column = match align {
+        "start" | "left" => 1,
+        "end" | "right" => 3,
+        _ => 2,
+    };
+
+    let line_percentage = line
+        .as_ref()
+        .and_then(|x| x.strip_suffix('%').and_then(|y| y.parse::<f32>().ok()));
+    let top = match (&line, line_percentage) {
+        (_, Some(x)) => x < 50.0,
+        // Line numbers start from top when positive and from bottom when negative.
+        (Some(x), None) => x.parse::<i32>().map(|y| y >= 0).unwrap_or(false),
+        (None, None) => false,
+    };
+
+    let alignment = if top { column + 6 } else { column };
+    let mut tags = String::new();
+
+    if let (Some(x), Some(y)) = (position, line_percentage) {
+        let _ = write!(
+            tags,
+            "{{\\an{}\\pos({:.0},{:.0})}}",
+            alignment,
+            x / 100.0 * ASS_PLAY_RES_X,
+            y / 100.0 * ASS_PLAY_RES_Y
+        );
+    } else if alignment != 2 {
+        let _ = write!(tags, "{{\\an{}}}", alignment);
+    }
+
+    tags
+}
+
+/// Convert html like payload tags into ass override tags.
+fn ass_text(payload: &str) -> String {
+    let mut text = String::new();
+    let mut chars = payload.trim().chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '<' => {
+                let mut tag = String::new();
+
+                for c in chars.by_ref() {
+                    if c == '>' {
+                        break;
+                    }
+
+                    tag.push(c);
+                }
+
+                let tag = tag.trim().to_lowercase();
+
+                match tag.as_str() {
+                    "b" => text.push_str("{\\b1}"),
+                    "/b" => text.push_str("{\\b0}"),
+                    "i" => text.push_str("{\\i1}"),
+                    "/i" => text.push_str("{\\i0}"),
+                    "u" => text.push_str("{\\u1}"),
+                    "/u" => text.push_str("{\\u0}"),
+                    "/font" => text.push_str("{\\c}"),
+                    x if x.starts_with("font") => {
+                        if let Some(color) = x
+                            .split_once("color=")
+                            .and_then(|(_, y)| {
+                                y.trim_matches(|z: char| z == '"' || z == '\'')
+                                    .split('"')
+                                    .next()
+                            })
+                            .and_then(ass_color)
+                        {
+                            let _ = write!(text, "{{\\c{}}}", color);
+                        }
+                    }
+                    // Other tags like <c.yellow>, <v Speaker> or <ruby> are dropped.
+                    _ => (),
+                }
+            }
+            '\n' => text.push_str("\\N"),
+            '\r' => (),
+            '{' => text.push('('),
+            '}' => text.push(')'),
+            '&' => {
+                let mut entity = String::from('&');
+
+                while let Some(&c) = chars.peek() {
+                    entity.push(c);
+                    chars.next();
+
+                    if c == ';' || entity.len() > 6 {
+                        break;
+                    }
+                }
+
+                text.push_str(match entity.as_str() {
+                    "&amp;" => "&",
+                    "&lt;" => "<",
+                    "&gt;" => ">",
+                    "&nbsp;" => "\\h",
+                    "&quot;" => "\"",
+                    "&apos;" => "'",
+                    x => x,
+                });
+            }
+            _ => text.push(c),
+        }
+    }
+
+    text
+}
+
+/// Convert `#rrggbb`, `#rrggbbaa` or some named css colors into ass `&HBBGGRR&` color.
+fn ass_color(color: &str) -> Option<String> {
+    let hex = match color.trim() {
+        "white" => "ffffff",
+        "black" => "000000",
+        "red" => "ff0000",
+        "lime" => "00ff00",
+        "green" => "008000",
+        "blue" => "0000ff",
+        "yellow" => "ffff00",
+        "cyan" | "aqua" => "00ffff",
+        "magenta" | "fuchsia" => "ff00ff",
+        x => x.trim_start_matches('#'),
+    };
+
+    if hex.len() < 6 || !hex.is_char_boundary(6) || !hex[..6].chars().all(|x| x.is_ascii_hexdigit())
+    {
+        return None;
+    }
+
+    Some(format!(
+        "&H{}{}{}&",
+        hex[4..6].to_uppercase(),
+        hex[2..4].to_uppercase(),
+        hex[0..2].to_uppercase()
+    ))
+}
+
+#[cfg(all(test, feature = "text-vtt"))]
+mod tests {
+    use super::*;
+    use crate::text::vtt_text_parser;
+
+    fn cues(subtitles: &Subtitles) -> Vec<(f32, f32, &str)> {
+        subtitles
+            .cues()
+            .iter()
+            .map(|x| (x.start_time, x.end_time, x.payload.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn merges_repeated_cues() {
+        let mut subtitles = Subtitles::default();
+        subtitles.merge(
+            vtt_text_parser::parse(
+                "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nHello\n\n00:00:03.000 --> 00:00:04.000\nWorld\n",
+            )
+            .unwrap(),
+        );
+        subtitles.merge(
+            vtt_text_parser::parse(
+                "WEBVTT\n\n00:00:03.000 --> 00:00:05.000\nWorld\n\n00:00:03.000 --> 00:00:04.000\nOther\n\n00:00:00.500 --> 00:00:01.000\nFirst\n",
+            )
+            .unwrap(),
+        );
+        subtitles.sort();
+
+        assert_eq!(
+            cues(&subtitles),
+            vec![
+                (0.5, 1.0, "First"),
+                (1.0, 2.0, "Hello"),
+                (3.0, 5.0, "World"),
+                (3.0, 4.0, "Other"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_after_sort_and_shift() {
+        let segment =
+            "WEBVTT\n\n00:00:02.000 --> 00:00:03.000\nB\n\n00:00:01.000 --> 00:00:02.000\nA\n";
+        let mut subtitles = Subtitles::default();
+        subtitles.merge(vtt_text_parser::parse(segment).unwrap());
+        subtitles.sort();
+        subtitles.merge(vtt_text_parser::parse(segment).unwrap());
+        assert_eq!(cues(&subtitles), vec![(1.0, 2.0, "A"), (2.0, 3.0, "B")]);
+
+        subtitles.shift(10.0);
+        subtitles.merge(vtt_text_parser::parse(segment).unwrap());
+        subtitles
+            .merge(vtt_text_parser::parse("WEBVTT\n\n00:00:11.000 --> 00:00:12.000\nA\n").unwrap());
+        subtitles.sort();
+        assert_eq!(
+            cues(&subtitles),
+            vec![
+                (1.0, 2.0, "A"),
+                (2.0, 3.0, "B"),
+                (11.0, 12.0, "A"),
+                (12.0, 13.0, "B"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Parse webvtt content.
+
+/*
+    REFERENCES
+    ----------
+
+    1. https://w3c.github.io/webvtt
+
+*/
+
+use super::{Cue, Subtitles};
+use crate::{Error, Result};
+
+/// Parse text as webvtt content.
+///
+/// Multiple concatenated webvtt files (for example hls subtitle segments) are also accepted,
+/// header, `NOTE`, `STYLE` and `REGION` blocks are skipped.
+/// Since subrip blocks have the same layout, subrip content can also be parsed.
+pub fn parse(text: &str) -> Result<Subtitles> {
+    Ok(Subtitles::new(parse_cues(text)?))
+}
+
+fn parse_cues(text: &str) -> Result<Vec<Cue>> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut cues = vec![];
+
+    for block in text.split("\n\n") {
+        let lines = block
+            .lines()
+            .skip_while(|x| x.trim().is_empty())
+            .collect::<Vec<_>>();
+
+        let timing_index = if let Some(index) = lines.iter().position(|x| x.contains("-->")) {
+            index
+        } else {
+            continue;
+        };
+
+        if lines
+            .first()
+            .map(|x| x.starts_with("NOTE") || x.starts_with("STYLE") || x.starts_with("REGION"))
+            .unwrap_or(false)
+        {
+            continue;
+        }
+
+        let (start_time, rest) = lines[timing_index].split_once("-->").unwrap();
+        let rest = rest.trim();
+        let (end_time, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+        cues.push(Cue {
+            end_time: timestamp_to_seconds(end_time.trim())?,
+            _id: if timing_index > 0 {
+                lines[timing_index - 1].trim().to_owned()
+            } else {
+                String::new()
+            },
+            payload: lines[(timing_index + 1)..].join("\n"),
+            settings: settings.trim().to_owned(),
+            start_time: timestamp_to_seconds(start_time.trim())?,
+        });
+    }
+
+    Ok(cues)
+}
+
+fn timestamp_to_seconds(timestamp: &str) -> Result<f32> {
+    let err = || Error::new_decode_err(format!("{} as webvtt timestamp", timestamp));
+    let mut total_seconds = 0.0;
+
+    for (i, part) in timestamp.rsplit(':').enumerate() {
+        let value = part.replace(',', ".").parse::<f32>().map_err(|_| err())?;
+
+        total_seconds += match i {
+            0 => value,
+            1 => value * 60.0,
+            2 => value * 3600.0,
+            _ => return Err(err()),
+        };
+    }
+
+    Ok(total_seconds)
+}
@@ -2,6 +2,8 @@ use std::{env, process::Command};
 
 fn main() {
     println!("cargo:rerun-if-env-changed=VSD_ICON");
+    // Used by self-update subcommand for finding prebuilt binary of same platform.
+    println!("cargo:rustc-env=VSD_TARGET={}", env::var("TARGET").unwrap());
 
     let icon = env::var("VSD_ICON").is_ok()
         && env::var("CARGO_CFG_TARGET_OS").unwrap() == "windows"
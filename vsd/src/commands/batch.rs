@@ -0,0 +1,341 @@
+use super::{OnError, Save};
+use crate::{events, exit::ExitCode, utils};
+use anyhow::{anyhow, bail, Result};
+use kdam::{term::Colorizer, tqdm, BarExt};
+use serde_json::json;
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Number of times a failed download is retried with `--on-error retry`.
+const RETRIES: usize = 2;
+
+/// Single download listed in a batch file or passed on command line.
+pub(super) struct Entry {
+    headers: Vec<String>,
+    output: Option<String>,
+    /// Where this entry came from, eg. line 3.
+    source: String,
+    url: String,
+}
+
+impl Entry {
+    pub(super) fn new(source: String, url: String) -> Self {
+        Self {
+            headers: vec![],
+            output: None,
+            source,
+            url,
+        }
+    }
+
+    /// Parse a line in `URL [OUTPUT] [-o OUTPUT] [--header KEY VALUE]...` format.
+    /// Empty lines and lines starting with # are skipped.
+    fn parse(line: usize, text: &str) -> Result<Option<Self>> {
+        let text = text.trim();
+
+        if text.is_empty() || text.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut args = utils::split_args(text)?.into_iter();
+        let mut entry = Self::new(format!("line {}", line), args.next().unwrap());
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => match args.next() {
+                    Some(output) => entry.output = Some(output),
+                    None => bail!("missing value of {} on line {} of batch file.", arg, line),
+                },
+                "-H" | "--header" => match (args.next(), args.next()) {
+                    (Some(key), Some(value)) => entry.headers.extend([key, value]),
+                    _ => bail!(
+                        "missing KEY VALUE of {} on line {} of batch file.",
+                        arg,
+                        line
+                    ),
+                },
+                x if !x.starts_with('-') && entry.output.is_none() => entry.output = Some(arg),
+                _ => bail!(
+                    "unexpected argument {} on line {} of batch file.",
+                    arg,
+                    line
+                ),
+            }
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Options used for downloading this entry, derived from options passed on command line.
+    fn save(&self, mut save: Save, position: Option<u16>) -> Save {
+        save.batch_file = None;
+        save.input = vec![self.url.clone()];
+        // Headers of entry are placed later so that they are preferred.
+        save.client.header.extend(self.headers.iter().cloned());
+
+        if self.output.is_some() {
+            save.output = self.output.clone();
+        }
+
+        // Prompts of parallel downloads cannot be answered one by one.
+        if let Some(position) = position {
+            save.bar_position = position;
+            save.skip_prompts = true;
+        }
+
+        save
+    }
+}
+
+/// Read entries listed in batch file.
+pub(super) fn read(path: &Path) -> Result<Vec<Entry>> {
+    let entries = std::fs::read_to_string(path)?
+        .lines()
+        .enumerate()
+        .map(|(i, x)| Entry::parse(i + 1, x))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if entries.is_empty() {
+        bail!("no urls found in batch file {}.", path.to_string_lossy());
+    }
+
+    Ok(entries)
+}
+
+/// Download entries using at most `jobs` parallel downloads, each one having its own progress bar,
+/// and print a summary of successful, failed and skipped downloads at the end.
+/// Failed downloads are handled as per `--on-error` policy.
+pub(super) fn download(save: Save, entries: Vec<Entry>, jobs: usize) -> Result<()> {
+    if save.output.as_deref() == Some("-") {
+        bail!("-o - cannot be used when downloading multiple urls.");
+    }
+
+    // Same output path would be overwritten by every download.
+    if entries.len() > 1
+        && entries.iter().any(|x| x.output.is_none())
+        && save.output.as_ref().is_some_and(|x| !x.contains('{'))
+    {
+        bail!("--output should be a template (eg. {{title}}.mkv) when downloading multiple urls.");
+    }
+
+    let jobs = jobs.min(entries.len());
+    let aborted = AtomicBool::new(false);
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(entries.iter().map(|_| None).collect::<Vec<_>>());
+    // Messages are written through a bar which is never drawn, so that line of progress bar
+    // above which they are written gets cleared first.
+    let pb = Mutex::new(tqdm!(leave = false));
+
+    std::thread::scope(|scope| {
+        for job in 0..jobs {
+            let save = &save;
+            let entries = &entries;
+            let aborted = &aborted;
+            let next = &next;
+            let results = &results;
+            let pb = &pb;
+
+            scope.spawn(move || loop {
+                if aborted.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let i = next.fetch_add(1, Ordering::SeqCst);
+
+                let entry = match entries.get(i) {
+                    Some(x) => x,
+                    None => break,
+                };
+
+                let _ = pb.lock().unwrap().write(format!(
+                    " {} [{}/{}] {}",
+                    "Processing".colorize("bold green"),
+                    i + 1,
+                    entries.len(),
+                    entry.url
+                ));
+
+                let position = if jobs > 1 { Some(job as u16) } else { None };
+                let mut attempt = 0;
+
+                let result = loop {
+                    let e = match entry.save(save.clone(), position).execute() {
+                        Ok(_) => break Ok(()),
+                        Err(e) => e,
+                    };
+                    let code = ExitCode::of(&e);
+                    let _ = pb.lock().unwrap().write(format!(
+                        "{}: {}",
+                        "error".colorize("bold red"),
+                        e
+                    ));
+
+                    // Retrying wouldn't help when keys are missing or user aborted.
+                    if save.on_error == OnError::Retry
+                        && attempt < RETRIES
+                        && code != ExitCode::Aborted
+                        && code != ExitCode::Drm
+                    {
+                        attempt += 1;
+                        let _ = pb.lock().unwrap().write(format!(
+                            "   {} [{}/{}] {} ({}/{})",
+                            "Retrying".colorize("bold yellow"),
+                            i + 1,
+                            entries.len(),
+                            entry.url,
+                            attempt,
+                            RETRIES
+                        ));
+                        continue;
+                    }
+
+                    if save.on_error == OnError::Abort || code == ExitCode::Aborted {
+                        aborted.store(true, Ordering::SeqCst);
+                    }
+
+                    break Err((code, e.to_string()));
+                };
+
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let skipped = results.iter().filter(|x| x.is_none()).count();
+    let failures = entries
+        .iter()
+        .zip(results)
+        .filter_map(|(entry, result)| match result {
+            Some(Err((code, e))) => Some((entry, code, e)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let succeeded = entries.len() - failures.len() - skipped;
+
+    eprintln!(
+        "    {} {} succeeded, {} failed, {} skipped",
+        "Summary".colorize("bold green"),
+        succeeded,
+        failures.len(),
+        skipped
+    );
+
+    for (entry, _, e) in &failures {
+        eprintln!(
+            "     {} {}: {} ({})",
+            "Failed".colorize("bold red"),
+            entry.source,
+            entry.url,
+            e
+        );
+    }
+
+    events::emit(
+        "summary",
+        json!({
+            "failed": failures
+                .iter()
+                .map(|(entry, code, e)| {
+                    json!({
+                        "code": *code as i32,
+                        "kind": code.as_str(),
+                        "message": e,
+                        "source": entry.source,
+                        "url": entry.url,
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "skipped": skipped,
+            "succeeded": succeeded,
+        }),
+    );
+
+    if !failures.is_empty() {
+        // Same exit code is kept only when every download failed for same reason.
+        let code = if failures.iter().any(|x| x.1 == ExitCode::Aborted) {
+            ExitCode::Aborted
+        } else if succeeded == 0 && skipped == 0 && failures.iter().all(|x| x.1 == failures[0].1) {
+            failures[0].1
+        } else {
+            ExitCode::Partial
+        };
+
+        return Err(code.tag(anyhow!(
+            "{} of {} downloads failed.",
+            failures.len(),
+            entries.len()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Entry> {
+        Entry::parse(3, text).unwrap()
+    }
+
+    #[test]
+    fn skips_empty_lines_and_comments() {
+        assert!(parse("").is_none());
+        assert!(parse("   ").is_none());
+        assert!(parse("# https://example.com/master.m3u8").is_none());
+    }
+
+    #[test]
+    fn parses_entries() {
+        let entry = parse("https://example.com/master.m3u8").unwrap();
+        assert_eq!(entry.url, "https://example.com/master.m3u8");
+        assert_eq!(entry.output, None);
+        assert!(entry.headers.is_empty());
+        assert_eq!(entry.source, "line 3");
+
+        let entry = parse("https://example.com/master.m3u8 \"my video.mkv\"").unwrap();
+        assert_eq!(entry.output.as_deref(), Some("my video.mkv"));
+
+        let entry = parse(
+            "  https://example.com/master.m3u8 -o out.mp4 -H Referer https://example.com --header 'User-Agent' 'vsd test'",
+        )
+        .unwrap();
+        assert_eq!(entry.output.as_deref(), Some("out.mp4"));
+        assert_eq!(
+            entry.headers,
+            ["Referer", "https://example.com", "User-Agent", "vsd test"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let error = |text: &str| Entry::parse(3, text).err().unwrap().to_string();
+
+        assert_eq!(
+            error("https://example.com/master.m3u8 -o"),
+            "missing value of -o on line 3 of batch file."
+        );
+        assert_eq!(
+            error("https://example.com/master.m3u8 --header Referer"),
+            "missing KEY VALUE of --header on line 3 of batch file."
+        );
+        assert_eq!(
+            error("https://example.com/master.m3u8 out.mkv other.mkv"),
+            "unexpected argument other.mkv on line 3 of batch file."
+        );
+        assert_eq!(
+            error("https://example.com/master.m3u8 --threads 4"),
+            "unexpected argument --threads on line 3 of batch file."
+        );
+        assert!(Entry::parse(3, "https://example.com/master.m3u8 \"out.mkv").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::cookie::{CookieJar, CookieParam};
+use anyhow::Result;
+use clap::Args;
+use cookie::Cookie;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Proxy, Url,
+};
+use std::{path::Path, sync::Arc};
+use vsd_core::playlist::MediaPlaylist;
+
+type CookieParams = Vec<CookieParam>;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36";
+
+/// Request client flags shared by `save` and `probe` subcommands.
+#[derive(Debug, Clone, Args)]
+pub struct ClientOptions {
+    /// Fill request client with some existing cookies value.
+    /// Cookies value can be same as document.cookie or in json format same as puppeteer.
+    #[arg(long, help_heading = "Client Options", default_value = "[]", hide_default_value = true, value_parser = cookie_parser)]
+    pub cookies: CookieParams,
+
+    /// Custom headers for requests.
+    /// This option can be used multiple times.
+    #[arg(long, help_heading = "Client Options", num_args = 2, value_names = &["KEY", "VALUE"])]
+    pub header: Vec<String>, // Vec<(String, String)> not supported
+
+    /// Skip checking and validation of site certificates.
+    #[arg(long, help_heading = "Client Options")]
+    pub no_certificate_checks: bool,
+
+    /// Skip passing query parameters where not needed.
+    #[arg(long, help_heading = "Client Options")]
+    pub no_query_pass: bool,
+
+    /// Set http(s) / socks proxy address for requests.
+    #[arg(long, help_heading = "Client Options", value_parser = proxy_address_parser)]
+    pub proxy: Option<Proxy>,
+
+    /// Set query parameters for requests.
+    #[arg(long, help_heading = "Client Options")]
+    pub query: Option<String>,
+
+    /// Fill request client with some existing cookies per domain.
+    /// First value for this option is set-cookie header and second value is url which was requested to send this set-cookie header.
+    /// Example `--set-cookie "foo=bar; Domain=yolo.local" https://yolo.local`.
+    /// This option can be used multiple times.
+    #[arg(long, help_heading = "Client Options", num_args = 2, value_names = &["SET_COOKIE", "URL"])]
+    pub set_cookie: Vec<String>, // Vec<(String, String)> not supported
+
+    /// Update and set user agent header for requests.
+    #[arg(long, help_heading = "Client Options", default_value = USER_AGENT)]
+    pub user_agent: String,
+}
+
+impl ClientOptions {
+    /// Build request client with cookies, headers, proxy and user agent set by these flags.
+    pub(super) fn build_client(&self) -> Result<Client> {
+        let mut client_builder = Client::builder()
+            .danger_accept_invalid_certs(self.no_certificate_checks)
+            .user_agent(&self.user_agent)
+            .cookie_store(true);
+
+        if !self.header.is_empty() {
+            let mut headers = HeaderMap::new();
+
+            for i in (0..self.header.len()).step_by(2) {
+                headers.insert(
+                    self.header[i].parse::<HeaderName>()?,
+                    self.header[i + 1].parse::<HeaderValue>()?,
+                );
+            }
+
+            client_builder = client_builder.default_headers(headers);
+        }
+
+        if let Some(proxy) = &self.proxy {
+            client_builder = client_builder.proxy(proxy.clone());
+        }
+
+        let mut jar = CookieJar::new();
+
+        if !self.set_cookie.is_empty() {
+            for i in (0..self.set_cookie.len()).step_by(2) {
+                jar.add_cookie_str(&self.set_cookie[i], &self.set_cookie[i + 1].parse::<Url>()?);
+            }
+        }
+
+        for cookie in &self.cookies {
+            if let Some(url) = &cookie.url {
+                jar.add_cookie_str(&format!("{}", cookie.as_cookie()), &url.parse::<Url>()?);
+            } else {
+                jar.add_cookie(cookie.as_cookie());
+            }
+        }
+
+        Ok(client_builder.cookie_provider(Arc::new(jar)).build()?)
+    }
+
+    /// Append `--query` or query parameters of stream url to segment urls of streams.
+    pub(super) fn pass_query(&self, streams: &mut [MediaPlaylist]) {
+        if self.no_query_pass {
+            return;
+        }
+
+        let query = self
+            .query
+            .as_ref()
+            .map(|x| x.trim_start_matches('&').to_owned());
+
+        for stream in streams {
+            if let Some(query) = query.clone().or(stream
+                .uri
+                .parse::<Url>()
+                .unwrap()
+                .query()
+                .map(|y| y.to_owned()))
+            {
+                stream.add_query(&query);
+            }
+        }
+    }
+}
+
+fn cookie_parser(s: &str) -> Result<CookieParams, String> {
+    if Path::new(s).exists() {
+        Ok(serde_json::from_slice::<CookieParams>(
+            &std::fs::read(s).map_err(|_| format!("could not read {}.", s))?,
+        )
+        .map_err(|x| format!("could not deserialize cookies from json file. {}", x))?)
+    } else if let Ok(cookies) = serde_json::from_str::<CookieParams>(s) {
+        Ok(cookies)
+    } else {
+        let mut cookies = vec![];
+        for cookie in Cookie::split_parse(s) {
+            match cookie {
+                Ok(x) => cookies.push(CookieParam::new(x.name(), x.value())),
+                Err(e) => return Err(format!("could not split parse cookies. {}", e)),
+            }
+        }
+        Ok(cookies)
+    }
+}
+
+fn proxy_address_parser(s: &str) -> Result<Proxy, String> {
+    Proxy::all(s).map_err(|x| x.to_string())
+}
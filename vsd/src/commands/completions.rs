@@ -0,0 +1,24 @@
+use super::Args as Cli;
+use anyhow::Result;
+use clap::{Args, CommandFactory};
+use clap_complete::Shell;
+
+/// Generate shell completions and print them to stdout.
+#[derive(Debug, Clone, Args)]
+pub struct Completions {
+    /// Shell for which completions are generated.
+    #[arg(required = true, value_enum)]
+    shell: Shell,
+}
+
+impl Completions {
+    pub fn execute(self) -> Result<()> {
+        clap_complete::generate(
+            self.shell,
+            &mut Cli::command(),
+            env!("CARGO_PKG_NAME"),
+            &mut std::io::stdout(),
+        );
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@ pub struct Extract {
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum Codec {
+    Ass,
     Subrip,
     Webvtt,
 }
@@ -39,6 +40,7 @@ impl Extract {
         print!(
             "{}",
             match &self.codec {
+                Codec::Ass => subtitles.as_ass(),
                 Codec::Subrip => subtitles.as_srt(),
                 Codec::Webvtt => subtitles.as_vtt(),
             }
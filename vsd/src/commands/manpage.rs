@@ -0,0 +1,28 @@
+use super::Args as Cli;
+use anyhow::Result;
+use clap::{Args, CommandFactory};
+use std::path::PathBuf;
+
+/// Generate man page and print it to stdout.
+#[derive(Debug, Clone, Args)]
+pub struct Manpage {
+    /// Write man pages of vsd and all of its subcommands (vsd-save.1, etc.) into this directory
+    /// instead of printing man page of vsd to stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl Manpage {
+    pub fn execute(self) -> Result<()> {
+        let command = Cli::command().name(env!("CARGO_PKG_NAME"));
+
+        if let Some(output) = self.output {
+            std::fs::create_dir_all(&output)?;
+            clap_mangen::generate_to(command, output)?;
+        } else {
+            clap_mangen::Man::new(command).render(&mut std::io::stdout())?;
+        }
+
+        Ok(())
+    }
+}
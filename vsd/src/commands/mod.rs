@@ -1,18 +1,29 @@
+mod batch;
+mod client;
+mod completions;
 mod extract;
+mod manpage;
 mod merge;
+mod probe;
 mod save;
+mod self_update;
 
 #[cfg(feature = "browser")]
 mod capture;
 
-pub use extract::Extract;
+pub use completions::Completions;
+pub use extract::{Codec, Extract};
+pub use manpage::Manpage;
 pub use merge::Merge;
-pub use save::{Quality, Save};
+pub use probe::Probe;
+pub use save::{Format, OnError, Save};
+pub use self_update::SelfUpdate;
 
 #[cfg(feature = "browser")]
 pub use capture::Capture;
 
-use clap::{ColorChoice, Parser, Subcommand};
+use clap::{ArgAction, ColorChoice, Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Parser)]
 #[command(
@@ -35,13 +46,26 @@ pub struct Args {
     /// When to output colored text.
     #[arg(long, global = true, default_value_t = ColorChoice::Auto)]
     pub color: ColorChoice,
+
+    /// Write logs (requests, retries, decryption keys, mux commands, etc.) to this file.
+    /// Debug logs are written by default, trace logs are also written with -vv.
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Print debug logs to stderr, use -vv for trace logs and -vvv for logs of dependencies too.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
 }
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum Commands {
     #[cfg(feature = "browser")]
     Capture(Capture),
+    Completions(Completions),
     Extract(Extract),
+    Manpage(Manpage),
     Merge(Merge),
+    Probe(Probe),
     Save(Save),
+    SelfUpdate(SelfUpdate),
 }
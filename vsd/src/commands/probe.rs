@@ -0,0 +1,181 @@
+use super::client::ClientOptions;
+use crate::{
+    downloader::{self, Prompts},
+    extractor, utils,
+};
+use anyhow::Result;
+use clap::Args;
+use kdam::term::Colorizer;
+use reqwest::Url;
+use serde_json::json;
+use std::path::PathBuf;
+use vsd_core::playlist::{MediaPlaylist, MediaType};
+
+/// Print streams of DASH and HLS playlists along with their codecs, durations,
+/// encryption and estimated sizes without downloading them.
+#[derive(Debug, Clone, Args)]
+pub struct Probe {
+    /// http(s):// | .mpd | .xml | .m3u8
+    #[arg(required = true)]
+    pub input: String,
+
+    /// Base url to be used for building absolute url to segment.
+    /// This flag is usually needed for local input files.
+    /// By default redirected playlist url is used.
+    #[arg(long)]
+    pub base_url: Option<Url>,
+
+    /// Site extractor used for resolving webpage url into playlist url and headers.
+    /// Executables inside ~/.config/vsd/extractors directory are also tried after these.
+    /// This option can be used multiple times.
+    #[arg(long)]
+    pub extractor: Vec<PathBuf>,
+
+    /// Print streams in json format.
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub client: ClientOptions,
+}
+
+impl Probe {
+    pub fn execute(mut self) -> Result<()> {
+        let extraction = extractor::extract(&self.extractor, &self.input)?;
+
+        if let Some(extraction) = &extraction {
+            self.client.header.splice(0..0, extraction.headers());
+            self.input = extraction.url.clone();
+        }
+
+        let client = self.client.build_client()?;
+        let prompts = Prompts {
+            skip: true,
+            raw: false,
+        };
+        let mut meta =
+            downloader::fetch_playlist(self.base_url.clone(), &client, &self.input, &prompts)?;
+
+        if let Some(title) = extraction.and_then(|x| x.title) {
+            meta.title = Some(title);
+        }
+
+        // Streams are sorted in same order as they are listed while selecting streams.
+        let mut playlist =
+            downloader::parse_all_streams(self.base_url, &client, &meta)?.sort_streams(None, None);
+        self.client.pass_query(&mut playlist.streams);
+
+        let streams = [MediaType::Video, MediaType::Audio, MediaType::Subtitles]
+            .into_iter()
+            .flat_map(|media_type| {
+                playlist
+                    .streams
+                    .iter()
+                    .filter(move |x| {
+                        x.media_type == media_type
+                            // Single media playlists have undefined media type.
+                            || (media_type == MediaType::Video
+                                && x.media_type == MediaType::Undefined)
+                    })
+                    .enumerate()
+                    .map(|(i, x)| (i + 1, x))
+            })
+            .collect::<Vec<_>>();
+
+        if self.json {
+            let streams = streams
+                .iter()
+                .map(|(id, stream)| {
+                    json!({
+                        "bandwidth": stream.bandwidth,
+                        "channels": stream.channels,
+                        "codecs": stream.codecs,
+                        "default_kid": stream.default_kid(),
+                        "drm": stream.display_drm(),
+                        "duration": duration(stream),
+                        "estimated_size": estimated_size(stream),
+                        "forced": stream.forced,
+                        "frame_rate": stream.frame_rate,
+                        "id": id,
+                        "language": stream.language,
+                        "live": stream.live,
+                        "media_type": stream.media_type.to_string(),
+                        "resolution": stream.resolution.map(|(w, h)| format!("{}x{}", w, h)),
+                        "segments": stream.segments.len(),
+                        "uri": stream.uri,
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            serde_json::to_writer(
+                std::io::stdout(),
+                &json!({
+                    "playlist_type": playlist.playlist_type,
+                    "streams": streams,
+                    "title": meta.media_title(),
+                    "url": meta.url.as_str(),
+                }),
+            )?;
+            println!();
+            return Ok(());
+        }
+
+        if let Some(title) = meta.media_title() {
+            println!("{} {}", "Title:".colorize("bold"), title);
+        }
+
+        println!("{} {}", "Url:".colorize("bold"), meta.url);
+        let mut media_type = None;
+
+        for (id, stream) in streams {
+            let kind = match stream.media_type {
+                MediaType::Audio => "Audio Streams",
+                MediaType::Subtitles => "Subtitle Streams",
+                MediaType::Undefined | MediaType::Video => "Video Streams",
+            };
+
+            if media_type != Some(kind) {
+                println!("─────── {} ────────", kind);
+                media_type = Some(kind);
+            }
+
+            let display = if stream.media_type == MediaType::Undefined {
+                stream.display_video_stream()
+            } else {
+                stream.display_stream()
+            };
+            println!("{:2}) {}", id, display);
+
+            let mut details = vec![format!(
+                "duration: {}",
+                utils::format_duration(duration(stream))
+            )];
+            details.push(format!("segments: {}", stream.segments.len()));
+
+            if let Some(size) = estimated_size(stream) {
+                details.push(format!("size: ~{}", utils::format_bytes(size, 2).2));
+            }
+
+            if let Some(kid) = stream.default_kid() {
+                details.push(format!("kid: {}", kid));
+            }
+
+            println!("    {}", details.join(", ").colorize("cyan"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Total duration of stream in seconds.
+fn duration(stream: &MediaPlaylist) -> f64 {
+    stream.segments.iter().map(|x| x.duration as f64).sum()
+}
+
+/// Size of stream in bytes, estimated from its bandwidth (bits per second) and duration.
+fn estimated_size(stream: &MediaPlaylist) -> Option<usize> {
+    stream
+        .bandwidth
+        .map(|x| (x as f64 / 8.0 * duration(stream)) as usize)
+        .filter(|x| *x > 0)
+}
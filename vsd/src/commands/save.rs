@@ -1,30 +1,34 @@
 use crate::{
-    cookie::{CookieJar, CookieParam},
-    downloader::{self, Prompts},
+    commands::{batch, client::ClientOptions, Codec},
+    downloader::{self, Prompts, Selection},
+    events, extractor,
+    ffmpeg::Ffmpeg,
+    notify::{Notifier, Output},
+    remote::Remote,
     utils,
 };
-use anyhow::Result;
-use clap::Args;
-use cookie::Cookie;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone};
+use clap::{Args, ValueEnum};
 use kdam::term::Colorizer;
-use reqwest::{
-    blocking::Client,
-    header::{HeaderMap, HeaderName, HeaderValue},
-    Proxy, Url,
-};
+use reqwest::Url;
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
+};
+use vsd_core::{
+    playlist::{self, Quality, StreamSelector},
+    Limiter,
 };
-
-type CookieParams = Vec<CookieParam>;
 
 /// Download DASH and HLS playlists.
 #[derive(Debug, Clone, Args)]
 pub struct Save {
     /// http(s):// | .mpd | .xml | .m3u8
-    #[arg(required = true)]
-    pub input: String,
+    /// Multiple inputs are downloaded in parallel, each one having its own progress bar.
+    #[arg(required_unless_present = "batch_file")]
+    pub input: Vec<String>,
 
     /// Base url to be used for building absolute url to segment.
     /// This flag is usually needed for local input files.
@@ -32,13 +36,69 @@ pub struct Save {
     #[arg(long)]
     pub base_url: Option<Url>,
 
+    /// Download every url listed in this file, one per line.
+    /// Each line can optionally be followed by an output path and headers,
+    /// eg. `URL video.mkv --header Referer https://example.com`.
+    /// Empty lines and lines starting with # are skipped.
+    /// A summary of successful and failed downloads is printed at the end.
+    #[arg(long, conflicts_with = "input")]
+    pub batch_file: Option<PathBuf>,
+
+    /// Maximum number of urls from --batch-file downloaded in parallel.
+    /// Prompts are skipped when more than one job is used.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub batch_jobs: u8,
+
+    /// What to do when a download from multiple inputs or --batch-file fails.
+    #[arg(long, value_enum, default_value_t = OnError::Continue)]
+    pub on_error: OnError,
+
     /// Change directory path for temporarily downloaded files.
     /// By default current working directory is used.
     #[arg(short, long)]
     pub directory: Option<PathBuf>,
 
+    /// Run this command once download completes or fails (eg. "notify-send vsd {output}").
+    /// {input}, {output} and {status} placeholders are replaced in arguments and
+    /// json payload of --notify-webhook is passed as VSD_NOTIFICATION environment variable.
+    #[arg(long, allow_hyphen_values = true)]
+    pub exec: Option<String>,
+
+    /// Site extractor used for resolving webpage url into playlist url and headers.
+    /// Extractor receives url as argument and prints {"url": "...", "headers": {...}, "title": "..."}
+    /// json on stdout, or nothing if url is not supported.
+    /// Executables inside ~/.config/vsd/extractors directory are also tried after these.
+    /// This option can be used multiple times.
+    #[arg(long)]
+    pub extractor: Vec<PathBuf>,
+
+    /// Extra arguments passed to ffmpeg just before output path when muxing
+    /// (eg. "-movflags +faststart").
+    /// Arguments are split on whitespaces, quotes can be used to keep whitespaces.
+    #[arg(long, allow_hyphen_values = true)]
+    pub ffmpeg_args: Option<String>,
+
+    /// Path to ffmpeg binary.
+    /// By default ffmpeg is searched in PATH environment variable.
+    #[arg(long)]
+    pub ffmpeg_path: Option<PathBuf>,
+
+    /// Post a json payload (status, input, output, size, duration and error) to this url
+    /// once download completes or fails.
+    #[arg(long)]
+    pub notify_webhook: Option<String>,
+
     /// Mux all downloaded streams to a video container (.mp4, .mkv, etc.) using ffmpeg.
     /// Note that existing files will be overwritten and downloaded streams will be deleted.
+    /// Use `-` to write the merged stream to stdout as segments complete (eg. `-o - | mpv -`),
+    /// in which case exactly one video/audio stream should be selected and prompts are skipped.
+    /// Output path can also be a template (eg. `{title}.{resolution}.{vcodec}.{lang}.mkv`).
+    /// Possible variables are acodec, bandwidth, date, height, lang, resolution, time, title,
+    /// vcodec and width. Values are sanitized to be valid file names on every platform.
+    /// Output path can also be a remote location (`s3://bucket/key.mkv` or
+    /// `webdav://[user:password@]host/path.mkv`), in which case output file is uploaded once
+    /// it is written and then deleted locally. S3 credentials are read from AWS_ACCESS_KEY_ID,
+    /// AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN, AWS_REGION and AWS_ENDPOINT_URL environment variables.
     #[arg(short, long)]
     pub output: Option<String>,
 
@@ -51,6 +111,11 @@ pub struct Save {
     #[arg(long)]
     pub raw_prompts: bool,
 
+    /// Keep only ascii letters, digits and -_.,+()[] characters in values of output template
+    /// (eg. {title}), accents are stripped and other characters are replaced with _.
+    #[arg(long)]
+    pub restrict_filenames: bool,
+
     /// Preferred language when multiple audio streams with different languages are available.
     /// Must be in RFC 5646 format (eg. fr or en-AU).
     /// If a preference is not specified and multiple audio streams are present,
@@ -67,54 +132,36 @@ pub struct Save {
 
     /// Automatic selection of some standard resolution streams with highest bandwidth stream variant from playlist.
     /// If matching resolution of WIDTHxHEIGHT is not found then only resolution HEIGHT would be considered for selection.
-    /// comman values: [lowest, min, 144p, 240p, 360p, 480p, 720p, hd, 1080p, fhd, 2k, 1440p, qhd, 4k, 8k, highest, max]
+    /// comman values: [lowest, min, worst, 144p, 240p, 360p, 480p, 720p, hd, 1080p, fhd, 2k, 1440p, qhd, 4k, 8k, highest, max, best]
     #[arg(short, long, help_heading = "Automation Options", default_value = "highest", value_name = "WIDTHxHEIGHT|HEIGHTp", value_parser = quality_parser)]
     pub quality: Quality,
 
-    /// Skip user input prompts and proceed with defaults.
-    #[arg(long, help_heading = "Automation Options")]
-    pub skip_prompts: bool,
-
-    /// Fill request client with some existing cookies value.
-    /// Cookies value can be same as document.cookie or in json format same as puppeteer.
-    #[arg(long, help_heading = "Client Options", default_value = "[]", hide_default_value = true, value_parser = cookie_parser)]
-    pub cookies: CookieParams,
-
-    /// Custom headers for requests.
+    /// Select audio streams without prompting.
+    /// Possible values are id=N (Nth audio stream as listed in prompt), lang=LANG, codec=CODEC, all and none.
+    /// First matching stream is selected for each value except all.
+    /// Prompts are skipped when any of --select-* flags is used.
     /// This option can be used multiple times.
-    #[arg(long, help_heading = "Client Options", num_args = 2, value_names = &["KEY", "VALUE"])]
-    pub header: Vec<String>, // Vec<(String, String)> not supported
-
-    /// Skip checking and validation of site certificates.
-    #[arg(long, help_heading = "Client Options")]
-    pub no_certificate_checks: bool,
+    #[arg(long, help_heading = "Automation Options", value_name = "SELECTOR", value_parser = selector_parser)]
+    pub select_audio: Vec<StreamSelector>,
 
-    /// Skip passing query parameters where not needed.
-    #[arg(long, help_heading = "Client Options")]
-    pub no_query_pass: bool,
-
-    /// Set http(s) / socks proxy address for requests.
-    #[arg(long, help_heading = "Client Options", value_parser = proxy_address_parser)]
-    pub proxy: Option<Proxy>,
-
-    /// Set query parameters for requests.
-    #[arg(long, help_heading = "Client Options")]
-    pub query: Option<String>,
+    /// Select subtitles streams without prompting.
+    /// Possible values are same as --select-audio.
+    /// This option can be used multiple times.
+    #[arg(long, help_heading = "Automation Options", value_name = "SELECTOR", value_parser = selector_parser)]
+    pub select_subs: Vec<StreamSelector>,
 
-    /// Fill request client with some existing cookies per domain.
-    /// First value for this option is set-cookie header and second value is url which was requested to send this set-cookie header.
-    /// Example `--set-cookie "foo=bar; Domain=yolo.local" https://yolo.local`.
+    /// Select video streams without prompting, --quality is ignored when this flag is used.
+    /// Possible values are same as --select-audio.
     /// This option can be used multiple times.
-    #[arg(long, help_heading = "Client Options", num_args = 2, value_names = &["SET_COOKIE", "URL"])]
-    pub set_cookie: Vec<String>, // Vec<(String, String)> not supported
+    #[arg(long, help_heading = "Automation Options", value_name = "SELECTOR", value_parser = selector_parser)]
+    pub select_video: Vec<StreamSelector>,
+
+    /// Skip user input prompts and proceed with defaults.
+    #[arg(long, help_heading = "Automation Options")]
+    pub skip_prompts: bool,
 
-    /// Update and set user agent header for requests.
-    #[arg(
-        long,
-        help_heading = "Client Options",
-        default_value = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36"
-    )]
-    pub user_agent: String,
+    #[command(flatten)]
+    pub client: ClientOptions,
 
     /// Use all supplied keys for decryption instead of using keys which matches with default kid only.
     #[arg(long, help_heading = "Decrypt Options")]
@@ -137,37 +184,184 @@ pub struct Save {
     #[arg(long, help_heading = "Download Options", default_value_t = 15)]
     pub retry_count: u8,
 
+    /// Maximum number of segment requests at a time, shared by all parallel downloads
+    /// (multiple inputs or --batch-jobs). By default only --threads limits requests of each download.
+    #[arg(long, help_heading = "Download Options")]
+    pub max_connections: Option<usize>,
+
+    /// Maximum download rate in bytes per second (eg. 500K or 2M), shared by all parallel downloads.
+    #[arg(long, help_heading = "Download Options", value_parser = size_parser)]
+    pub rate_limit: Option<u64>,
+
     /// Download streams without merging them.
     /// Note that --output flag is ignored if this flag is used.
     #[arg(long, help_heading = "Download Options")]
     pub no_merge: bool,
 
+    /// Mux streams into this container. Extension is added to --output if it doesn't have one.
+    /// Codecs of selected streams are checked against this container before downloading,
+    /// eg. ec-3 audio cannot be stored in ts and webvtt subtitles are only kept in mkv.
+    #[arg(long, help_heading = "Download Options", value_enum)]
+    pub format: Option<Format>,
+
+    /// Show download progress as a progress bar or emit it as newline delimited json events.
+    /// Json events are written to stdout (stderr when writing stream to stdout).
+    #[arg(long, help_heading = "Download Options", value_enum, default_value_t = ProgressStyle::Bar)]
+    pub progress: ProgressStyle,
+
+    /// Save each selected stream as its own clean file (eg. video.mp4, audio.en.m4a, subs.en.srt)
+    /// instead of muxing them together. Stem of --output is used as prefix of these files.
+    /// Subtitles are converted to srt unless --subs-codec is used.
+    #[arg(long, help_heading = "Download Options")]
+    pub no_mux: bool,
+
+    /// Mux streams into output file while they are being downloaded,
+    /// so that partially downloaded output can already be opened in a player.
+    /// Segments of all streams are downloaded together when this flag is used.
+    /// Note that only .mkv outputs and .mp4 outputs without subtitles are supported.
+    #[arg(long, help_heading = "Download Options")]
+    pub progressive: bool,
+
+    /// Convert downloaded subtitles to this codec.
+    /// ass preserves positioning, colors and italics of cues which are lost in srt.
+    /// By default wvtt and vtt subtitles are saved as vtt and others as srt.
+    #[arg(long, help_heading = "Download Options", value_enum)]
+    pub subs_codec: Option<Codec>,
+
+    /// Split output into numbered files (eg. output.001.mkv) at each boundary of this kind.
+    /// Useful for event streams where each program should land in its own file.
+    /// Note that --output flag is required when this flag is used.
+    #[arg(long, help_heading = "Download Options", value_enum)]
+    pub split_on: Option<SplitOn>,
+
     /// Maximum number of threads for parllel downloading of segments.
     /// Number of threads should be in range 1-16 (inclusive).
     #[arg(short, long, help_heading = "Download Options", default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..=16))]
     pub threads: u8,
+
+    /// Stop recording live streams once this much media is recorded (eg. 90s, 30m or 1h30m).
+    /// By default live streams are recorded until they end or are interrupted.
+    #[arg(long, help_heading = "Live Options", value_parser = duration_parser)]
+    pub duration: Option<Duration>,
+
+    /// Start recording live streams from oldest segment which is still available
+    /// (start of DVR window or timeShiftBufferDepth) instead of the live edge.
+    #[arg(long, help_heading = "Live Options", conflicts_with = "live_edge")]
+    pub from_start: bool,
+
+    /// Start recording live streams from this many segments before the live edge.
+    /// Use 0 to record only segments published after recording started.
+    #[arg(long, help_heading = "Live Options", default_value_t = 3)]
+    pub live_edge: usize,
+
+    /// Keep retrying to refresh live playlists for this long (eg. 90s or 10m) when they
+    /// temporarily fail (HTTP 404/5xx or dropped connections) before giving up.
+    #[arg(long, help_heading = "Live Options", default_value = "5m", value_parser = duration_parser)]
+    pub live_retry_window: Duration,
+
+    /// Keep only this much of most recently recorded media of live streams on disk
+    /// (eg. 30m or 2h), older segments are deleted while recording.
+    #[arg(long, help_heading = "Live Options", value_parser = duration_parser)]
+    pub max_duration: Option<Duration>,
+
+    /// Keep only this many bytes of most recently recorded media of live streams on disk
+    /// (eg. 500M or 4G), older segments are deleted while recording.
+    #[arg(long, help_heading = "Live Options", value_parser = size_parser)]
+    pub max_size: Option<u64>,
+
+    /// While recording live streams, also maintain a rolling local hls playlist (live.m3u8)
+    /// and its segments inside this directory, so that recording can be watched with a short delay.
+    #[arg(long, help_heading = "Live Options")]
+    pub restream_dir: Option<PathBuf>,
+
+    /// Serve --restream-dir over http on this port (eg. http://192.168.1.2:8080/live.m3u8),
+    /// so that another device on the LAN can watch the recording.
+    #[arg(long, help_heading = "Live Options", requires = "restream_dir")]
+    pub restream_port: Option<u16>,
+
+    /// Wait until this time before fetching playlist and downloading it.
+    /// Time can be in RFC 3339 format (eg. 2024-05-01T20:00:00+02:00), local time
+    /// (eg. "2024-05-01 20:00" or 20:00) or relative to now (eg. +30m or +1h30m).
+    #[arg(long, help_heading = "Live Options", value_parser = start_at_parser)]
+    pub start_at: Option<DateTime<Local>>,
+
+    /// Start recording live streams from this wall clock time inside DVR window, using
+    /// EXT-X-PROGRAM-DATE-TIME tags of hls playlists or availability start time of dash manifests.
+    /// Time can be in RFC 3339 format (eg. 2024-05-01T19:55:00Z), local time
+    /// (eg. "2024-05-01 19:55") or relative to now (eg. -30m).
+    #[arg(long, help_heading = "Live Options", allow_hyphen_values = true, conflicts_with_all = ["from_start", "live_edge"], value_parser = start_pdt_parser)]
+    pub start_pdt: Option<DateTime<Local>>,
+
+    /// Position of progress bar, used when multiple downloads are running in parallel.
+    #[arg(skip)]
+    pub bar_position: u16,
+
+    /// Limits shared by all parallel downloads.
+    #[arg(skip)]
+    pub limiter: Option<Arc<Limiter>>,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum ProgressStyle {
+    /// Progress bar along with status messages.
+    Bar,
+    /// Newline delimited json events (segment, stage, retry, key and error).
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OnError {
+    /// Stop starting new downloads, running ones are completed.
+    Abort,
+    /// Continue with remaining downloads.
+    Continue,
+    /// Retry failed download up to 2 more times, unless it failed because of drm.
+    Retry,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Matroska container, supports every codec and subtitles.
+    Mkv,
+    /// Mp4 container.
+    Mp4,
+    /// Mpeg transport stream container.
+    Ts,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Mkv => "mkv",
+            Self::Mp4 => "mp4",
+            Self::Ts => "ts",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum SplitOn {
+    /// HLS EXT-X-DATERANGE tags and SCTE-35 cue tags.
+    Daterange,
+    /// HLS EXT-X-DISCONTINUITY tags.
+    Discontinuity,
+    /// DASH periods.
+    Period,
 }
 
-#[derive(Debug, Clone)]
-pub enum Quality {
-    Lowest,
-    Highest,
-    Resolution(u16, u16),
-    Youtube144p,
-    Youtube240p,
-    Youtube360p,
-    Youtube480p,
-    Youtube720p,
-    Youtube1080p,
-    Youtube2k,
-    Youtube1440p,
-    Youtube4k,
-    Youtube8k,
+impl From<&SplitOn> for playlist::SplitOn {
+    fn from(value: &SplitOn) -> Self {
+        match value {
+            SplitOn::Daterange => Self::Daterange,
+            SplitOn::Discontinuity => Self::Discontinuity,
+            SplitOn::Period => Self::Period,
+        }
+    }
 }
 
 fn quality_parser(s: &str) -> Result<Quality, String> {
     Ok(match s.to_lowercase().as_str() {
-        "lowest" | "min" => Quality::Lowest,
+        "lowest" | "min" | "worst" => Quality::Lowest,
         "144p" => Quality::Youtube144p,
         "240p" => Quality::Youtube240p,
         "360p" => Quality::Youtube360p,
@@ -178,7 +372,7 @@ fn quality_parser(s: &str) -> Result<Quality, String> {
         "1440p" | "qhd" => Quality::Youtube1440p,
         "4k" => Quality::Youtube4k,
         "8k" => Quality::Youtube8k,
-        "highest" | "max" => Quality::Highest,
+        "highest" | "max" | "best" => Quality::Highest,
         x if x.ends_with('p') => Quality::Resolution(
             0,
             x.trim_end_matches('p')
@@ -197,8 +391,8 @@ fn quality_parser(s: &str) -> Result<Quality, String> {
                 Err(format!(
                     "could not parse resolution WIDTHxHEIGHT. comman values: [{}]",
                     [
-                        "lowest", "min", "144p", "240p", "360p", "480p", "720p", "hd", "1080p",
-                        "fhd", "2k", "1440p", "qhd", "4k", "8k", "highest", "max"
+                        "lowest", "min", "worst", "144p", "240p", "360p", "480p", "720p", "hd",
+                        "1080p", "fhd", "2k", "1440p", "qhd", "4k", "8k", "highest", "max", "best"
                     ]
                     .iter()
                     .map(|x| x.colorize("green"))
@@ -238,78 +432,219 @@ fn key_parser(s: &str) -> Result<(Option<String>, String), String> {
     Ok((key_id, key))
 }
 
-fn cookie_parser(s: &str) -> Result<CookieParams, String> {
-    if Path::new(s).exists() {
-        Ok(serde_json::from_slice::<CookieParams>(
-            &std::fs::read(s).map_err(|_| format!("could not read {}.", s))?,
-        )
-        .map_err(|x| format!("could not deserialize cookies from json file. {}", x))?)
-    } else if let Ok(cookies) = serde_json::from_str::<CookieParams>(s) {
-        Ok(cookies)
-    } else {
-        let mut cookies = vec![];
-        for cookie in Cookie::split_parse(s) {
-            match cookie {
-                Ok(x) => cookies.push(CookieParam::new(x.name(), x.value())),
-                Err(e) => return Err(format!("could not split parse cookies. {}", e)),
+fn selector_parser(s: &str) -> Result<StreamSelector, String> {
+    Ok(match s.split_once('=') {
+        Some(("id", x)) => StreamSelector::Id(
+            x.parse::<usize>()
+                .ok()
+                .filter(|x| *x > 0)
+                .ok_or_else(|| "id should be a number starting from 1.".to_owned())?,
+        ),
+        Some(("lang", x)) if !x.is_empty() => StreamSelector::Lang(x.to_owned()),
+        Some(("codec", x)) if !x.is_empty() => StreamSelector::Codec(x.to_owned()),
+        None if s == "all" => StreamSelector::All,
+        None if s == "none" => StreamSelector::None,
+        _ => Err(format!(
+            "could not parse selector. possible values: [{}]",
+            ["id=N", "lang=LANG", "codec=CODEC", "all", "none"]
+                .iter()
+                .map(|x| x.colorize("green"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ))?,
+    })
+}
+
+fn size_parser(s: &str) -> Result<u64, String> {
+    let (number, multiplier) = match s.to_uppercase().trim_end_matches(['B', 'I']) {
+        x if x.ends_with('K') => (x.trim_end_matches('K').to_owned(), 1024.0),
+        x if x.ends_with('M') => (x.trim_end_matches('M').to_owned(), 1024.0 * 1024.0),
+        x if x.ends_with('G') => (x.trim_end_matches('G').to_owned(), 1024.0 * 1024.0 * 1024.0),
+        x => (x.to_owned(), 1.0),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|x| *x > 0.0)
+        .map(|x| (x * multiplier) as u64)
+        .ok_or_else(|| "could not parse size, eg. 500K or 2M.".to_owned())
+}
+
+/// Parse durations like 90, 90s, 30m, 2h or 1h30m.
+fn duration_parser(s: &str) -> Result<Duration, String> {
+    let error = || format!("could not parse duration {}, eg. 90s, 30m or 1h30m.", s);
+    let mut seconds = 0;
+    let mut number = String::new();
+
+    for c in s.trim().chars() {
+        let multiplier = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'h' | 'H' => 3600,
+            'm' | 'M' => 60,
+            's' | 'S' => 1,
+            _ => return Err(error()),
+        };
+
+        seconds += number.parse::<u64>().map_err(|_| error())? * multiplier;
+        number.clear();
+    }
+
+    if !number.is_empty() {
+        seconds += number.parse::<u64>().map_err(|_| error())?;
+    }
+
+    if seconds == 0 {
+        return Err(error());
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+fn start_at_parser(s: &str) -> Result<DateTime<Local>, String> {
+    start_at(s, Local::now())
+}
+
+/// Parse start time, relative times are counted from `now`.
+fn start_at(s: &str, now: DateTime<Local>) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+
+    if let Some(duration) = s.strip_prefix('+') {
+        let duration =
+            chrono::Duration::from_std(duration_parser(duration)?).map_err(|x| x.to_string())?;
+        return Ok(now + duration);
+    }
+
+    if let Some(time) = parse_datetime(s) {
+        return Ok(time);
+    }
+
+    let local = |x: NaiveDateTime| Local.from_local_datetime(&x).earliest();
+
+    // Only time is specified, next occurrence of it is used.
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            let mut date = now.date_naive();
+
+            if time <= now.time() {
+                date = date.succ_opt().unwrap();
+            }
+
+            if let Some(time) = local(date.and_time(time)) {
+                return Ok(time);
             }
         }
-        Ok(cookies)
     }
+
+    Err(format!(
+        "could not parse time {}, eg. 2024-05-01T20:00:00+02:00, 20:00 or +30m.",
+        s
+    ))
+}
+
+fn start_pdt_parser(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+
+    if let Some(duration) = s.strip_prefix('-') {
+        let duration =
+            chrono::Duration::from_std(duration_parser(duration)?).map_err(|x| x.to_string())?;
+        return Ok(Local::now() - duration);
+    }
+
+    parse_datetime(s).ok_or_else(|| {
+        format!(
+            "could not parse time {}, eg. 2024-05-01T19:55:00Z, \"2024-05-01 19:55\" or -30m.",
+            s
+        )
+    })
 }
 
-fn proxy_address_parser(s: &str) -> Result<Proxy, String> {
-    Proxy::all(s).map_err(|x| x.to_string())
+/// Parse time in RFC 3339 format or local date and time.
+fn parse_datetime(s: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.with_timezone(&Local));
+    }
+
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .filter_map(|x| NaiveDateTime::parse_from_str(s, x).ok())
+        .find_map(|x| Local.from_local_datetime(&x).earliest())
 }
 
 impl Save {
     pub fn execute(mut self) -> Result<()> {
-        let mut client_builder = Client::builder()
-            .danger_accept_invalid_certs(self.no_certificate_checks)
-            .user_agent(self.user_agent)
-            .cookie_store(true);
-
-        if !self.header.is_empty() {
-            let mut headers = HeaderMap::new();
-
-            for i in (0..self.header.len()).step_by(2) {
-                headers.insert(
-                    self.header[i].parse::<HeaderName>()?,
-                    self.header[i + 1].parse::<HeaderValue>()?,
-                );
-            }
+        // Waited here only once even when multiple urls are downloaded.
+        if let Some(start_at) = self.start_at.take() {
+            wait_until(start_at);
+        }
 
-            client_builder = client_builder.default_headers(headers);
+        if self.limiter.is_none() && (self.max_connections.is_some() || self.rate_limit.is_some()) {
+            self.limiter = Some(Arc::new(Limiter::new(
+                self.max_connections,
+                self.rate_limit,
+            )));
         }
 
-        if let Some(proxy) = self.proxy {
-            client_builder = client_builder.proxy(proxy);
+        if let Some(batch_file) = self.batch_file.take() {
+            let jobs = self.batch_jobs as usize;
+            return batch::download(self, batch::read(&batch_file)?, jobs);
         }
 
-        let mut jar = CookieJar::new();
+        if self.input.len() > 1 {
+            let entries = self
+                .input
+                .iter()
+                .enumerate()
+                .map(|(i, x)| batch::Entry::new(format!("input {}", i + 1), x.to_owned()))
+                .collect::<Vec<_>>();
+            let jobs = entries.len();
+            return batch::download(self, entries, jobs);
+        }
 
-        if !self.set_cookie.is_empty() {
-            for i in (0..self.set_cookie.len()).step_by(2) {
-                jar.add_cookie_str(&self.set_cookie[i], &self.set_cookie[i + 1].parse::<Url>()?);
-            }
+        let notifier = Notifier {
+            exec: self.exec.clone(),
+            webhook: self.notify_webhook.clone(),
+        };
+        let input = self.input[0].clone();
+        let started = Instant::now();
+        let mut outputs = vec![];
+        let result = self.download(&mut outputs);
+        notifier.notify(&input, &outputs, started.elapsed(), &result);
+        result
+    }
+
+    /// Download single input, paths of written output files are pushed to `outputs`.
+    fn download(mut self, outputs: &mut Vec<Output>) -> Result<()> {
+        if self.progress == ProgressStyle::Json {
+            events::enable(self.output.as_deref() == Some("-"));
         }
 
-        for cookie in self.cookies {
-            if let Some(url) = &cookie.url {
-                jar.add_cookie_str(&format!("{}", cookie.as_cookie()), &url.parse::<Url>()?);
-            } else {
-                jar.add_cookie(cookie.as_cookie());
-            }
+        let extraction = extractor::extract(&self.extractor, &self.input[0])?;
+
+        if let Some(extraction) = &extraction {
+            // Headers passed on command line are preferred over extracted ones.
+            self.client.header.splice(0..0, extraction.headers());
+            self.input = vec![extraction.url.clone()];
         }
 
-        let client = client_builder.cookie_provider(Arc::new(jar)).build()?;
+        let client = self.client.build_client()?;
+        let ffmpeg = Ffmpeg::new(self.ffmpeg_path.clone(), self.ffmpeg_args.as_deref())?;
 
         let prompts = Prompts {
-            skip: self.skip_prompts,
+            // Interactive prompts cannot be used while stream is being written to stdout.
+            skip: self.skip_prompts || self.output.as_deref() == Some("-"),
             raw: self.raw_prompts,
         };
-        let meta =
-            downloader::fetch_playlist(self.base_url.clone(), &client, &self.input, &prompts)?;
+        events::stage("fetching");
+        let mut meta =
+            downloader::fetch_playlist(self.base_url.clone(), &client, &self.input[0], &prompts)?;
+
+        if let Some(title) = extraction.and_then(|x| x.title) {
+            meta.title = Some(title);
+        }
 
         if self.parse {
             let playlist = downloader::parse_all_streams(self.base_url.clone(), &client, &meta)?;
@@ -323,55 +658,242 @@ impl Save {
                 self.prefer_subs_lang,
                 &prompts,
                 self.quality,
+                &Selection {
+                    audio: self.select_audio.clone(),
+                    subs: self.select_subs.clone(),
+                    video: self.select_video.clone(),
+                },
             )?;
 
-            if !self.no_query_pass {
-                if let Some(query) = self.query.as_mut() {
-                    if query.starts_with('&') {
-                        *query = query.trim_start_matches('&').to_owned();
-                    }
+            self.client.pass_query(&mut selected_playlists.0);
+            self.client.pass_query(&mut selected_playlists.1);
+
+            let output = self
+                .output
+                .map(|x| {
+                    downloader::format_output(
+                        &x,
+                        &meta,
+                        &selected_playlists,
+                        self.restrict_filenames,
+                    )
+                })
+                .transpose()?;
+
+            let output = if let Some(format) = &self.format {
+                if self.no_mux {
+                    bail!("--format cannot be used with --no-mux.");
                 }
 
-                selected_playlists.0.iter_mut().for_each(|x| {
-                    if let Some(query) = self.query.clone().or(x
-                        .uri
-                        .parse::<Url>()
-                        .unwrap()
-                        .query()
-                        .map(|y| y.to_owned()))
-                    {
-                        x.add_query(&query);
+                let output = match output.as_deref() {
+                    None | Some("-") => {
+                        bail!("--format requires --output to be set to a file path.")
                     }
+                    Some(x) => downloader::container_output(x, format)?,
+                };
+
+                downloader::check_container(format, &selected_playlists)?;
+                Some(output)
+            } else {
+                output
+            };
+
+            let parts = if let Some(split_on) = &self.split_on {
+                if output.is_none() || output.as_deref() == Some("-") {
+                    bail!("--split-on requires --output to be set to a file path.");
+                }
+
+                downloader::split_playlists(selected_playlists, &split_on.into())
+            } else {
+                vec![selected_playlists]
+            };
+
+            let numbered = parts.len() > 1;
+            let remote = output.as_deref().map(Remote::parse).transpose()?.flatten();
+
+            if let Some(remote) = &remote {
+                if self.no_decrypt || self.no_merge || self.no_mux {
+                    bail!(
+                        "--no-decrypt, --no-merge and --no-mux cannot be used with remote output."
+                    );
+                }
+
+                remote.preflight()?;
+            }
+
+            for (i, part) in parts.into_iter().enumerate() {
+                let part_output = if numbered {
+                    output
+                        .as_ref()
+                        .map(|x| downloader::numbered_output(x, i + 1))
+                } else {
+                    output.clone()
+                };
+
+                // Remote outputs are written locally first and then uploaded.
+                let remote = part_output
+                    .as_deref()
+                    .filter(|_| remote.is_some())
+                    .map(Remote::parse)
+                    .transpose()?
+                    .flatten();
+                // Remote outputs are staged inside --directory when it is set.
+                let local_output = remote.as_ref().map(|x| match &self.directory {
+                    Some(directory) => directory.join(x.file_name()).to_string_lossy().to_string(),
+                    None => x.file_name(),
                 });
 
-                selected_playlists.1.iter_mut().for_each(|x| {
-                    if let Some(query) = self.query.clone().or(x
-                        .uri
-                        .parse::<Url>()
-                        .unwrap()
-                        .query()
-                        .map(|y| y.to_owned()))
-                    {
-                        x.add_query(&query);
+                // Staged file is deleted after uploading, so an existing file is never overwritten.
+                if let Some(local_output) = &local_output {
+                    if Path::new(local_output).exists() {
+                        bail!(
+                            "{} already exists, remote output is written there before uploading.",
+                            local_output
+                        );
                     }
-                });
-            }
+                }
 
-            downloader::download(
-                self.all_keys,
-                self.base_url,
-                client,
-                self.directory,
-                self.key,
-                self.no_decrypt,
-                self.no_merge,
-                self.output,
-                selected_playlists,
-                self.retry_count,
-                self.threads,
-            )?;
+                let written = local_output.clone().or(part_output.clone());
+
+                downloader::download(
+                    self.all_keys,
+                    self.bar_position,
+                    self.base_url.clone(),
+                    client.clone(),
+                    self.directory.clone(),
+                    ffmpeg.clone(),
+                    self.key.clone(),
+                    self.limiter.clone(),
+                    &downloader::LiveOptions {
+                        duration: self.duration,
+                        from_start: self.from_start,
+                        live_edge: self.live_edge,
+                        live_retry_window: self.live_retry_window,
+                        max_duration: self.max_duration,
+                        max_size: self.max_size,
+                        restream_dir: self.restream_dir.clone(),
+                        restream_port: self.restream_port,
+                        start_pdt: self.start_pdt,
+                    },
+                    self.no_decrypt,
+                    self.no_merge,
+                    self.no_mux,
+                    written.clone(),
+                    self.progressive,
+                    part,
+                    self.retry_count,
+                    self.subs_codec.clone(),
+                    self.threads,
+                    meta.media_title(),
+                )?;
+
+                if let (Some(remote), Some(local_output)) = (remote, local_output) {
+                    if !Path::new(&local_output).exists() {
+                        bail!("{} was not written, nothing to upload.", local_output);
+                    }
+
+                    let size = std::fs::metadata(&local_output)?.len();
+                    events::stage("uploading");
+                    remote.upload(&local_output)?;
+                    eprintln!("   {} {}", "Deleting".colorize("bold red"), local_output);
+                    std::fs::remove_file(&local_output)?;
+                    outputs.push(Output {
+                        path: part_output.unwrap(),
+                        size: Some(size),
+                    });
+                } else if let Some(written) = written.filter(|x| x != "-") {
+                    outputs.push(Output {
+                        size: std::fs::metadata(&written).ok().map(|x| x.len()),
+                        path: written,
+                    });
+                }
+            }
         }
 
+        events::stage("done");
         Ok(())
     }
 }
+
+/// Sleep until time, remaining time is checked at regular intervals so that system suspends
+/// and clock changes don't delay the start.
+fn wait_until(time: DateTime<Local>) {
+    let remaining = || (time - Local::now()).to_std().ok();
+
+    if let Some(duration) = remaining() {
+        eprintln!(
+            "    {} until {} ({} left)",
+            "Waiting".colorize("bold cyan"),
+            time.format("%Y-%m-%d %H:%M:%S %:z"),
+            utils::format_duration(duration.as_secs_f64())
+        );
+    }
+
+    while let Some(duration) = remaining() {
+        std::thread::sleep(duration.min(Duration::from_secs(30)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(duration_parser("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(duration_parser("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(duration_parser("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(duration_parser("2H"), Ok(Duration::from_secs(7200)));
+        assert_eq!(duration_parser("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(duration_parser(" 1h30m15 "), Ok(Duration::from_secs(5415)));
+        assert!(duration_parser("0").is_err());
+        assert!(duration_parser("0m").is_err());
+        assert!(duration_parser("h").is_err());
+        assert!(duration_parser("1h 30m").is_err());
+        assert!(duration_parser("1.5h").is_err());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(size_parser("100"), Ok(100));
+        assert_eq!(size_parser("500K"), Ok(500 * 1024));
+        assert_eq!(size_parser("2M"), Ok(2 * 1024 * 1024));
+        assert_eq!(size_parser("2mb"), Ok(2 * 1024 * 1024));
+        assert_eq!(size_parser("1.5MiB"), Ok(1024 * 1024 * 3 / 2));
+        assert_eq!(size_parser("1G"), Ok(1024 * 1024 * 1024));
+        assert!(size_parser("0").is_err());
+        assert!(size_parser("-1M").is_err());
+        assert!(size_parser("fast").is_err());
+    }
+
+    #[test]
+    fn parses_start_times() {
+        let now = Local
+            .from_local_datetime(
+                &chrono::NaiveDate::from_ymd_opt(2024, 1, 15)
+                    .unwrap()
+                    .and_hms_opt(12, 0, 0)
+                    .unwrap(),
+            )
+            .earliest()
+            .unwrap();
+        let local = |s: &str| start_at(s, now).map(|x| x.naive_local().to_string());
+
+        assert_eq!(
+            start_at("+30m", now),
+            Ok(now + chrono::Duration::minutes(30))
+        );
+        assert_eq!(
+            start_at("2024-05-01T20:00:00+02:00", now).unwrap(),
+            DateTime::parse_from_rfc3339("2024-05-01T18:00:00Z").unwrap()
+        );
+
+        // Next occurrence of time is used.
+        assert_eq!(local("20:00"), Ok("2024-01-15 20:00:00".to_owned()));
+        assert_eq!(local("08:00:30"), Ok("2024-01-16 08:00:30".to_owned()));
+        assert_eq!(local("12:00"), Ok("2024-01-16 12:00:00".to_owned()));
+
+        assert!(start_at("+0m", now).is_err());
+        assert!(start_at("tomorrow", now).is_err());
+    }
+}
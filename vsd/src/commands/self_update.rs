@@ -0,0 +1,296 @@
+use anyhow::{anyhow, bail, Result};
+use clap::Args;
+use kdam::term::Colorizer;
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Ordering,
+    env::consts::EXE_SUFFIX,
+    io::{Cursor, Read, Write},
+    path::Path,
+};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/clitic/vsd/releases/latest";
+
+/// Update vsd to latest release from GitHub.
+#[derive(Debug, Clone, Args)]
+pub struct SelfUpdate {
+    /// Only check whether a newer release is available.
+    #[arg(long)]
+    check: bool,
+
+    /// Install latest release even if it is not newer than current version.
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<Asset>,
+    html_url: String,
+    tag_name: String,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    browser_download_url: String,
+    name: String,
+}
+
+impl SelfUpdate {
+    pub fn execute(self) -> Result<()> {
+        let client = Client::builder()
+            .user_agent(concat!("vsd/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let release = client
+            .get(LATEST_RELEASE_URL)
+            .send()?
+            .error_for_status()?
+            .json::<Release>()?;
+        let version = release.tag_name.trim_start_matches('v');
+        let current_version = env!("CARGO_PKG_VERSION");
+
+        if !self.force && !is_newer(version, current_version) {
+            eprintln!(
+                " {} vsd {} is the latest release",
+                "Up-to-date".colorize("bold green"),
+                current_version
+            );
+            return Ok(());
+        }
+
+        eprintln!(
+            "  {} vsd {} (current {})",
+            "Available".colorize("bold green"),
+            version,
+            current_version
+        );
+
+        if self.check {
+            return Ok(());
+        }
+
+        let asset = release_asset(&release, version)?;
+        eprintln!(
+            "{} {}",
+            "Downloading".colorize("bold green"),
+            asset.browser_download_url
+        );
+        let archive = client
+            .get(&asset.browser_download_url)
+            .send()?
+            .error_for_status()?
+            .bytes()?;
+
+        let expected_checksum = release_checksum(&client, &release, &asset.name)?;
+        let checksum = hex::encode(Sha256::digest(&archive));
+
+        if checksum != expected_checksum {
+            bail!(
+                "sha256 checksum of {} doesn't match (expected {} but got {}).",
+                asset.name,
+                expected_checksum,
+                checksum
+            );
+        }
+
+        eprintln!(
+            "   {} sha256 {}",
+            "Verified".colorize("bold green"),
+            checksum
+        );
+
+        let binary = extract_binary(&asset.name, &archive)?;
+        let current_exe = std::env::current_exe()?;
+        // Binary is written next to executable with a random name, so that it can't be replaced
+        // by other users in between. It is deleted once dropped.
+        let mut temp_file = tempfile::Builder::new()
+            .prefix(".vsd-")
+            .suffix(EXE_SUFFIX)
+            .tempfile_in(current_exe.parent().unwrap_or(Path::new(".")))?;
+        temp_file.write_all(&binary)?;
+        temp_file.flush()?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(temp_file.path(), std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        // Running executable cannot be overwritten on windows, so it is moved aside first.
+        self_replace::self_replace(temp_file.path())?;
+        eprintln!("    {} vsd {}", "Updated".colorize("bold green"), version);
+        Ok(())
+    }
+}
+
+/// Compare versions by semver precedence, eg. 0.3.10 is newer than 0.3.2 and 0.4.0 is newer
+/// than 0.4.0-beta.1. Build metadata (+...) is ignored.
+fn is_newer(version: &str, current_version: &str) -> bool {
+    let parse = |x: &str| {
+        let x = x.split('+').next().unwrap();
+        let (core, pre_release) = x.split_once('-').unwrap_or((x, ""));
+        let core = core
+            .split('.')
+            .map(|x| x.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>();
+        (core, pre_release.to_owned())
+    };
+
+    let (core, pre_release) = parse(version);
+    let (current_core, current_pre_release) = parse(current_version);
+
+    match core.cmp(&current_core) {
+        Ordering::Equal => match (pre_release.is_empty(), current_pre_release.is_empty()) {
+            (true, false) => true,
+            (false, false) => {
+                compare_pre_releases(&pre_release, &current_pre_release) == Ordering::Greater
+            }
+            _ => false,
+        },
+        x => x == Ordering::Greater,
+    }
+}
+
+/// Compare dot separated pre-release identifiers, numeric ones have lower precedence than
+/// alphanumeric ones, eg. alpha < alpha.1 < beta < beta.2 < beta.11 < rc.1.
+fn compare_pre_releases(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('.');
+    let mut b = b.split('.');
+
+    loop {
+        let ordering = match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => x.cmp(y),
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Archive built for current platform, linux gnu builds fallback to musl archives.
+fn release_asset<'a>(release: &'a Release, version: &str) -> Result<&'a Asset> {
+    let target = env!("VSD_TARGET");
+    let targets = [target.to_owned(), target.replace("-gnu", "-musl")];
+
+    targets
+        .iter()
+        .flat_map(|target| {
+            [
+                format!("vsd-{}-{}.tar.xz", version, target),
+                format!("vsd-{}-{}.zip", version, target),
+            ]
+        })
+        .find_map(|name| release.assets.iter().find(|x| x.name == name))
+        .ok_or_else(|| {
+            anyhow!(
+                "release {} doesn't contain prebuilt binary for {}, see {}",
+                version,
+                target,
+                release.html_url
+            )
+        })
+}
+
+/// Expected sha256 checksum of asset, read either from `{name}.sha256` asset or a checksums file.
+fn release_checksum(client: &Client, release: &Release, name: &str) -> Result<String> {
+    let sha256_name = format!("{}.sha256", name);
+
+    for asset in &release.assets {
+        let is_sha256_file = asset.name == sha256_name;
+        let is_checksums_file = ["sha256sums", "sha256sums.txt", "checksums.txt"]
+            .contains(&asset.name.to_lowercase().as_str());
+
+        if !is_sha256_file && !is_checksums_file {
+            continue;
+        }
+
+        let text = client
+            .get(&asset.browser_download_url)
+            .send()?
+            .error_for_status()?
+            .text()?;
+
+        // Lines are in `{checksum}  {name}` format, name is optional in `{name}.sha256` files.
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+
+            if let Some(checksum) = parts.next() {
+                let file = parts.next().map(|x| x.trim_start_matches('*'));
+
+                if file == Some(name) || (is_sha256_file && file.is_none()) {
+                    return Ok(checksum.to_lowercase());
+                }
+            }
+        }
+    }
+
+    bail!(
+        "release doesn't provide sha256 checksum of {}, download it manually from {}",
+        name,
+        release.html_url
+    )
+}
+
+/// Read vsd binary from .tar.xz or .zip archive.
+fn extract_binary(name: &str, archive: &[u8]) -> Result<Vec<u8>> {
+    let binary_name = format!("vsd{}", EXE_SUFFIX);
+    let is_binary = |path: &Path| path.file_name().map(|x| x == binary_name.as_str()) == Some(true);
+    let mut binary = vec![];
+
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(archive))?;
+
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+
+            if file.enclosed_name().as_deref().is_some_and(is_binary) {
+                file.read_to_end(&mut binary)?;
+                return Ok(binary);
+            }
+        }
+    } else {
+        let mut archive = tar::Archive::new(xz2::read::XzDecoder::new(archive));
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+
+            if is_binary(&entry.path()?) {
+                entry.read_to_end(&mut binary)?;
+                return Ok(binary);
+            }
+        }
+    }
+
+    bail!("{} doesn't contain {} binary.", name, binary_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("0.3.10", "0.3.2"));
+        assert!(is_newer("1.0.0", "0.9.9"));
+        assert!(!is_newer("0.3.3", "0.3.3"));
+        assert!(!is_newer("0.3.2", "0.3.10"));
+        assert!(is_newer("0.4.0", "0.4.0-beta.1"));
+        assert!(!is_newer("0.4.0-beta.1", "0.4.0"));
+        assert!(is_newer("0.4.0-beta.1", "0.3.3"));
+        assert!(is_newer("0.4.0-beta.11", "0.4.0-beta.2"));
+        assert!(is_newer("0.4.0-rc.1", "0.4.0-beta.2"));
+        assert!(is_newer("0.4.0-beta.1", "0.4.0-beta"));
+        assert!(!is_newer("0.4.0+build.5", "0.4.0"));
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use clap::Command;
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// Default values of `save` subcommand flags loaded from `~/.config/vsd/config.toml`.
+/// Keys are same as long flag names, flags passed on command line take precedence.
+///
+/// ```toml
+/// directory = "/tmp/vsd"
+/// ffmpeg-path = "/usr/local/bin/ffmpeg"
+/// prefer-audio-lang = "en"
+/// proxy = "socks5://127.0.0.1:9050"
+/// quality = "1080p"
+/// threads = 8
+///
+/// [header]
+/// Referer = "https://example.com"
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(crate) struct Config {
+    directory: Option<String>,
+    ffmpeg_args: Option<String>,
+    ffmpeg_path: Option<String>,
+    header: BTreeMap<String, String>,
+    prefer_audio_lang: Option<String>,
+    prefer_subs_lang: Option<String>,
+    proxy: Option<String>,
+    quality: Option<String>,
+    retry_count: Option<u8>,
+    threads: Option<u8>,
+    user_agent: Option<String>,
+}
+
+impl Config {
+    /// Path of config file, `$XDG_CONFIG_HOME/vsd/config.toml` or `~/.config/vsd/config.toml`.
+    /// `VSD_CONFIG` environment variable can be used to change this path.
+    fn path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("VSD_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        Self::directory().map(|x| x.join("config.toml"))
+    }
+
+    /// Directory of vsd config files, `$XDG_CONFIG_HOME/vsd` or `~/.config/vsd`.
+    pub(crate) fn directory() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .or_else(|| std::env::var_os("USERPROFILE"))
+                    .map(|x| PathBuf::from(x).join(".config"))
+            })
+            .map(|x| x.join("vsd"))
+    }
+
+    /// Load config file, an empty config is returned if it doesn't exist.
+    pub(crate) fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+
+        toml::from_str(&std::fs::read_to_string(&path)?).map_err(|x| {
+            anyhow!(
+                "couldn't parse config file {} (failed with {}).",
+                path.to_string_lossy(),
+                x.message()
+            )
+        })
+    }
+
+    /// Set config values as default values of `save` subcommand flags.
+    pub(crate) fn apply(&self, command: Command) -> Command {
+        let defaults = [
+            ("directory", self.directory.clone()),
+            ("ffmpeg_args", self.ffmpeg_args.clone()),
+            ("ffmpeg_path", self.ffmpeg_path.clone()),
+            ("prefer_audio_lang", self.prefer_audio_lang.clone()),
+            ("prefer_subs_lang", self.prefer_subs_lang.clone()),
+            ("proxy", self.proxy.clone()),
+            ("quality", self.quality.clone()),
+            ("retry_count", self.retry_count.map(|x| x.to_string())),
+            ("threads", self.threads.map(|x| x.to_string())),
+            ("user_agent", self.user_agent.clone()),
+        ];
+
+        command.mut_subcommand("save", |mut save| {
+            for (id, value) in defaults {
+                if let Some(value) = value {
+                    save = save.mut_arg(id, |arg| arg.default_value(value));
+                }
+            }
+
+            save
+        })
+    }
+
+    /// Headers in same format as `--header` flag values, headers passed on command line
+    /// should be placed after these so that they are preferred.
+    pub(crate) fn headers(&self) -> Vec<String> {
+        self.header
+            .iter()
+            .flat_map(|(key, value)| [key.to_owned(), value.to_owned()])
+            .collect()
+    }
+}
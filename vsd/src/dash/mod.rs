@@ -1,8 +0,0 @@
-mod locator;
-mod playlist;
-mod template;
-
-use locator::DashUrl;
-use template::Template;
-
-pub(crate) use playlist::{parse_as_master, push_segments};
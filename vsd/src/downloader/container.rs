@@ -0,0 +1,97 @@
+use crate::{commands::Format, downloader::SelectedPlaylists};
+use anyhow::{bail, Result};
+
+const CONTAINER_EXTENSIONS: [&str; 7] = ["m4a", "m4v", "mkv", "mov", "mp4", "ts", "webm"];
+
+/// Add extension of format to output path, or fail if output path already has a different
+/// container extension.
+pub fn container_output(output: &str, format: &Format) -> Result<String> {
+    let ext = format.extension();
+
+    if output.ends_with(&format!(".{}", ext)) {
+        return Ok(output.to_owned());
+    }
+
+    if let Some((_, x)) = output.rsplit_once('.') {
+        if CONTAINER_EXTENSIONS.contains(&x.to_lowercase().as_str()) {
+            bail!("--output extension .{} doesn't match --format {}.", x, ext);
+        }
+    }
+
+    Ok(format!("{}.{}", output, ext))
+}
+
+/// Check whether all selected streams can be stored in container of given format.
+/// Streams with unknown codecs are assumed to be compatible.
+pub fn check_container(format: &Format, selected_playlists: &SelectedPlaylists) -> Result<()> {
+    let (video_audio_streams, subtitle_streams) = selected_playlists;
+
+    let mut codecs = vec![];
+
+    for stream in video_audio_streams {
+        if let Some(x) = &stream.codecs {
+            // Hls variant streams can list codecs of both video and audio tracks.
+            codecs.extend(x.split(',').map(|x| x.trim().to_owned()));
+        }
+    }
+
+    if let Some(codec) = codecs.iter().find(|x| !supports(format, x)) {
+        bail!(
+            "{} codec cannot be stored in {} container, use {} instead.",
+            codec,
+            format.extension(),
+            suggest(format, &codecs, !subtitle_streams.is_empty())
+        );
+    }
+
+    if !subtitle_streams.is_empty() && *format != Format::Mkv {
+        let codec = subtitle_streams[0]
+            .codecs
+            .clone()
+            .unwrap_or_else(|| "subtitle".to_owned());
+
+        bail!(
+            "{} streams cannot be stored in {} container without losing them or their styling, \
+            use {} instead or skip subtitle streams.",
+            codec,
+            format.extension(),
+            suggest(format, &codecs, true)
+        );
+    }
+
+    Ok(())
+}
+
+fn supports(format: &Format, codec: &str) -> bool {
+    let fourcc = codec.split('.').next().unwrap_or(codec).to_lowercase();
+
+    match format {
+        Format::Mkv => true,
+        Format::Mp4 => !matches!(fourcc.as_str(), "vp8" | "theora" | "vorbis"),
+        Format::Ts => !matches!(
+            fourcc.as_str(),
+            "av01"
+                | "ec-3"
+                | "flac"
+                | "opus"
+                | "theora"
+                | "vorbis"
+                | "vp08"
+                | "vp09"
+                | "vp8"
+                | "vp9"
+        ),
+    }
+}
+
+fn suggest(format: &Format, codecs: &[String], subtitles: bool) -> String {
+    let format = [Format::Mp4, Format::Mkv, Format::Ts]
+        .into_iter()
+        .filter(|x| x != format)
+        .find(|x| {
+            (!subtitles || *x == Format::Mkv) && codecs.iter().all(|codec| supports(x, codec))
+        })
+        .unwrap_or(Format::Mkv);
+
+    format!("--format {}", format.extension())
+}
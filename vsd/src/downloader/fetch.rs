@@ -1,19 +1,23 @@
-use crate::{downloader::Prompts, playlist::PlaylistType};
+use crate::{downloader::Prompts, logger::warning};
 use anyhow::{anyhow, bail, Result};
 use kdam::term::Colorizer;
 use regex::Regex;
 use reqwest::{blocking::Client, header, Url};
 use std::{collections::HashSet, io::Write, path::Path};
+use vsd_core::playlist::PlaylistType;
 
 pub struct InputMetadata {
     pub pl_type: Option<PlaylistType>,
     pub text: String,
+    pub title: Option<String>,
     pub url: Url,
 }
 
 impl InputMetadata {
     fn fetch(&mut self, client: &Client) -> Result<()> {
+        log::debug!("fetching playlist {}", self.url);
         let response = client.get(self.url.as_ref()).send()?;
+        log::debug!("{} {}", response.status(), response.url());
         self.url = response.url().to_owned();
 
         if let Some(content_type) = response.headers().get(header::CONTENT_TYPE) {
@@ -33,6 +37,30 @@ impl InputMetadata {
         Ok(())
     }
 
+    /// Title of media, which is the scraped website title, DASH program title
+    /// or file name from playlist url.
+    pub fn media_title(&self) -> Option<String> {
+        if let Some(title) = &self.title {
+            return Some(title.to_owned());
+        }
+
+        if let Some(PlaylistType::Dash) = self.pl_type {
+            let re = Regex::new(r"(?s)<ProgramInformation.*?<Title>(.*?)</Title>").unwrap();
+
+            if let Some(title) = re.captures(&self.text).map(|x| x[1].trim().to_owned()) {
+                if !title.is_empty() {
+                    return Some(title);
+                }
+            }
+        }
+
+        self.url
+            .path_segments()
+            .and_then(|mut x| x.next_back())
+            .map(|x| x.split('.').next().unwrap_or(x).to_owned())
+            .filter(|x| !x.is_empty())
+    }
+
     fn update_pl_type_from_text(&mut self) {
         if self.pl_type.is_none() {
             if self.text.contains("<MPD") {
@@ -53,6 +81,7 @@ pub fn fetch_playlist(
     let mut meta = InputMetadata {
         pl_type: None,
         text: String::new(),
+        title: None,
         url: base_url
             .clone()
             .unwrap_or_else(|| "https://example.com".parse::<Url>().unwrap()),
@@ -61,10 +90,7 @@ pub fn fetch_playlist(
 
     if path.exists() {
         if base_url.is_none() {
-            println!(
-                "    {} base url is not set",
-                "Warning".colorize("bold yellow")
-            );
+            warning!("base url is not set");
         }
 
         if let Some(ext) = path.extension() {
@@ -80,7 +106,6 @@ pub fn fetch_playlist(
         meta.update_pl_type_from_text();
     } else {
         meta.url = input.parse::<Url>().unwrap();
-        // TODO - We can add site specific parsers here
         meta.fetch(&client)?;
 
         if meta.pl_type.is_none() {
@@ -92,41 +117,46 @@ pub fn fetch_playlist(
 }
 
 fn fetch_from_website(client: &Client, meta: &mut InputMetadata, prompts: &Prompts) -> Result<()> {
-    println!(
+    eprintln!(
         "   {} website for DASH and HLS playlists",
         "Scraping".colorize("bold cyan")
     );
 
     let links = scrape_playlist_links(&meta.text);
+    meta.title = Regex::new(r"(?is)<title[^>]*>(.*?)</title>")
+        .unwrap()
+        .captures(&meta.text)
+        .map(|x| x[1].trim().to_owned())
+        .filter(|x| !x.is_empty());
 
     match links.len() {
         0 => bail!("No playlists were found in website source."),
         1 => {
-            println!("      {} {}", "Found".colorize("bold green"), &links[0]);
+            eprintln!("      {} {}", "Found".colorize("bold green"), &links[0]);
             meta.url = links[0].parse::<Url>()?;
         }
         _ => {
             if prompts.skip || prompts.raw {
-                println!("Select one playlist:");
+                eprintln!("Select one playlist:");
 
                 for (i, link) in links.iter().enumerate() {
-                    println!("{:2}) [{}] {}", i + 1, if i == 0 { 'x' } else { ' ' }, link);
+                    eprintln!("{:2}) [{}] {}", i + 1, if i == 0 { 'x' } else { ' ' }, link);
                 }
 
-                println!("------------------------------");
+                eprintln!("------------------------------");
 
                 let mut index = 0;
 
                 if prompts.raw && !prompts.skip {
-                    print!(
+                    eprint!(
                         "Press enter to proceed with defaults.\n\
                     Or select playlist to download (1, 2, etc.): "
                     );
-                    std::io::stdout().flush()?;
+                    std::io::stderr().flush()?;
                     let mut input = String::new();
                     std::io::stdin().read_line(&mut input)?;
 
-                    println!("------------------------------");
+                    eprintln!("------------------------------");
 
                     let input = input.trim();
 
@@ -142,7 +172,7 @@ fn fetch_from_website(client: &Client, meta: &mut InputMetadata, prompts: &Promp
                     .get(index)
                     .ok_or_else(|| anyhow!("selected playlist is out of index bounds."))?
                     .parse::<Url>()?;
-                println!("   {} {}", "Selected".colorize("bold green"), meta.url);
+                eprintln!("   {} {}", "Selected".colorize("bold green"), meta.url);
             } else {
                 let question = requestty::Question::select("scraped-link")
                     .message("Select one playlist")
@@ -0,0 +1,1010 @@
+use super::{
+    restream::{self, Restream},
+    subtitle::LiveSubtitles,
+};
+use crate::{events, exit::ExitCode, utils};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local, Utc};
+use kdam::{term::Colorizer, BarExt, Column, RichProgress};
+use reqwest::{blocking::Client, header, Url};
+use serde_json::json;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Once,
+    },
+    time::{Duration, Instant},
+};
+use vsd_core::{
+    dash, hls,
+    playlist::{Key, KeyMethod, Map, MediaPlaylist, MediaType, Segment},
+    Downloader, Merger, Progress, SegmentEvent,
+};
+
+/// Live playlists are never refreshed more often than this.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum delay between retries of refreshing a live playlist.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Options for recording live streams.
+#[derive(Clone)]
+pub(crate) struct LiveOptions {
+    /// Stop recording once this much media is recorded.
+    pub(crate) duration: Option<Duration>,
+    /// Start recording from oldest segment still available in playlist instead of live edge.
+    pub(crate) from_start: bool,
+    /// Number of segments before live edge from which recording starts.
+    pub(crate) live_edge: usize,
+    /// Keep retrying to refresh playlist for this long before giving up.
+    pub(crate) live_retry_window: Duration,
+    /// Keep only this much of most recently recorded media on disk.
+    pub(crate) max_duration: Option<Duration>,
+    /// Keep only this many bytes of most recently recorded media on disk.
+    pub(crate) max_size: Option<u64>,
+    /// Also write a rolling local hls playlist of recording inside this directory.
+    pub(crate) restream_dir: Option<PathBuf>,
+    /// Serve restream directory over http on this port.
+    pub(crate) restream_port: Option<u16>,
+    /// Start recording from segment which was live at this wall clock time.
+    pub(crate) start_pdt: Option<DateTime<Local>>,
+}
+
+/// Record live dash and hls streams into their files. Each stream is recorded by its own thread
+/// which refreshes media playlist once every target duration and downloads segments added to it.
+/// Cues of subtitle streams are merged while recording and written once recording stops.
+/// Recording is stopped gracefully on Ctrl-C, so that recorded streams can still be muxed.
+pub(super) fn record(
+    base_url: Option<&Url>,
+    client: &Client,
+    downloader: &Downloader,
+    options: &LiveOptions,
+    pb: Arc<Mutex<RichProgress>>,
+    streams: &[(MediaPlaylist, String)],
+    subtitle_streams: &[(MediaPlaylist, String)],
+) -> Result<()> {
+    {
+        let mut pb = pb.lock().unwrap();
+        pb.columns = vec![
+            Column::Text("[bold blue]?".to_owned()),
+            Column::Text("•".to_owned()),
+            Column::Text("[cyan]00:00:00".to_owned()),
+            Column::Text("•".to_owned()),
+            Column::ElapsedTime,
+            Column::Text("•".to_owned()),
+            Column::Rate,
+        ];
+        pb.pb.reset(None);
+    }
+
+    let restream = match &options.restream_dir {
+        Some(directory) => {
+            let restream = Restream::new(directory, streams)?;
+
+            if let Some(port) = options.restream_port {
+                restream::serve(directory, port)?;
+            }
+
+            Some(restream)
+        }
+        None => None,
+    };
+
+    let stop = AtomicBool::new(false);
+    // Shared by recorder of every stream and its thread.
+    let stop = &stop;
+    let stored = Arc::new(AtomicUsize::new(0));
+
+    handle_interrupts();
+    RECORDING.fetch_add(1, Ordering::SeqCst);
+
+    let result = std::thread::scope(|scope| {
+        let handles = streams
+            .iter()
+            .chain(subtitle_streams)
+            .enumerate()
+            .map(|(i, (stream, path))| {
+                let recorder = Recorder {
+                    base_url,
+                    client,
+                    downloader: downloader.clone().progress(Arc::new(LiveProgress {
+                        pb: pb.clone(),
+                        stored: stored.clone(),
+                        stream: i,
+                    })),
+                    index: i,
+                    options,
+                    path,
+                    pb: pb.clone(),
+                    restream: restream.as_ref(),
+                    stop,
+                    stream,
+                };
+
+                scope.spawn(move || {
+                    let result = recorder.record();
+
+                    // Other streams are stopped too, so that they have same length.
+                    if result.is_err() {
+                        stop.store(true, Ordering::SeqCst);
+                    }
+
+                    result
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle
+                .join()
+                .map_err(|_| anyhow!("live recording thread panicked."))??;
+        }
+
+        Ok(())
+    });
+
+    // Interruption only stops recordings in progress, later ones (eg. from a batch file) still run.
+    if RECORDING.fetch_sub(1, Ordering::SeqCst) == 1 {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+    }
+
+    if let Some(restream) = restream {
+        restream.finish()?;
+    }
+
+    result
+}
+
+/// Number of live recordings in progress.
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+/// Whether Ctrl-C was pressed while recording live streams, reset once no recording is left.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Handle Ctrl-C by stopping live recordings instead of exiting, so that segments which are
+/// being downloaded are written and recorded streams are muxed into a playable file.
+/// Pressing Ctrl-C again or when nothing is being recorded exits immediately.
+fn handle_interrupts() {
+    static HANDLER: Once = Once::new();
+
+    HANDLER.call_once(|| {
+        let result = ctrlc::set_handler(|| {
+            if RECORDING.load(Ordering::SeqCst) == 0 || INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(ExitCode::Aborted as i32);
+            }
+
+            log::info!("stopping live recording (interrupted)");
+
+            if events::enabled() {
+                events::emit("interrupt", json!({}));
+            } else {
+                eprintln!(
+                    "\n   {} recording, press Ctrl-C again to exit immediately",
+                    "Stopping".colorize("bold yellow")
+                );
+            }
+        });
+
+        if let Err(e) = result {
+            log::warn!("couldn't set Ctrl-C handler ({})", e);
+        }
+    });
+}
+
+struct Recorder<'a> {
+    base_url: Option<&'a Url>,
+    client: &'a Client,
+    downloader: Downloader,
+    index: usize,
+    options: &'a LiveOptions,
+    path: &'a str,
+    pb: Arc<Mutex<RichProgress>>,
+    restream: Option<&'a Restream>,
+    stop: &'a AtomicBool,
+    stream: &'a MediaPlaylist,
+}
+
+/// Live playlist after refreshing it.
+struct Window {
+    /// Whether stream has ended and no more segments will be added to it.
+    ended: bool,
+    playlist: MediaPlaylist,
+    /// Media sequence number of first segment, only known for hls playlists.
+    sequence: Option<u64>,
+    /// Time after which playlist should be refreshed again.
+    target_duration: Duration,
+}
+
+impl Recorder<'_> {
+    fn record(&self) -> Result<()> {
+        if self.stream.media_type == MediaType::Subtitles {
+            let mut subtitles = LiveSubtitles::new(self.stream);
+            let result = self.record_into(&mut None, Some(&mut subtitles));
+
+            // Merged cues are kept even if recording failed.
+            subtitles.finish(self.path)?;
+            return result;
+        }
+
+        let mut buffer = RollingBuffer::new(self.options, self.path);
+        let result = self.record_into(&mut buffer, None);
+
+        // Buffered segments are kept even if recording failed.
+        if let Some(buffer) = buffer {
+            buffer.finish()?;
+        }
+
+        result
+    }
+
+    fn record_into(
+        &self,
+        buffer: &mut Option<RollingBuffer>,
+        mut subtitles: Option<&mut LiveSubtitles>,
+    ) -> Result<()> {
+        // Keys and init segments are only present on segments where they change,
+        // so they are carried over to first segment of every batch.
+        let mut key: Option<Key> = None;
+        let mut map: Option<Map> = None;
+        let mut written_map: Option<String> = None;
+        let mut history = History::default();
+        let mut last_sequence: Option<u64> = None;
+        let mut recorded = 0.0;
+
+        while !self.stopped() {
+            let fetched_at = Instant::now();
+            let mut window = match self.refresh_with_retries()? {
+                Some(window) => window,
+                None => break,
+            };
+            let length = window.playlist.segments.len();
+            // Segments before live edge are skipped on first refresh.
+            let start = if last_sequence.is_some()
+                || !history.is_empty()
+                || self.options.from_start
+                || self.options.start_pdt.is_some()
+            {
+                0
+            } else {
+                length.saturating_sub(self.options.live_edge)
+            };
+
+            if let (Some(last), Some(sequence)) = (last_sequence, window.sequence) {
+                let window_last = (sequence + length as u64).saturating_sub(1);
+
+                // Window doesn't overlap with recorded segments even after going back in time,
+                // which happens when packager is restarted and segment names can be reused.
+                if length > 0 && window_last + (length as u64) < last {
+                    self.warn(format!(
+                        "media sequence of playlist went back from {} to {}, assuming stream was restarted",
+                        last, window_last
+                    ));
+                    history.clear();
+                } else if sequence > last + 1 {
+                    self.warn(format!(
+                        "{} segments were removed from playlist before they could be recorded",
+                        sequence - last - 1
+                    ));
+                }
+            }
+
+            // Subtitle streams are recorded from same time as other streams, even without
+            // program date times.
+            if let Some(start_pdt) = self
+                .options
+                .start_pdt
+                .filter(|_| history.is_empty() && subtitles.is_none())
+            {
+                self.check_start_pdt(&window.playlist, start_pdt)?;
+            }
+
+            // Every chunk of rolling buffer starts with init segment.
+            let chunk = buffer.as_mut().map(|x| x.chunk());
+
+            if chunk.as_ref().is_some_and(|x| x.1) {
+                written_map = None;
+            }
+
+            if let Some(sequence) = window.sequence.filter(|_| length > 0) {
+                last_sequence = Some(sequence + length as u64 - 1);
+            }
+
+            let mut segments = vec![];
+
+            for (i, mut segment) in std::mem::take(&mut window.playlist.segments)
+                .into_iter()
+                .enumerate()
+            {
+                if let Some(x) = &segment.map {
+                    map = Some(x.clone());
+                }
+
+                if let Some(x) = &segment.key {
+                    key = Some(x.clone());
+                }
+
+                // Segments are identified by their url, so that they are never written twice
+                // even if playlist window shifts irregularly or it is renumbered.
+                if !history.insert(&segment) || i < start {
+                    continue;
+                }
+
+                // Segments which ended before requested program date time are skipped.
+                if let (Some(start_pdt), Some(program_date_time)) =
+                    (self.options.start_pdt, segment.program_date_time)
+                {
+                    if program_date_time + (segment.duration as f64)
+                        <= start_pdt.timestamp_millis() as f64 / 1000.0
+                    {
+                        continue;
+                    }
+                }
+
+                if segments.is_empty() && segment.key.is_none() {
+                    segment.key = key.clone();
+                }
+
+                // Init segment is written again only when it changes, but encrypted
+                // segments always need it for decryption.
+                let encrypted = key
+                    .as_ref()
+                    .is_some_and(|x| matches!(x.method, KeyMethod::Aes128 | KeyMethod::Cenc));
+
+                if segments.is_empty() && segment.map.is_none() {
+                    segment.map = map.clone();
+                }
+
+                if let Some(x) = &segment.map {
+                    if !encrypted && written_map.as_deref() == Some(&x.uri) {
+                        segment.map = None;
+                    } else {
+                        written_map = Some(x.uri.clone());
+                    }
+                }
+
+                segments.push(segment);
+            }
+
+            let mut finished = false;
+
+            if let Some(duration) = self.options.duration {
+                let duration = duration.as_secs_f64();
+                let mut total = recorded;
+                let count = segments
+                    .iter()
+                    .take_while(|x| {
+                        let take = total < duration;
+                        total += x.duration as f64;
+                        take
+                    })
+                    .count();
+
+                segments.truncate(count);
+                finished = total >= duration;
+            }
+
+            let count = segments.len();
+
+            if count > 0 {
+                let duration = segments.iter().map(|x| x.duration as f64).sum::<f64>();
+                recorded += duration;
+                window.playlist.segments = segments;
+
+                if let Some(subtitles) = subtitles.as_deref_mut() {
+                    // Recording isn't stopped if some subtitles couldn't be fetched or parsed.
+                    if let Err(e) = self.download_subtitles(&window.playlist, subtitles) {
+                        self.warn(format!("couldn't record subtitles ({})", e));
+                    }
+                } else {
+                    let path = chunk.as_ref().map(|x| x.0.as_str()).unwrap_or(self.path);
+                    let size = self.download(&window.playlist, path)?;
+
+                    if let Some(buffer) = buffer {
+                        buffer.written(duration, size as u64)?;
+                    }
+
+                    // Recording isn't stopped if restreaming fails.
+                    if let Some(restream) = self.restream {
+                        if let Err(e) = restream.push(self.index, path, size, duration) {
+                            self.warn(format!("couldn't update restream playlist ({})", e));
+                        }
+                    }
+                }
+
+                self.recorded(recorded, count);
+            }
+
+            if finished {
+                break;
+            }
+
+            if window.ended {
+                log::info!("live stream {} has ended", self.stream.uri);
+                break;
+            }
+
+            // Playlist is reloaded after target duration, or half of it when it didn't change.
+            let wait = if count == 0 {
+                window.target_duration / 2
+            } else {
+                window.target_duration
+            };
+            self.sleep(wait.saturating_sub(fetched_at.elapsed()));
+        }
+
+        Ok(())
+    }
+
+    /// Refresh playlist, retrying with an exponential backoff when it temporarily fails
+    /// (eg. HTTP 404/5xx or a dropped connection). `None` is returned if recording is stopped
+    /// meanwhile.
+    fn refresh_with_retries(&self) -> Result<Option<Window>> {
+        let mut delay = Duration::from_secs(1);
+        let mut failed_at: Option<Instant> = None;
+
+        loop {
+            match self.refresh() {
+                Ok(window) => {
+                    if let Some(failed_at) = failed_at {
+                        log::info!(
+                            "live playlist {} recovered after {:.1}s",
+                            self.stream.uri,
+                            failed_at.elapsed().as_secs_f64()
+                        );
+                    }
+
+                    return Ok(Some(window));
+                }
+                Err(e) => {
+                    let failed_at = *failed_at.get_or_insert_with(Instant::now);
+
+                    if failed_at.elapsed() >= self.options.live_retry_window {
+                        return Err(e);
+                    }
+
+                    self.warn(format!(
+                        "couldn't refresh live playlist ({}), retrying in {}s",
+                        e.to_string().lines().next().unwrap_or_default(),
+                        delay.as_secs()
+                    ));
+                    self.sleep(delay);
+
+                    if self.stopped() {
+                        return Ok(None);
+                    }
+
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+
+    fn refresh(&self) -> Result<Window> {
+        log::debug!("refreshing live playlist {}", self.stream.uri);
+        let response = self.client.get(&self.stream.uri).send()?;
+        log::debug!("{} {}", response.status(), response.url());
+        let text = response.error_for_status()?.text()?;
+        let mut playlist = MediaPlaylist {
+            segments: vec![],
+            ..self.stream.clone()
+        };
+
+        if self.stream.is_hls() {
+            let m3u8 = m3u8_rs::parse_media_playlist_res(text.as_bytes()).map_err(|x| {
+                anyhow!(
+                    "couldn't parse response as hls playlist (failed with {}).\n\n{}\n\n{}",
+                    x,
+                    self.stream.uri,
+                    text
+                )
+            })?;
+            hls::push_segments(&m3u8, &mut playlist);
+
+            return Ok(Window {
+                ended: m3u8.end_list,
+                playlist,
+                sequence: Some(m3u8.media_sequence),
+                target_duration: Duration::from_secs_f64(m3u8.target_duration as f64)
+                    .max(MIN_REFRESH_INTERVAL),
+            });
+        }
+
+        let mpd = dash_mpd::parse(&text).map_err(|x| {
+            anyhow!(
+                "couldn't parse response as dash playlist (failed with {}).\n\n{}",
+                x,
+                text
+            )
+        })?;
+
+        // Segments of dash streams are located using their locator instead of manifest url.
+        playlist.uri = self
+            .stream
+            .locator
+            .clone()
+            .ok_or_else(|| anyhow!("couldn't locate live stream inside dash manifest."))?;
+        dash::push_segments(
+            &mpd,
+            &mut playlist,
+            self.base_url
+                .map(|x| x.as_str())
+                .unwrap_or(&self.stream.uri),
+        )?;
+        playlist.uri = self.stream.uri.clone();
+
+        let target_duration = mpd
+            .minimumUpdatePeriod
+            .filter(|x| !x.is_zero())
+            .unwrap_or_else(|| {
+                Duration::from_secs_f32(
+                    playlist
+                        .segments
+                        .iter()
+                        .map(|x| x.duration)
+                        .fold(0.0, f32::max),
+                )
+            });
+
+        // Manifest becomes static once live stream ends, availability window can also end
+        // without manifest being updated.
+        let ended = mpd.mpdtype.as_deref() != Some("dynamic")
+            || mpd.availabilityEndTime.is_some_and(|x| x <= Utc::now());
+
+        Ok(Window {
+            ended,
+            playlist,
+            sequence: None,
+            target_duration: target_duration.max(MIN_REFRESH_INTERVAL),
+        })
+    }
+
+    /// Download segments of playlist at end of file, returns size of downloaded segments.
+    fn download(&self, playlist: &MediaPlaylist, path: &str) -> Result<usize> {
+        let merger = Arc::new(Mutex::new(Merger::append(playlist.segments.len(), path)?));
+        self.downloader
+            .download(self.downloader.segments(playlist, self.base_url, &merger)?)?;
+        super::check_merger(&merger, self.stream, path)
+    }
+
+    /// Download segments of subtitles playlist and merge their cues.
+    fn download_subtitles(
+        &self,
+        playlist: &MediaPlaylist,
+        subtitles: &mut LiveSubtitles,
+    ) -> Result<()> {
+        let stream_base_url = self
+            .base_url
+            .cloned()
+            .unwrap_or(playlist.uri.parse::<Url>()?);
+
+        for segment in &playlist.segments {
+            if let Some(map) = &segment.map {
+                let url = stream_base_url.join(&map.uri)?;
+                log::debug!("fetching live subtitles init segment {}", url);
+                let mut request = self.client.get(url);
+
+                if let Some(range) = &map.range {
+                    request = request.header(header::RANGE, range.as_header_value());
+                }
+
+                subtitles.init(request.send()?.error_for_status()?.bytes()?.to_vec());
+            }
+
+            let url = stream_base_url.join(&segment.uri)?;
+            log::debug!("fetching live subtitles segment {}", url);
+            let mut request = self.client.get(url);
+
+            if let Some(range) = &segment.range {
+                request = request.header(header::RANGE, range.as_header_value());
+            }
+
+            subtitles.push(&request.send()?.error_for_status()?.bytes()?)?;
+        }
+
+        Ok(())
+    }
+
+    fn recorded(&self, recorded: f64, segments: usize) {
+        log::debug!(
+            "recorded {} segments of stream {} ({:.1}s)",
+            segments,
+            self.index,
+            recorded
+        );
+        events::emit(
+            "live",
+            json!({ "recorded": recorded, "segments": segments, "stream": self.index }),
+        );
+
+        // Recorded duration of first stream is shown.
+        if self.index == 0 {
+            self.pb.lock().unwrap().replace(
+                2,
+                Column::Text(format!("[cyan]{}", utils::format_duration(recorded))),
+            );
+        }
+    }
+
+    /// Check whether `--start-pdt` can be used with playlist window.
+    fn check_start_pdt(&self, playlist: &MediaPlaylist, start_pdt: DateTime<Local>) -> Result<()> {
+        let oldest = match playlist.segments.first() {
+            Some(segment) => segment.program_date_time,
+            None => return Ok(()),
+        };
+
+        match oldest {
+            Some(oldest) if oldest > start_pdt.timestamp_millis() as f64 / 1000.0 => {
+                self.warn(format!(
+                    "{} is no longer available in playlist, recording from {}",
+                    start_pdt.to_rfc3339(),
+                    DateTime::<Utc>::from_timestamp_millis((oldest * 1000.0) as i64)
+                        .map(|x| x.with_timezone(&Local).to_rfc3339())
+                        .unwrap_or_default()
+                ));
+                Ok(())
+            }
+            Some(_) => Ok(()),
+            None => bail!(
+                "{} cannot be used as {} stream doesn't have program date times.",
+                "--start-pdt".colorize("bold green"),
+                self.stream.display_stream().colorize("cyan"),
+            ),
+        }
+    }
+
+    fn warn(&self, message: String) {
+        log::warn!("{}", message);
+        let _ = self.pb.lock().unwrap().write(format!(
+            "    {} {}",
+            "Warning".colorize("bold yellow"),
+            message
+        ));
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst) || INTERRUPTED.load(Ordering::SeqCst)
+    }
+
+    /// Sleep in small steps, so that recording can be stopped meanwhile.
+    fn sleep(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+
+        while !self.stopped() {
+            let remaining = until.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                break;
+            }
+
+            std::thread::sleep(remaining.min(Duration::from_millis(200)));
+        }
+    }
+}
+
+/// Progress of live streams, total size is unknown so only recorded size is shown.
+struct LiveProgress {
+    pb: Arc<Mutex<RichProgress>>,
+    /// Size of segments recorded across all streams.
+    stored: Arc<AtomicUsize>,
+    stream: usize,
+}
+
+impl Progress for LiveProgress {
+    fn key(&self, kid: &str, key: &str) {
+        if events::enabled() {
+            events::emit("key", json!({ "kid": kid, "stream": self.stream }));
+            return;
+        }
+
+        let _ = self.pb.lock().unwrap().write(format!(
+            "        {} {}:{}",
+            "Key".colorize("bold green"),
+            kid,
+            key
+        ));
+    }
+
+    fn retry(&self, url: &str, reason: &str) {
+        if events::enabled() {
+            events::emit(
+                "retry",
+                json!({ "reason": reason, "stream": self.stream, "url": url }),
+            );
+            return;
+        }
+
+        let _ = self.pb.lock().unwrap().write(format!(
+            "    {} {} ({})",
+            "Request".colorize("bold yellow"),
+            url,
+            reason
+        ));
+    }
+
+    fn segment(&self, event: &SegmentEvent) {
+        let stored = self.stored.fetch_add(event.bytes, Ordering::SeqCst) + event.bytes;
+
+        if events::enabled() {
+            events::emit(
+                "segment",
+                json!({
+                    "bytes": event.bytes,
+                    "downloaded_bytes": stored,
+                    "index": event.index,
+                    "stream": self.stream,
+                }),
+            );
+            return;
+        }
+
+        let mut pb = self.pb.lock().unwrap();
+        pb.replace(
+            0,
+            Column::Text(format!("[bold blue]{}", utils::format_bytes(stored, 2).2)),
+        );
+        let _ = pb.update(1);
+    }
+}
+
+/// Segments which were already recorded (or skipped), only most recent ones are remembered.
+#[derive(Default)]
+struct History {
+    order: VecDeque<String>,
+    seen: HashSet<String>,
+}
+
+impl History {
+    const CAPACITY: usize = 10_000;
+
+    fn clear(&mut self) {
+        self.order.clear();
+        self.seen.clear();
+    }
+
+    fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Remember segment, returns false if it was already seen.
+    fn insert(&mut self, segment: &Segment) -> bool {
+        let id = match &segment.range {
+            Some(range) => format!("{} {}-{}", segment.uri, range.start, range.end),
+            None => segment.uri.clone(),
+        };
+
+        if !self.seen.insert(id.clone()) {
+            return false;
+        }
+
+        self.order.push_back(id);
+
+        if self.order.len() > Self::CAPACITY {
+            if let Some(id) = self.order.pop_front() {
+                self.seen.remove(&id);
+            }
+        }
+
+        true
+    }
+}
+
+/// Rolling buffer of a live stream on disk, so that recorders left running for days don't fill
+/// the disk. Segments are written into chunk files next to stream file and oldest chunks are
+/// deleted once buffer exceeds `--max-duration` or `--max-size`. Remaining chunks are joined
+/// into stream file once recording stops.
+struct RollingBuffer<'a> {
+    chunks: VecDeque<Chunk>,
+    count: usize,
+    options: &'a LiveOptions,
+    path: &'a str,
+}
+
+struct Chunk {
+    duration: f64,
+    path: String,
+    size: u64,
+}
+
+impl<'a> RollingBuffer<'a> {
+    /// Create buffer for stream file, `None` is returned if there are no limits.
+    fn new(options: &'a LiveOptions, path: &'a str) -> Option<Self> {
+        if options.max_duration.is_none() && options.max_size.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            chunks: VecDeque::new(),
+            count: 0,
+            options,
+            path,
+        })
+    }
+
+    /// Path of chunk where next segments should be written and whether it is a new chunk.
+    /// Each chunk holds about a tenth of buffer, so that buffer stays close to its limits.
+    fn chunk(&mut self) -> (String, bool) {
+        let full = self.chunks.back().is_none_or(|x| {
+            self.options
+                .max_duration
+                .is_some_and(|y| x.duration >= y.as_secs_f64() / 10.0)
+                || self.options.max_size.is_some_and(|y| x.size >= y / 10)
+        });
+
+        if full {
+            self.count += 1;
+            self.chunks.push_back(Chunk {
+                duration: 0.0,
+                path: format!("{}.part{}", self.path, self.count),
+                size: 0,
+            });
+        }
+
+        (self.chunks.back().unwrap().path.clone(), full)
+    }
+
+    /// Account segments written into last chunk and delete oldest chunks exceeding limits.
+    fn written(&mut self, duration: f64, size: u64) -> Result<()> {
+        if let Some(chunk) = self.chunks.back_mut() {
+            chunk.duration += duration;
+            chunk.size += size;
+        }
+
+        while self.chunks.len() > 1 {
+            let duration = self.chunks.iter().map(|x| x.duration).sum::<f64>();
+            let size = self.chunks.iter().map(|x| x.size).sum::<u64>();
+            let exceeded = self
+                .options
+                .max_duration
+                .is_some_and(|x| duration > x.as_secs_f64())
+                || self.options.max_size.is_some_and(|x| size > x);
+
+            if !exceeded {
+                break;
+            }
+
+            let chunk = self.chunks.pop_front().unwrap();
+            log::debug!("deleting buffered chunk {}", chunk.path);
+            std::fs::remove_file(&chunk.path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Join remaining chunks into stream file. Every chunk starts with init segment of fragmented
+    /// mp4 streams, which is only written again if it is changed.
+    fn finish(self) -> Result<()> {
+        let mut file = File::create(self.path)?;
+        let mut last_init = vec![];
+
+        for chunk in self.chunks {
+            if Path::new(&chunk.path).exists() {
+                let mut chunk_file = File::open(&chunk.path)?;
+                let init = init_segment(&mut chunk_file)?;
+
+                if !init.is_empty() && init == last_init {
+                    chunk_file.seek(SeekFrom::Start(init.len() as u64))?;
+                } else {
+                    chunk_file.rewind()?;
+                }
+
+                std::io::copy(&mut chunk_file, &mut file)?;
+                std::fs::remove_file(&chunk.path)?;
+
+                if !init.is_empty() {
+                    last_init = init;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read leading `ftyp` and `moov` boxes of fragmented mp4 file.
+/// Files which aren't fragmented mp4 (eg. mpeg-ts) don't have an init segment.
+fn init_segment(file: &mut File) -> Result<Vec<u8>> {
+    let mut init = vec![];
+    let mut header = [0; 8];
+
+    while file.read_exact(&mut header).is_ok() {
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+
+        if !matches!(&header[4..], b"ftyp" | b"moov") || size < 8 {
+            break;
+        }
+
+        init.extend_from_slice(&header);
+
+        if (&mut *file).take(size - 8).read_to_end(&mut init)? as u64 != size - 8 {
+            bail!("init segment of live chunk is truncated");
+        }
+    }
+
+    Ok(init)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsd_core::playlist::Range;
+
+    fn segment(uri: &str, range: Option<(u64, u64)>) -> Segment {
+        Segment {
+            range: range.map(|(start, end)| Range { start, end }),
+            uri: uri.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn history_skips_seen_segments() {
+        let mut history = History::default();
+        assert!(history.is_empty());
+        assert!(history.insert(&segment("a.ts", None)));
+        assert!(!history.insert(&segment("a.ts", None)));
+        assert!(history.insert(&segment("b.mp4", Some((0, 99)))));
+        assert!(history.insert(&segment("b.mp4", Some((100, 199)))));
+        assert!(!history.insert(&segment("b.mp4", Some((0, 99)))));
+        assert!(history.insert(&segment("b.mp4", None)));
+
+        history.clear();
+        assert!(history.is_empty());
+        assert!(history.insert(&segment("a.ts", None)));
+    }
+
+    #[test]
+    fn history_forgets_oldest_segments() {
+        let mut history = History::default();
+
+        for i in 0..=History::CAPACITY {
+            assert!(history.insert(&segment(&format!("{}.ts", i), None)));
+        }
+
+        assert_eq!(history.order.len(), History::CAPACITY);
+        assert!(history.insert(&segment("0.ts", None)));
+        assert!(!history.insert(&segment(&format!("{}.ts", History::CAPACITY), None)));
+    }
+
+    #[test]
+    fn rolling_buffer_writes_init_segment_once() {
+        let options = LiveOptions {
+            duration: None,
+            from_start: false,
+            live_edge: 3,
+            live_retry_window: Duration::from_secs(30),
+            max_duration: None,
+            max_size: Some(200),
+            restream_dir: None,
+            restream_port: None,
+            start_pdt: None,
+        };
+        let directory = std::env::temp_dir().join(format!("vsd-rolling-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("stream.mp4").to_string_lossy().to_string();
+        let mut buffer = RollingBuffer::new(&options, &path).unwrap();
+
+        let ftyp = [&[0, 0, 0, 12][..], b"ftyp", b"isom"].concat();
+        let moov = [&[0, 0, 0, 8][..], b"moov"].concat();
+        let moof = |x: u8| [&[0, 0, 0, 9][..], b"moof", &[x]].concat();
+
+        for data in [
+            [ftyp.as_slice(), &moov, &moof(0)].concat(),
+            [ftyp.as_slice(), &moov, &moof(1)].concat(),
+        ] {
+            let (chunk, new) = buffer.chunk();
+            assert!(new);
+            std::fs::write(chunk, &data).unwrap();
+            buffer.written(1.0, data.len() as u64).unwrap();
+        }
+
+        buffer.finish().unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            [ftyp.as_slice(), &moov, &moof(0), &moof(1)].concat()
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,29 +1,49 @@
+mod container;
 mod fetch;
+mod live;
 mod parse;
+mod restream;
+mod select;
+mod separate;
+mod split;
 mod subtitle;
+mod template;
 
+pub use container::{check_container, container_output};
 pub use fetch::{fetch_playlist, InputMetadata};
+pub(crate) use live::LiveOptions;
 pub use parse::{parse_all_streams, parse_selected_streams};
+pub use select::Selection;
+pub use split::{numbered_output, split_playlists};
 pub use subtitle::download_subtitle_streams;
+pub use template::format_output;
 
 use crate::{
-    merger::Merger,
-    playlist::{KeyMethod, MediaPlaylist, MediaType, Range, Segment},
+    commands::Codec,
+    events::{self, JsonProgress},
+    exit::ExitCode,
+    ffmpeg::Ffmpeg,
+    logger::warning,
+    mux::{self, Chapter, Metadata},
     utils,
 };
 use anyhow::{anyhow, bail, Result};
 use kdam::{term::Colorizer, tqdm, BarExt, Column, RichProgress};
-use reqwest::{
-    blocking::{Client, RequestBuilder},
-    header, StatusCode, Url,
-};
+use reqwest::{blocking::Client, header, Url};
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::HashSet,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
     time::Instant,
 };
+use vsd_core::{
+    playlist::{KeyMethod, MediaPlaylist, MediaType},
+    Downloader, Limiter, Merger, Progress, SegmentEvent,
+};
 use vsd_mp4::pssh::Pssh;
 
 pub type SelectedPlaylists = (Vec<MediaPlaylist>, Vec<MediaPlaylist>);
@@ -33,8 +53,10 @@ pub struct Prompts {
     pub raw: bool,
 }
 
+#[derive(Clone)]
 pub struct Stream {
     pub file_path: String,
+    pub forced: bool,
     pub language: Option<String>,
     pub media_type: MediaType,
 }
@@ -42,21 +64,80 @@ pub struct Stream {
 #[allow(clippy::too_many_arguments)]
 pub(crate) fn download(
     all_keys: bool,
+    bar_position: u16,
     base_url: Option<Url>,
     client: Client,
     directory: Option<PathBuf>,
+    ffmpeg: Ffmpeg,
     keys: Vec<(Option<String>, String)>,
+    limiter: Option<Arc<Limiter>>,
+    live: &LiveOptions,
     no_decrypt: bool,
     no_merge: bool,
+    no_mux: bool,
     output: Option<String>,
+    progressive: bool,
     selected_playlists: SelectedPlaylists,
     retry_count: u8,
+    subs_codec: Option<Codec>,
     threads: u8,
+    title: Option<String>,
 ) -> Result<()> {
-    let (mut video_audio_streams, subtitle_streams) = selected_playlists;
+    let (video_audio_streams, mut subtitle_streams) = selected_playlists;
+    let to_stdout = output.as_deref() == Some("-");
+
+    if to_stdout {
+        if video_audio_streams.len() != 1 {
+            bail!("exactly one video/audio stream should be selected when writing to stdout.");
+        }
+
+        if no_merge {
+            bail!("--no-merge cannot be used when writing to stdout.");
+        }
+
+        if no_mux {
+            bail!("--no-mux cannot be used when writing to stdout.");
+        }
+
+        if !subtitle_streams.is_empty() {
+            warning!("subtitle streams are skipped when writing to stdout");
+            subtitle_streams.clear();
+        }
+    }
+
+    if no_mux && (no_decrypt || no_merge || progressive) {
+        bail!("--no-mux cannot be used with --no-decrypt, --no-merge or --progressive.");
+    }
+
+    if video_audio_streams
+        .iter()
+        .chain(&subtitle_streams)
+        .any(|x| x.live)
+    {
+        if no_merge || progressive {
+            bail!("--no-merge and --progressive cannot be used when recording live streams.");
+        }
+
+        if to_stdout
+            && (live.max_duration.is_some()
+                || live.max_size.is_some()
+                || live.restream_dir.is_some())
+        {
+            bail!(
+                "--max-duration, --max-size and --restream-dir cannot be used when writing to stdout."
+            );
+        }
+    }
+
+    // Tracks are saved separately as clean files, subtitles default to srt.
+    let subs_codec = if no_mux {
+        subs_codec.or(Some(Codec::Subrip))
+    } else {
+        subs_codec
+    };
 
     let one_stream = (video_audio_streams.len() == 1) && subtitle_streams.is_empty();
-    let mut should_mux = !no_decrypt && !no_merge;
+    let mut should_mux = !no_decrypt && !no_merge && !no_mux;
 
     if let Some(output) = &output {
         if one_stream
@@ -74,12 +155,29 @@ pub(crate) fn download(
         .filter(|x| x.media_type == MediaType::Video)
         .count();
 
-    if video_streams_count > 1 {
+    if video_streams_count > 1 || to_stdout {
         should_mux = false;
     }
 
-    if should_mux && utils::find_ffmpeg().is_none() {
-        bail!("ffmpeg couldn't be found, it is required to continue further.");
+    // Fragmented mp4 and mpeg-ts streams can be muxed into matroska and mp4 (without subtitles)
+    // containers without ffmpeg.
+    let native_mux = output
+        .as_ref()
+        .map(|x| x.ends_with(".mkv") || (x.ends_with(".mp4") && subtitle_streams.is_empty()))
+        .unwrap_or(false);
+
+    if progressive && should_mux && !native_mux {
+        bail!(
+            "{} requires output to be a .mkv file or a .mp4 file without subtitles.",
+            "--progressive".colorize("bold green")
+        );
+    }
+
+    // Output file is written directly when it is same as the only stream being downloaded.
+    let progressive = progressive && should_mux;
+
+    if should_mux && !native_mux {
+        ffmpeg.preflight().map_err(|e| ExitCode::Mux.tag(e))?;
     }
 
     // -----------------------------------------------------------------------------------------
@@ -93,10 +191,10 @@ pub(crate) fn download(
             if let Some(key) = &segment.key {
                 if !no_decrypt {
                     match &key.method {
-                        KeyMethod::Other(x) => bail!("{} decryption is not supported. Use {} flag to download encrypted streams.", x, "--no-decrypt".colorize("bold green")),
+                        KeyMethod::Other(x) => return Err(ExitCode::Drm.tag(anyhow!("{} decryption is not supported. Use {} flag to download encrypted streams.", x, "--no-decrypt".colorize("bold green")))),
                         KeyMethod::SampleAes => {
                             if stream.is_hls() {
-                                bail!("sample-aes (HLS) decryption is not supported. Use {} flag to download encrypted streams.", "--no-decrypt".colorize("bold green"));
+                                return Err(ExitCode::Drm.tag(anyhow!("sample-aes (HLS) decryption is not supported. Use {} flag to download encrypted streams.", "--no-decrypt".colorize("bold green"))));
                             }
                         }
                         _ => (),
@@ -120,6 +218,7 @@ pub(crate) fn download(
         if let Some(segment) = stream.segments.get(0) {
             if let Some(map) = &segment.map {
                 let url = stream_base_url.join(&map.uri)?;
+                log::debug!("fetching init segment {}", url);
                 let mut request = client.get(url);
 
                 if let Some(range) = &map.range {
@@ -132,7 +231,7 @@ pub(crate) fn download(
                 for key_id in pssh.key_ids {
                     if !kids.contains(&key_id.value) {
                         kids.insert(key_id.value.clone());
-                        println!(
+                        eprintln!(
                             "      {} {} {} ({})",
                             "KeyId".colorize("bold green"),
                             if default_kids.contains(&key_id.value) {
@@ -156,10 +255,10 @@ pub(crate) fn download(
             .any(|x| x == default_kid)
             && !no_decrypt
         {
-            bail!(
+            return Err(ExitCode::Drm.tag(anyhow!(
                 "use {} flag to specify CENC content decryption keys for at least * (star) prefixed key ids.",
                 "--key".colorize("bold green")
-            );
+            )));
         }
     }
 
@@ -168,7 +267,12 @@ pub(crate) fn download(
     // -----------------------------------------------------------------------------------------
 
     let mut pb = RichProgress::new(
-        tqdm!(unit = " SEG".to_owned(), dynamic_ncols = true),
+        tqdm!(
+            unit = " SEG".to_owned(),
+            dynamic_ncols = true,
+            disable = events::enabled(),
+            position = bar_position
+        ),
         vec![
             Column::Text("[bold blue]?".to_owned()),
             Column::Animation,
@@ -194,7 +298,7 @@ pub(crate) fn download(
         }
     }
 
-    if output.is_some() {
+    if output.is_some() && !to_stdout && !no_mux {
         let video_streams_count = video_audio_streams
             .iter()
             .filter(|x| x.media_type == MediaType::Video)
@@ -205,10 +309,7 @@ pub(crate) fn download(
             .count();
 
         if video_streams_count > 1 {
-            println!(
-                "    {} --output is ignored when multiple video streams are selected",
-                "Warning".colorize("bold yellow")
-            );
+            warning!("--output is ignored when multiple video streams are selected");
         }
 
         if video_streams_count == 0
@@ -216,126 +317,66 @@ pub(crate) fn download(
                 || subtitle_streams.len() > 1
                 || (audio_streams_count != 0 && !subtitle_streams.is_empty()))
         {
-            println!(
-                "    {} --output is ignored when no video streams is selected but multiple audio/subtitle streams are selected",
-                "Warning".colorize("bold yellow")
+            warning!(
+                "--output is ignored when no video streams is selected but multiple audio/subtitle streams are selected"
             );
         }
 
         if no_decrypt {
-            println!(
-                "    {} --output is ignored when --no-decrypt is used",
-                "Warning".colorize("bold yellow")
-            );
+            warning!("--output is ignored when --no-decrypt is used");
         }
 
         if no_merge {
-            println!(
-                "    {} --output is ignored when --no-merge is used",
-                "Warning".colorize("bold yellow")
-            );
+            warning!("--output is ignored when --no-merge is used");
         }
     }
 
     if !subtitle_streams.is_empty() && no_merge {
-        println!(
-            "    {} subtitle streams are always merged even if --no-merge is used",
-            "Warning".colorize("bold yellow")
-        );
+        warning!("subtitle streams are always merged even if --no-merge is used");
     }
 
-    let mut temp_files = vec![];
-
-    // -----------------------------------------------------------------------------------------
-    // Download Subtitle Streams
-    // -----------------------------------------------------------------------------------------
+    let metadata = Metadata {
+        // Chapter marks are taken from first video stream or first stream if there isn't any.
+        chapters: video_audio_streams
+            .iter()
+            .find(|x| x.media_type == MediaType::Video)
+            .or(video_audio_streams.first())
+            .map(|x| mux::chapters(&x.segments))
+            .unwrap_or_default(),
+        title,
+    };
 
-    download_subtitle_streams(
-        base_url.clone(),
-        &client,
-        &directory,
-        &subtitle_streams,
-        &mut pb,
-        &mut temp_files,
-    )?;
+    let mut temp_files = vec![];
 
     // -----------------------------------------------------------------------------------------
     // Estimation
     // -----------------------------------------------------------------------------------------
 
-    let mut downloaded_bytes = 0;
-    let mut relative_sizes = VecDeque::new();
-
-    for stream in video_audio_streams.iter_mut() {
-        let stream_base_url = base_url
-            .clone()
-            .unwrap_or(stream.uri.parse::<Url>().unwrap());
-
-        let total_segments = stream.segments.len();
-        let buffer_size = 1024 * 1024 * 2; // 2 MiB
-        let mut ranges = None;
-
-        if let Some(segment) = stream.segments.get(0) {
-            let url = stream_base_url.join(&segment.uri)?;
-            let mut request = client.head(url.clone());
-
-            if total_segments == 1 {
-                let response = request.send()?;
-                let content_length = response
-                    .headers()
-                    .get(header::CONTENT_LENGTH)
-                    .map(|x| x.to_str().unwrap().parse::<usize>().unwrap())
-                    .unwrap_or(0);
-
-                if content_length == 0 {
-                    bail!(
-                        "cannot download a single segment ({}) of unknown content length.",
-                        url
-                    );
-                } else {
-                    ranges = Some(PartialRangeIter {
-                        start: 0,
-                        end: content_length as u64 - 1,
-                        buffer_size,
-                    });
-                    relative_sizes.push_back(content_length);
-                }
-            } else {
-                if let Some(range) = &segment.range {
-                    request = request.header(header::RANGE, range.as_header_value());
-                }
-
-                let response = request.send()?;
-                let content_length = response
-                    .headers()
-                    .get(header::CONTENT_LENGTH)
-                    .map(|x| x.to_str().unwrap().parse::<usize>().unwrap())
-                    .unwrap_or(0);
-
-                relative_sizes.push_back(total_segments * content_length);
-            }
-        }
+    // Live streams are recorded once rest of the streams are downloaded.
+    let (live_streams, mut video_audio_streams): (Vec<_>, Vec<_>) =
+        video_audio_streams.into_iter().partition(|x| x.live);
+    let (live_subtitle_streams, subtitle_streams): (Vec<_>, Vec<_>) =
+        subtitle_streams.into_iter().partition(|x| x.live);
+
+    let mut downloader = Downloader::new(client.clone())
+        .all_keys(all_keys)
+        .decrypt(!no_decrypt)
+        .keys(keys)
+        .retries(retry_count)
+        .threads(threads);
+
+    if let Some(limiter) = limiter {
+        downloader = downloader.limiter(limiter);
+    }
 
-        if let Some(ranges) = ranges {
-            let segment = stream.segments.remove(0);
+    let mut estimates = vec![];
 
-            for (i, range) in ranges.enumerate() {
-                if i == 0 {
-                    let mut segment_copy = segment.clone();
-                    segment_copy.range = Some(range);
-                    stream.segments.push(segment_copy);
-                } else {
-                    stream.segments.push(Segment {
-                        range: Some(range),
-                        duration: segment.duration,
-                        uri: segment.uri.clone(),
-                        ..Default::default()
-                    });
-                }
-            }
-        }
+    for stream in video_audio_streams.iter_mut() {
+        estimates.push(downloader.estimate(stream, base_url.as_ref())?);
     }
 
+    let sizes = Arc::new(StreamSizes::new(estimates));
+
     // -----------------------------------------------------------------------------------------
     // Prepare Progress Bar
     // -----------------------------------------------------------------------------------------
@@ -346,20 +387,69 @@ pub(crate) fn download(
         Column::Text("[yellow]?".to_owned()),
     ]);
     pb.pb.reset(Some(
-        video_audio_streams.iter().map(|x| x.segments.len()).sum(),
+        video_audio_streams
+            .iter()
+            .chain(&subtitle_streams)
+            .map(|x| x.segments.len())
+            .sum(),
     ));
     let pb = Arc::new(Mutex::new(pb));
 
     // -----------------------------------------------------------------------------------------
-    // Download Video & Audio Streams
+    // Download Subtitle Streams
+    // -----------------------------------------------------------------------------------------
+
+    events::stage("downloading");
+
+    // Subtitle streams are small and fetched one segment at a time,
+    // so they are downloaded in background while video and audio streams are downloaded.
+    let subtitles = {
+        let base_url = base_url.clone();
+        let client = client.clone();
+        let directory = directory.clone();
+        let pb = pb.clone();
+        let subs_codec = subs_codec.clone();
+
+        std::thread::spawn(move || -> Result<Vec<Stream>> {
+            let mut temp_files = vec![];
+            download_subtitle_streams(
+                base_url,
+                &client,
+                &directory,
+                &subtitle_streams,
+                &subs_codec,
+                &pb,
+                &mut temp_files,
+            )?;
+            Ok(temp_files)
+        })
+    };
+    let mut subtitles = Some(subtitles);
+
     // -----------------------------------------------------------------------------------------
+    // Prepare Video & Audio Streams
+    // -----------------------------------------------------------------------------------------
+
+    let temp_file = |stream: &MediaPlaylist| {
+        if let Some(output) = &output {
+            if to_stdout
+                || (!no_mux && one_stream && output.ends_with(&format!(".{}", stream.extension())))
+            {
+                return output.to_owned();
+            }
+        }
+
+        stream
+            .file_path(&directory, &stream.extension())
+            .to_string_lossy()
+            .to_string()
+    };
 
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(threads as usize)
-        .build()
-        .unwrap();
+    // Streams are downloaded together, sharing --threads and progress bar.
+    let mut streams = vec![];
+    let timer = Instant::now();
 
-    for stream in video_audio_streams {
+    for (i, stream) in video_audio_streams.into_iter().enumerate() {
         pb.lock().unwrap().write(format!(
             " {} {} stream {}",
             "Processing".colorize("bold green"),
@@ -370,6 +460,7 @@ pub(crate) fn download(
         let length = stream.segments.len();
 
         if length == 0 {
+            log::warn!("skipping stream (no segments)");
             pb.lock().unwrap().write(format!(
                 "    {} skipping stream (no segments)",
                 "Warning".colorize("bold yellow"),
@@ -377,19 +468,10 @@ pub(crate) fn download(
             continue;
         }
 
-        let mut temp_file = stream
-            .file_path(&directory, &stream.extension())
-            .to_string_lossy()
-            .to_string();
-
-        if let Some(output) = &output {
-            if one_stream && output.ends_with(&format!(".{}", stream.extension())) {
-                temp_file = output.to_owned();
-            }
-        }
-
+        let temp_file = temp_file(&stream);
         temp_files.push(Stream {
             file_path: temp_file.clone(),
+            forced: stream.forced,
             language: stream.language.clone(),
             media_type: stream.media_type.clone(),
         });
@@ -404,165 +486,194 @@ pub(crate) fn download(
         } else {
             Merger::new(stream.segments.len(), &temp_file)?
         }));
-        let timer = Arc::new(Instant::now());
+        events::emit(
+            "stream",
+            serde_json::json!({
+                "media_type": stream.media_type.to_string(),
+                "path": temp_file,
+                "stream": i,
+                "total_segments": stream.segments.len(),
+            }),
+        );
 
-        let _ = relative_sizes.pop_front();
-        let relative_size = relative_sizes.iter().sum();
-        let mut previous_map = None;
-        let mut previous_key = None;
+        let progress: Arc<dyn Progress> = if events::enabled() {
+            Arc::new(JsonProgress {
+                downloaded_segments: AtomicUsize::new(0),
+                sizes: sizes.clone(),
+                stream: i,
+                timer,
+                total_segments: stream.segments.len(),
+            })
+        } else {
+            Arc::new(StreamProgress {
+                pb: pb.clone(),
+                sizes: sizes.clone(),
+                stream: i,
+                timer,
+            })
+        };
+        let stream_downloader = downloader.clone().progress(progress);
+        let thread_datas = stream_downloader.segments(&stream, base_url.as_ref(), &merger)?;
+        streams.push((stream, temp_file, merger, thread_datas));
+    }
 
-        let stream_base_url = base_url
-            .clone()
-            .unwrap_or(stream.uri.parse::<Url>().unwrap());
+    // Segments of all streams are interleaved by their start time, so that streams are
+    // downloaded alongside each other and muxer doesn't have to wait for a whole stream.
+    let mut thread_datas = streams
+        .iter_mut()
+        .flat_map(|x| std::mem::take(&mut x.3))
+        .collect::<Vec<_>>();
+    thread_datas.sort_by(|x, y| x.start_time().total_cmp(&y.start_time()));
 
-        let mut thread_datas = Vec::with_capacity(stream.segments.len());
+    // -----------------------------------------------------------------------------------------
+    // Download (& Mux Progressively) Video & Audio Streams
+    // -----------------------------------------------------------------------------------------
 
-        for (i, segment) in stream.segments.iter().enumerate() {
-            if let Some(map) = &segment.map {
-                let url = stream_base_url.join(&map.uri)?;
-                let mut request = client.get(url);
+    let muxer = match &output {
+        Some(output) if progressive && !streams.is_empty() => {
+            // Muxer needs all streams, so subtitle streams are downloaded before it is started.
+            join_subtitles(&mut subtitles, &mut temp_files)?;
 
-                if let Some(range) = &map.range {
-                    request = request.header(header::RANGE, range.as_header_value());
-                }
+            let downloaded = Arc::new(AtomicBool::new(false));
+            let mux_streams = mux_order(&temp_files)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            let muxer = {
+                let downloaded = downloaded.clone();
+                let metadata = metadata.clone();
+                let output = output.to_owned();
 
-                let response = request.send()?;
-                let bytes = response.bytes()?;
-                previous_map = Some(bytes.to_vec())
-            }
+                std::thread::spawn(move || {
+                    let streams = mux_streams.iter().collect::<Vec<_>>();
 
-            if !no_decrypt {
-                if let Some(key) = &segment.key {
-                    match key.method {
-                        KeyMethod::Aes128 => {
-                            if !keys.is_empty() {
-                                bail!("custom keys with AES-128 encryption is not supported");
-                            }
+                    if output.ends_with(".mp4") {
+                        mux::mux_mp4(&streams, &output, &metadata, Some(downloaded))
+                    } else {
+                        mux::mux_matroska(&streams, &output, &metadata, Some(downloaded))
+                    }
+                })
+            };
 
-                            if let Some(uri) = &key.uri {
-                                previous_key = Some(Keys {
-                                    bytes: if key.key_format.is_none() {
-                                        let url = stream_base_url.join(uri)?;
-                                        let request = client.get(url);
-                                        let response = request.send()?;
-                                        response.bytes()?.to_vec()
-                                    } else {
-                                        vec![]
-                                    },
-                                    iv: key.iv.clone(),
-                                    method: key.method.clone(),
-                                });
-                            } else {
-                                bail!("uri cannot be none when key method is AES-128");
-                            }
-                        }
-                        KeyMethod::Cenc => {
-                            let default_kid = stream.default_kid();
-                            let mut decryption_keys = HashMap::new();
-
-                            if all_keys {
-                                for key in &keys {
-                                    if let Some(kid) = &key.0 {
-                                        decryption_keys.insert(kid.to_owned(), key.1.to_owned());
-                                    } else if let Some(default_kid) = &default_kid {
-                                        decryption_keys
-                                            .insert(default_kid.to_owned(), key.1.to_owned());
-                                    }
-                                }
-                            } else {
-                                for key in &keys {
-                                    if let Some(default_kid) = &default_kid {
-                                        if let Some(kid) = &key.0 {
-                                            if default_kid == kid {
-                                                decryption_keys
-                                                    .insert(kid.to_owned(), key.1.to_owned());
-                                            }
-                                        } else {
-                                            decryption_keys
-                                                .insert(default_kid.to_owned(), key.1.to_owned());
-                                        }
-                                    }
-                                }
-                            }
+            pb.lock().unwrap().write(format!(
+                "     {} {} while downloading",
+                "Muxing".colorize("bold cyan"),
+                output.colorize("cyan"),
+            ))?;
+            Some((muxer, downloaded))
+        }
+        _ => None,
+    };
 
-                            if decryption_keys.is_empty() {
-                                bail!(
-                                    "cannot determine keys to use, bypass this error using {} flag.",
-                                    "--all-keys".colorize("bold green")
-                                );
-                            }
+    let result = downloader.download(thread_datas).and_then(|_| {
+        for (stream, temp_file, merger, _) in &streams {
+            check_merger(merger, stream, temp_file)?;
+        }
 
-                            for key in &decryption_keys {
-                                pb.lock().unwrap().write(format!(
-                                    "        {} {}:{}",
-                                    "Key".colorize("bold green"),
-                                    key.0,
-                                    key.1
-                                ))?;
-                            }
+        Ok(())
+    });
 
-                            previous_key = Some(Keys::from_hex_keys(decryption_keys));
-                        }
-                        _ => previous_key = None,
-                    }
-                }
-            }
+    // Muxer waits for streams to grow until they are marked as downloaded, even if download failed.
+    if let Some((_, downloaded)) = &muxer {
+        downloaded.store(true, Ordering::SeqCst);
+    }
+
+    if let Err(e) = result {
+        if let Some((muxer, _)) = muxer {
+            let _ = muxer.join();
+        }
 
-            let url = stream_base_url.join(&segment.uri)?;
-            let mut request = client.get(url);
+        return Err(e);
+    }
 
-            if let Some(range) = &segment.range {
-                request = request.header(header::RANGE, range.as_header_value());
+    join_subtitles(&mut subtitles, &mut temp_files)?;
+    let mut progressive_muxed = false;
+
+    if let Some((muxer, _)) = muxer {
+        match muxer.join() {
+            Ok(Ok(_)) => progressive_muxed = true,
+            Ok(Err(e)) => {
+                log::warn!("progressive muxing failed ({})", e);
+                pb.lock().unwrap().write(format!(
+                    "    {} progressive muxing failed ({}), streams will be muxed again",
+                    "Warning".colorize("bold yellow"),
+                    e
+                ))?;
             }
+            Err(_) => return Err(ExitCode::Mux.tag(anyhow!("progressive muxer thread panicked."))),
+        }
+    }
 
-            let thread_data = ThreadData {
-                downloaded_bytes,
-                index: i,
-                keys: previous_key.clone(),
-                map: previous_map.clone(),
-                merger: merger.clone(),
-                pb: pb.clone(),
-                relative_size,
-                request,
-                timer: timer.clone(),
-                total_retries: retry_count,
-            };
+    if !streams.is_empty() {
+        pb.lock().unwrap().write(format!(
+            " {} streams successfully",
+            "Downloaded".colorize("bold green"),
+        ))?;
+    }
 
-            if previous_key.is_none() {
-                previous_map = None;
-            }
+    // -----------------------------------------------------------------------------------------
+    // Record Live Streams
+    // -----------------------------------------------------------------------------------------
 
-            thread_datas.push(thread_data);
-        }
+    if !live_streams.is_empty() || !live_subtitle_streams.is_empty() {
+        let mut streams = vec![];
+        let mut subtitle_streams = vec![];
 
-        pool.scope_fifo(|s| {
-            for thread_data in thread_datas {
-                s.spawn_fifo(move |_| {
-                    if let Err(e) = thread_data.execute() {
-                        let _lock = thread_data.pb.lock().unwrap();
-                        println!("\n{}: {}", "error".colorize("bold red"), e);
-                        std::process::exit(1);
-                    }
-                });
+        for stream in live_streams {
+            let temp_file = temp_file(&stream);
+            pb.lock().unwrap().write(format!(
+                "  {} {} stream {} to {}",
+                "Recording".colorize("bold green"),
+                stream.media_type,
+                stream.display_stream().colorize("cyan"),
+                temp_file.colorize("cyan"),
+            ))?;
+
+            // Segments are appended to stream files, so files of an earlier recording are cleared.
+            if !to_stdout {
+                std::fs::File::create(&temp_file)?;
             }
-        });
 
-        let mut merger = merger.lock().unwrap();
-        merger.flush()?;
+            temp_files.push(Stream {
+                file_path: temp_file.clone(),
+                forced: stream.forced,
+                language: stream.language.clone(),
+                media_type: stream.media_type.clone(),
+            });
+            streams.push((stream, temp_file));
+        }
 
-        if !merger.buffered() {
-            bail!(
-                "failed to download {} stream to {}",
+        for stream in live_subtitle_streams {
+            let temp_file = subtitle::live_subtitles_path(&directory, &stream, &subs_codec);
+            pb.lock().unwrap().write(format!(
+                "  {} {} stream {} to {}",
+                "Recording".colorize("bold green"),
+                stream.media_type,
                 stream.display_stream().colorize("cyan"),
-                temp_file
-            );
+                temp_file.colorize("cyan"),
+            ))?;
+            temp_files.push(Stream {
+                file_path: temp_file.clone(),
+                forced: stream.forced,
+                language: stream.language.clone(),
+                media_type: stream.media_type.clone(),
+            });
+            subtitle_streams.push((stream, temp_file));
         }
 
-        downloaded_bytes += merger.stored();
+        live::record(
+            base_url.as_ref(),
+            &client,
+            &downloader,
+            live,
+            pb.clone(),
+            &streams,
+            &subtitle_streams,
+        )?;
 
         pb.lock().unwrap().write(format!(
-            " {} stream successfully",
-            "Downloaded".colorize("bold green"),
+            "   {} streams successfully",
+            "Recorded".colorize("bold green"),
         ))?;
     }
 
@@ -586,116 +697,78 @@ pub(crate) fn download(
         .filter(|x| x.media_type == MediaType::Subtitles)
         .count();
 
+    if no_mux {
+        // Output path without extension is used as prefix of track files.
+        let prefix = output.as_ref().map(|x| {
+            Path::new(x)
+                .with_extension("")
+                .to_string_lossy()
+                .to_string()
+        });
+        events::stage("muxing");
+        separate::save_separately(&ffmpeg, &metadata, prefix.as_deref(), &temp_files)
+            .map_err(|e| ExitCode::Mux.tag(e))?;
+    }
+
     if should_mux
         && (video_streams_count == 1 || audio_streams_count == 1 || subtitle_streams_count == 1)
     {
         if let Some(output) = &output {
-            let all_temp_files = temp_files
-                .iter()
-                .filter(|x| {
-                    (x.media_type == MediaType::Video) || (x.media_type == MediaType::Undefined)
-                })
-                .chain(
-                    temp_files
-                        .iter()
-                        .filter(|x| x.media_type == MediaType::Audio),
-                )
-                .chain(
-                    temp_files
-                        .iter()
-                        .filter(|x| x.media_type == MediaType::Subtitles),
-                )
-                .collect::<Vec<_>>();
+            let all_temp_files = mux_order(&temp_files);
+            let mut muxed = progressive_muxed;
+            events::stage("muxing");
 
-            let mut args = vec![];
-
-            for temp_file in &all_temp_files {
-                args.extend_from_slice(&["-i".to_owned(), temp_file.file_path.clone()]);
+            if !muxed && Path::new(output).exists() {
+                eprintln!("   {} {}", "Deleting".colorize("bold red"), output);
+                std::fs::remove_file(output)?;
             }
 
-            if (video_streams_count == 1)
-                || (audio_streams_count == 1)
-                || (subtitle_streams_count == 1)
-            {
-                // TODO - Re-consider this copy
-                args.extend_from_slice(&["-c".to_owned(), "copy".to_owned()]);
-            } else {
-                args.extend_from_slice(&["-c".to_owned(), "copy".to_owned()]);
+            if !muxed && native_mux {
+                eprintln!("     {} {}", "Muxing".colorize("bold cyan"), output);
 
-                if subtitle_streams_count > 0 && output.ends_with(".mp4") {
-                    args.extend_from_slice(&["-c:s".to_owned(), "mov_text".to_owned()]);
-                }
-
-                for i in 0..all_temp_files.len() {
-                    args.extend_from_slice(&["-map".to_owned(), i.to_string()]);
-                }
-
-                let mut audio_index = 0;
-                let mut subtitle_index = 0;
-
-                for temp_file in &all_temp_files {
-                    match temp_file.media_type {
-                        MediaType::Audio => {
-                            if let Some(language) = &temp_file.language {
-                                args.extend_from_slice(&[
-                                    format!("-metadata:s:a:{}", audio_index),
-                                    format!("language={}", language),
-                                ]);
-                            }
+                let (container, result) = if output.ends_with(".mp4") {
+                    (
+                        "mp4",
+                        mux::mux_mp4(&all_temp_files, output, &metadata, None),
+                    )
+                } else {
+                    (
+                        "matroska",
+                        mux::mux_matroska(&all_temp_files, output, &metadata, None),
+                    )
+                };
 
-                            audio_index += 1;
+                match result {
+                    Ok(_) => muxed = true,
+                    Err(e) => {
+                        if Path::new(output).exists() {
+                            std::fs::remove_file(output)?;
                         }
-                        MediaType::Subtitles => {
-                            if let Some(language) = &temp_file.language {
-                                args.extend_from_slice(&[
-                                    format!("-metadata:s:s:{}", subtitle_index),
-                                    format!("language={}", language),
-                                ]);
-                            }
 
-                            subtitle_index += 1;
+                        if ffmpeg.binary().is_none() {
+                            return Err(ExitCode::Mux.tag(anyhow!(
+                                "built-in {} muxer failed ({}) and ffmpeg couldn't be found.",
+                                container,
+                                e
+                            )));
                         }
-                        _ => (),
-                    }
-                }
 
-                if subtitle_streams_count > 0 {
-                    args.extend_from_slice(&["-disposition:s:0".to_owned(), "default".to_owned()]);
+                        warning!(
+                            "built-in {} muxer failed ({}), falling back to ffmpeg",
+                            container,
+                            e
+                        );
+                    }
                 }
             }
 
-            args.push(output.to_owned());
-
-            println!(
-                "  {} ffmpeg {}",
-                "Executing".colorize("bold cyan"),
-                args.iter()
-                    .map(|x| if x.contains(' ') {
-                        format!("\"{}\"", x)
-                    } else {
-                        x.to_owned()
-                    })
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            );
-
-            if Path::new(output).exists() {
-                println!("   {} {}", "Deleting".colorize("bold red"), output);
-                std::fs::remove_file(output)?;
-            }
-
-            let code = Command::new("ffmpeg")
-                .args(args)
-                .stderr(Stdio::null())
-                .spawn()?
-                .wait()?;
-
-            if !code.success() {
-                bail!("ffmpeg exited with code {}", code.code().unwrap_or(1))
+            if !muxed {
+                mux_with_ffmpeg(&ffmpeg, &all_temp_files, output, &metadata)
+                    .map_err(|e| ExitCode::Mux.tag(e))?;
             }
 
             for temp_file in &all_temp_files {
-                println!(
+                eprintln!(
                     "   {} {}",
                     "Deleting".colorize("bold red"),
                     temp_file.file_path
@@ -705,7 +778,7 @@ pub(crate) fn download(
 
             if let Some(directory) = &directory {
                 if std::fs::read_dir(directory)?.next().is_none() {
-                    println!(
+                    eprintln!(
                         "   {} {}",
                         "Deleting".colorize("bold red"),
                         directory.to_string_lossy()
@@ -719,191 +792,281 @@ pub(crate) fn download(
     Ok(())
 }
 
-#[derive(Clone)]
-struct Keys {
-    bytes: Vec<u8>,
-    iv: Option<String>,
-    method: KeyMethod,
+/// Streams in the order they are muxed, video streams first followed by audio and subtitles.
+fn mux_order(temp_files: &[Stream]) -> Vec<&Stream> {
+    temp_files
+        .iter()
+        .filter(|x| (x.media_type == MediaType::Video) || (x.media_type == MediaType::Undefined))
+        .chain(
+            temp_files
+                .iter()
+                .filter(|x| x.media_type == MediaType::Audio),
+        )
+        .chain(
+            temp_files
+                .iter()
+                .filter(|x| x.media_type == MediaType::Subtitles),
+        )
+        .collect()
+}
+
+/// Wait for subtitle streams being downloaded in background,
+/// their files are kept before files of other streams.
+fn join_subtitles(
+    subtitles: &mut Option<JoinHandle<Result<Vec<Stream>>>>,
+    temp_files: &mut Vec<Stream>,
+) -> Result<()> {
+    if let Some(subtitles) = subtitles.take() {
+        let subtitle_files = subtitles
+            .join()
+            .map_err(|_| anyhow!("subtitles download thread panicked."))??;
+        temp_files.splice(0..0, subtitle_files);
+    }
+
+    Ok(())
 }
 
-impl Keys {
-    fn from_hex_keys(keys: HashMap<String, String>) -> Self {
-        let mut bytes = String::new();
+/// Flush remaining segments of stream and return its downloaded size.
+fn check_merger(
+    merger: &Arc<Mutex<Merger>>,
+    stream: &MediaPlaylist,
+    temp_file: &str,
+) -> Result<usize> {
+    let mut merger = merger.lock().unwrap();
+    merger.flush()?;
+
+    if !merger.buffered() {
+        bail!(
+            "failed to download {} stream to {}",
+            stream.display_stream().colorize("cyan"),
+            temp_file
+        );
+    }
+
+    Ok(merger.stored())
+}
+
+fn mux_with_ffmpeg(
+    ffmpeg: &Ffmpeg,
+    streams: &[&Stream],
+    output: &str,
+    metadata: &Metadata,
+) -> Result<()> {
+    let mut args = vec![];
+
+    for stream in streams {
+        args.extend_from_slice(&["-i".to_owned(), stream.file_path.clone()]);
+    }
+
+    // Chapters are passed as an extra ffmetadata input.
+    let metadata_file = if metadata.chapters.is_empty() {
+        None
+    } else {
+        let metadata_file = Path::new(&streams[0].file_path)
+            .with_extension("ffmetadata")
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(&metadata_file, ffmetadata(&metadata.chapters))?;
+        args.extend_from_slice(&["-i".to_owned(), metadata_file.clone()]);
+        Some(metadata_file)
+    };
 
-        for (kid, key) in keys {
-            bytes += &(kid + ":" + &key + ";");
+    args.extend_from_slice(&["-c".to_owned(), "copy".to_owned()]);
+
+    // Single stream is copied as it is and ffmpeg selects its tracks.
+    if streams.len() > 1 {
+        if streams.iter().any(|x| x.media_type == MediaType::Subtitles) && output.ends_with(".mp4")
+        {
+            args.extend_from_slice(&["-c:s".to_owned(), "mov_text".to_owned()]);
         }
 
-        Self {
-            bytes: bytes.get(..(bytes.len() - 1)).unwrap().as_bytes().to_vec(),
-            iv: None,
-            method: KeyMethod::Cenc,
+        for i in 0..streams.len() {
+            args.extend_from_slice(&["-map".to_owned(), i.to_string()]);
         }
     }
 
-    fn as_hex_keys(&self) -> HashMap<String, String> {
-        String::from_utf8(self.bytes.clone())
-            .unwrap()
-            .split(';')
-            .map(|x| {
-                x.split_once(':')
-                    .map(|(x, y)| (x.to_owned(), y.to_owned()))
-                    .unwrap()
-            })
-            .collect()
+    if metadata_file.is_some() {
+        args.extend_from_slice(&["-map_chapters".to_owned(), streams.len().to_string()]);
     }
 
-    fn decrypt(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
-        Ok(match self.method {
-            KeyMethod::Aes128 => {
-                let iv = if let Some(iv) = &self.iv {
-                    Some(hex::decode(iv.trim_start_matches("0x"))?)
-                } else {
-                    None
-                };
+    if let Some(title) = &metadata.title {
+        args.extend_from_slice(&["-metadata".to_owned(), format!("title={}", title)]);
+    }
 
-                utils::decrypt_aes_128_cbc(&mut data, &self.bytes, iv.as_ref())?
+    args.extend_from_slice(&[
+        "-metadata".to_owned(),
+        format!("encoded_by={}", Metadata::app()),
+    ]);
+
+    let mut video_index = 0;
+    let mut audio_index = 0;
+    let mut subtitle_index = 0;
+    let mut default_subtitle = false;
+
+    for stream in streams {
+        // Containers expects ISO 639-2 codes, otherwise tracks are tagged as undefined.
+        let language = stream
+            .language
+            .as_ref()
+            .map(|x| mux::iso639_2b(x).unwrap_or_else(|| x.to_owned()));
+
+        match stream.media_type {
+            MediaType::Video | MediaType::Undefined => {
+                if let Some(language) = &language {
+                    args.extend_from_slice(&[
+                        format!("-metadata:s:v:{}", video_index),
+                        format!("language={}", language),
+                    ]);
+                }
+
+                video_index += 1;
             }
-            KeyMethod::Cenc => {
-                mp4decrypt::mp4decrypt(&data, self.as_hex_keys(), None).map_err(|x| anyhow!(x))?
+            MediaType::Audio => {
+                if let Some(language) = &language {
+                    args.extend_from_slice(&[
+                        format!("-metadata:s:a:{}", audio_index),
+                        format!("language={}", language),
+                    ]);
+                }
+
+                audio_index += 1;
             }
-            _ => data,
-        })
-    }
-}
+            MediaType::Subtitles => {
+                if let Some(language) = &language {
+                    args.extend_from_slice(&[
+                        format!("-metadata:s:s:{}", subtitle_index),
+                        format!("language={}", language),
+                    ]);
+                }
 
-// https://rust-lang-nursery.github.io/rust-cookbook/web/clients/download.html#make-a-partial-download-with-http-range-headers
-struct PartialRangeIter {
-    start: u64,
-    end: u64,
-    buffer_size: u32,
-}
+                // First full subtitles stream is marked as default and forced
+                // subtitles streams are flagged so that players can auto select them.
+                let mut disposition = vec![];
 
-impl Iterator for PartialRangeIter {
-    type Item = Range;
+                if !stream.forced && !default_subtitle {
+                    default_subtitle = true;
+                    disposition.push("default");
+                }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.start > self.end {
-            None
-        } else {
-            let prev_start = self.start;
-            self.start += std::cmp::min(self.buffer_size as u64, self.end - self.start + 1);
-            Some(Range {
-                start: prev_start,
-                end: self.start - 1,
-            })
-        }
-    }
-}
-struct ThreadData {
-    downloaded_bytes: usize,
-    index: usize,
-    keys: Option<Keys>,
-    map: Option<Vec<u8>>,
-    merger: Arc<Mutex<Merger>>,
-    pb: Arc<Mutex<RichProgress>>,
-    relative_size: usize,
-    request: RequestBuilder,
-    timer: Arc<Instant>,
-    total_retries: u8,
-}
+                if stream.forced {
+                    disposition.push("forced");
+                }
 
-impl ThreadData {
-    fn execute(&self) -> Result<()> {
-        let mut segment = self.map.clone().unwrap_or(vec![]);
-        segment.append(&mut self.download_segment()?);
+                if !disposition.is_empty() {
+                    args.extend_from_slice(&[
+                        format!("-disposition:s:{}", subtitle_index),
+                        disposition.join("+"),
+                    ]);
+                }
 
-        if let Some(keys) = &self.keys {
-            segment = keys.decrypt(segment)?;
+                subtitle_index += 1;
+            }
         }
+    }
 
-        let mut merger = self.merger.lock().unwrap();
-        merger.write(self.index, &segment)?;
-        merger.flush()?;
+    args.push(output.to_owned());
+    let result = ffmpeg.run(args);
 
-        self.notify(merger.stored(), merger.estimate())?;
-        Ok(())
+    if let Some(metadata_file) = &metadata_file {
+        std::fs::remove_file(metadata_file)?;
     }
 
-    fn download_segment(&self) -> Result<Vec<u8>> {
-        for _ in 0..self.total_retries {
-            let response = match self.request.try_clone().unwrap().send() {
-                Ok(response) => response,
-                Err(error) => {
-                    self.pb
-                        .lock()
-                        .unwrap()
-                        .write(check_reqwest_error(&error)?)?;
-                    continue;
-                }
-            };
+    result
+}
 
-            let status = response.status();
+/// Build ffmetadata file content containing chapters.
+fn ffmetadata(chapters: &[Chapter]) -> String {
+    let mut content = ";FFMETADATA1\n".to_owned();
 
-            if status.is_client_error() || status.is_server_error() {
-                bail!("failed to fetch segments");
-            }
+    for chapter in chapters {
+        let mut title = String::new();
 
-            let data = response.bytes()?.to_vec();
-            let elapsed_time = self.timer.elapsed().as_secs() as usize;
-
-            if elapsed_time != 0 {
-                let stored = self.merger.lock().unwrap().stored() + data.len();
-                self.pb.lock().unwrap().replace(
-                    12,
-                    Column::Text(format!(
-                        "[yellow]{}/s",
-                        utils::format_bytes(stored / elapsed_time, 2).2
-                    )),
-                );
+        for c in chapter.title.chars() {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                title.push('\\');
             }
 
-            return Ok(data);
+            title.push(c);
         }
 
-        bail!("reached maximum number of retries to download a segment");
+        content += &format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start * 1000.0) as u64,
+            (chapter.end * 1000.0) as u64,
+            title
+        );
     }
 
-    fn notify(&self, stored: usize, estimate: usize) -> Result<()> {
-        let mut pb = self.pb.lock().unwrap();
-        pb.replace(
-            0,
-            Column::Text(format!(
-                "[bold blue]{}",
-                utils::format_download_bytes(
-                    self.downloaded_bytes + stored,
-                    self.downloaded_bytes + estimate + self.relative_size,
-                ),
-            )),
-        );
-        pb.update(1).unwrap();
-        Ok(())
+    content
+}
+
+/// Downloaded and estimated sizes of video and audio streams which are downloaded together.
+pub(crate) struct StreamSizes(Mutex<Vec<(usize, usize)>>);
+
+impl StreamSizes {
+    fn new(estimates: Vec<usize>) -> Self {
+        Self(Mutex::new(estimates.into_iter().map(|x| (0, x)).collect()))
+    }
+
+    /// Update sizes of a stream and return total downloaded and estimated size of all streams.
+    pub(crate) fn update(&self, stream: usize, event: &SegmentEvent) -> (usize, usize) {
+        let mut sizes = self.0.lock().unwrap();
+        sizes[stream] = (event.stored, event.estimate);
+        sizes
+            .iter()
+            .fold((0, 0), |total, x| (total.0 + x.0, total.1 + x.1))
     }
 }
 
-fn check_reqwest_error(error: &reqwest::Error) -> Result<String> {
-    let request = "Request".colorize("bold yellow");
-    let url = error.url().unwrap();
+/// Progress of a stream shown on progress bar shared by all streams.
+struct StreamProgress {
+    pb: Arc<Mutex<RichProgress>>,
+    sizes: Arc<StreamSizes>,
+    stream: usize,
+    timer: Instant,
+}
 
-    if error.is_timeout() {
-        return Ok(format!("    {} {} (timeout)", request, url));
-    } else if error.is_connect() {
-        return Ok(format!("    {} {} (connection error)", request, url));
+impl Progress for StreamProgress {
+    fn key(&self, kid: &str, key: &str) {
+        let _ = self.pb.lock().unwrap().write(format!(
+            "        {} {}:{}",
+            "Key".colorize("bold green"),
+            kid,
+            key
+        ));
     }
 
-    if let Some(status) = error.status() {
-        match status {
-            StatusCode::REQUEST_TIMEOUT => Ok(format!("    {} {} (timeout)", request, url)),
-            StatusCode::TOO_MANY_REQUESTS => {
-                Ok(format!("    {} {} (too many requests)", request, url))
-            }
-            StatusCode::SERVICE_UNAVAILABLE => {
-                Ok(format!("    {} {} (service unavailable)", request, url))
-            }
-            StatusCode::GATEWAY_TIMEOUT => Ok(format!("    {} {} (gateway timeout)", request, url)),
-            _ => bail!("download failed {} (HTTP {})", url, status),
+    fn retry(&self, url: &str, reason: &str) {
+        let _ = self.pb.lock().unwrap().write(format!(
+            "    {} {} ({})",
+            "Request".colorize("bold yellow"),
+            url,
+            reason
+        ));
+    }
+
+    fn segment(&self, event: &SegmentEvent) {
+        let (stored, estimate) = self.sizes.update(self.stream, event);
+        let mut pb = self.pb.lock().unwrap();
+        let elapsed_time = self.timer.elapsed().as_secs() as usize;
+
+        if elapsed_time != 0 {
+            pb.replace(
+                12,
+                Column::Text(format!(
+                    "[yellow]{}/s",
+                    utils::format_bytes(stored / elapsed_time, 2).2
+                )),
+            );
         }
-    } else {
-        bail!("download failed {}", url)
+
+        pb.replace(
+            0,
+            Column::Text(format!(
+                "[bold blue]{}",
+                utils::format_download_bytes(stored, estimate),
+            )),
+        );
+        let _ = pb.update(1);
     }
 }
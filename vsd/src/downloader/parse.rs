@@ -1,11 +1,16 @@
 use crate::{
-    commands::Quality,
-    downloader::{InputMetadata, Prompts, SelectedPlaylists},
-    playlist::{MasterPlaylist, MediaPlaylist, PlaylistType},
+    downloader::{InputMetadata, Prompts, SelectedPlaylists, Selection},
     utils
 };
 use anyhow::{anyhow, bail, Result};
+use kdam::term::Colorizer;
+use requestty::prompt::style::Stylize;
 use reqwest::{blocking::Client, Url};
+use std::io::{IsTerminal, Write};
+use vsd_core::{
+    dash, hls,
+    playlist::{MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Quality},
+};
 
 pub fn parse_all_streams(
     base_url: Option<Url>,
@@ -21,10 +26,10 @@ pub fn parse_all_streams(
                     meta.text
                 )
             })?;
-            let mut playlist = crate::dash::parse_as_master(&mpd, meta.url.as_ref());
+            let mut playlist = dash::parse_as_master(&mpd, meta.url.as_ref());
 
             for stream in playlist.streams.iter_mut() {
-                crate::dash::push_segments(
+                dash::push_segments(
                     &mpd,
                     stream,
                     base_url.as_ref().unwrap_or(&meta.url).as_str(),
@@ -36,7 +41,7 @@ pub fn parse_all_streams(
         }
         Some(PlaylistType::Hls) => match m3u8_rs::parse_playlist_res(meta.text.as_bytes()) {
             Ok(m3u8_rs::Playlist::MasterPlaylist(m3u8)) => {
-                let mut playlist = crate::hls::parse_as_master(&m3u8, meta.url.as_ref());
+                let mut playlist = hls::parse_as_master(&m3u8, meta.url.as_ref());
 
                 for stream in playlist.streams.iter_mut() {
                     stream.uri = base_url
@@ -50,6 +55,7 @@ pub fn parse_all_streams(
                         let decoded = utils::decode_base64(bs)?;
                         text = String::from_utf8(decoded)?;
                     } else {
+                        log::debug!("fetching media playlist {}", stream.uri);
                         let response = client.get(&stream.uri).send()?;
                         text = response.text()?;
                     }
@@ -63,17 +69,17 @@ pub fn parse_all_streams(
                                 text
                             )
                         })?;
-                    crate::hls::push_segments(&media_playlist, stream);
+                    hls::push_segments(&media_playlist, stream);
                 }
 
                 Ok(playlist)
             }
             Ok(m3u8_rs::Playlist::MediaPlaylist(m3u8)) => {
-                let mut media_playlist = crate::playlist::MediaPlaylist {
+                let mut media_playlist = MediaPlaylist {
                     uri: meta.url.as_ref().to_owned(),
                     ..Default::default()
                 };
-                crate::hls::push_segments(&m3u8, &mut media_playlist);
+                hls::push_segments(&m3u8, &mut media_playlist);
                 Ok(MasterPlaylist {
                     playlist_type: PlaylistType::Hls,
                     streams: vec![media_playlist],
@@ -91,6 +97,7 @@ pub fn parse_all_streams(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn parse_selected_streams(
     base_url: Option<Url>,
     client: &Client,
@@ -99,6 +106,7 @@ pub fn parse_selected_streams(
     prefer_subs_lang: Option<String>,
     prompts: &Prompts,
     quality: Quality,
+    selection: &Selection,
 ) -> Result<SelectedPlaylists> {
     match meta.pl_type {
         Some(PlaylistType::Dash) => {
@@ -109,16 +117,20 @@ pub fn parse_selected_streams(
                     meta.text
                 )
             })?;
-            let (mut video_audio_streams, mut subtitle_streams) =
-                crate::dash::parse_as_master(&mpd, meta.url.as_ref())
-                    .sort_streams(prefer_audio_lang, prefer_subs_lang)
-                    .select_streams(quality, prompts.skip, prompts.raw)?;
+            let (mut video_audio_streams, mut subtitle_streams) = select_streams(
+                dash::parse_as_master(&mpd, meta.url.as_ref())
+                    .sort_streams(prefer_audio_lang, prefer_subs_lang),
+                quality,
+                selection,
+                prompts.skip,
+                prompts.raw,
+            )?;
 
             for stream in video_audio_streams
                 .iter_mut()
                 .chain(subtitle_streams.iter_mut())
             {
-                crate::dash::push_segments(
+                dash::push_segments(
                     &mpd,
                     stream,
                     base_url.as_ref().unwrap_or(&meta.url).as_str(),
@@ -130,10 +142,14 @@ pub fn parse_selected_streams(
         }
         Some(PlaylistType::Hls) => match m3u8_rs::parse_playlist_res(meta.text.as_bytes()) {
             Ok(m3u8_rs::Playlist::MasterPlaylist(m3u8)) => {
-                let (mut video_audio_streams, mut subtitle_streams) =
-                    crate::hls::parse_as_master(&m3u8, meta.url.as_str())
-                        .sort_streams(prefer_audio_lang, prefer_subs_lang)
-                        .select_streams(quality, prompts.skip, prompts.raw)?;
+                let (mut video_audio_streams, mut subtitle_streams) = select_streams(
+                    hls::parse_as_master(&m3u8, meta.url.as_str())
+                        .sort_streams(prefer_audio_lang, prefer_subs_lang),
+                    quality,
+                    selection,
+                    prompts.skip,
+                    prompts.raw,
+                )?;
 
                 for stream in video_audio_streams
                     .iter_mut()
@@ -150,6 +166,7 @@ pub fn parse_selected_streams(
                         let decoded = utils::decode_base64(bs)?;
                         text = String::from_utf8(decoded)?;
                     } else {
+                        log::debug!("fetching media playlist {}", stream.uri);
                         let response = client.get(&stream.uri).send()?;
                         text = response.text()?;
                     }
@@ -163,7 +180,7 @@ pub fn parse_selected_streams(
                                 text
                             )
                         })?;
-                    crate::hls::push_segments(&media_playlist, stream);
+                    hls::push_segments(&media_playlist, stream);
                 }
 
                 Ok((video_audio_streams, subtitle_streams))
@@ -173,7 +190,7 @@ pub fn parse_selected_streams(
                     uri: meta.url.as_ref().to_owned(),
                     ..Default::default()
                 };
-                crate::hls::push_segments(&m3u8, &mut media_playlist);
+                hls::push_segments(&m3u8, &mut media_playlist);
                 Ok((vec![media_playlist], vec![]))
             }
             Err(x) => bail!(
@@ -186,3 +203,275 @@ pub fn parse_selected_streams(
         _ => bail!("couldn't determine playlist type, only DASH and HLS playlists are supported."),
     }
 }
+
+/// Select streams from master playlist using selectors or prompts, video stream matching quality
+/// and first audio and subtitle streams are selected by default.
+fn select_streams(
+    playlist: MasterPlaylist,
+    quality: Quality,
+    selection: &Selection,
+    skip_prompts: bool,
+    raw_prompts: bool,
+) -> Result<(Vec<MediaPlaylist>, Vec<MediaPlaylist>)> {
+    // Quality is only used for choosing default video stream, which --select-video replaces.
+    let default_video_stream_index = if selection.video.is_empty() {
+        playlist.select_video_stream(&quality)
+    } else {
+        Some(0)
+    };
+    // Numbered prompt is used when arrow keys cannot be read from a terminal.
+    let interactive = !skip_prompts
+        && !raw_prompts
+        && std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal();
+
+    if let Some(default_video_stream_index) = default_video_stream_index {
+        let mut video_streams = vec![];
+        let mut audio_streams = vec![];
+        let mut subtitle_streams = vec![];
+        // TODO - Add support for downloading undefined streams
+        let mut undefined_streams = vec![];
+
+        for stream in playlist.streams {
+            match stream.media_type {
+                MediaType::Audio => audio_streams.push(stream),
+                MediaType::Subtitles => subtitle_streams.push(stream),
+                MediaType::Undefined => undefined_streams.push(stream),
+                MediaType::Video => video_streams.push(stream),
+            }
+        }
+
+        if !selection.is_empty() {
+            return selection.select(
+                video_streams,
+                audio_streams,
+                subtitle_streams,
+                default_video_stream_index,
+            );
+        }
+
+        if interactive {
+            let mut selected_streams = vec![];
+            let mut selected_subtitle_streams = vec![];
+
+            if !video_streams.is_empty() {
+                let question = requestty::Question::multi_select("video")
+                    .should_loop(false)
+                    .message("Select video streams")
+                    .choices_with_default(
+                        video_streams
+                            .iter()
+                            .enumerate()
+                            .map(|(i, x)| {
+                                (x.display_video_stream(), i == default_video_stream_index)
+                            }),
+                    )
+                    .transform(|choices, _, backend| {
+                        backend.write_styled(
+                            &choices
+                                .iter()
+                                .map(|x| compact(&x.text))
+                                .collect::<Vec<_>>()
+                                .join(" | ")
+                                .cyan(),
+                        )
+                    })
+                    .build();
+                let answer = requestty::prompt_one(question)?;
+                let indexes = answer
+                    .as_list_items()
+                    .unwrap()
+                    .iter()
+                    .map(|x| x.index)
+                    .collect::<Vec<_>>();
+
+                selected_streams.extend(
+                    video_streams
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(i, _)| indexes.contains(i))
+                        .map(|(_, x)| x),
+                );
+            }
+
+            for (name, streams, selected) in [
+                ("audio", audio_streams, &mut selected_streams),
+                ("subtitle", subtitle_streams, &mut selected_subtitle_streams),
+            ] {
+                if streams.is_empty() {
+                    continue;
+                }
+
+                let question = requestty::Question::multi_select(name)
+                    .should_loop(false)
+                    .message(format!("Select {} streams", name))
+                    .choices_with_default(
+                        streams
+                            .iter()
+                            .enumerate()
+                            .map(|(i, x)| (x.display_stream(), i == 0)),
+                    )
+                    .transform(|choices, _, backend| {
+                        backend.write_styled(
+                            &choices
+                                .iter()
+                                .map(|x| compact(&x.text))
+                                .collect::<Vec<_>>()
+                                .join(" | ")
+                                .cyan(),
+                        )
+                    })
+                    .build();
+                let answer = requestty::prompt_one(question)?;
+                let indexes = answer
+                    .as_list_items()
+                    .unwrap()
+                    .iter()
+                    .map(|x| x.index)
+                    .collect::<Vec<_>>();
+
+                selected.extend(
+                    streams
+                        .into_iter()
+                        .enumerate()
+                        .filter(|(i, _)| indexes.contains(i))
+                        .map(|(_, x)| x),
+                );
+            }
+
+            Ok((selected_streams, selected_subtitle_streams))
+        } else {
+            let mut choices_with_default = vec![];
+            let mut choices_with_default_ranges: [std::ops::Range<usize>; 4] =
+                [(0..0), (0..0), (0..0), (0..0)];
+
+            choices_with_default.push(requestty::Separator(
+                "─────── Video Streams ────────".to_owned(),
+            ));
+            choices_with_default.extend(video_streams.iter().enumerate().map(|(i, x)| {
+                requestty::Choice((x.display_video_stream(), i == default_video_stream_index))
+            }));
+            choices_with_default_ranges[0] = 1..choices_with_default.len();
+            choices_with_default.push(requestty::Separator(
+                "─────── Audio Streams ────────".to_owned(),
+            ));
+            choices_with_default.extend(
+                audio_streams
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| requestty::Choice((x.display_audio_stream(), i == 0))),
+            );
+
+            choices_with_default_ranges[1] =
+                choices_with_default_ranges[0].end..(choices_with_default.len() - 1);
+
+            choices_with_default.push(requestty::Separator(
+                "────── Subtitle Streams ──────".to_owned(),
+            ));
+            choices_with_default.extend(
+                subtitle_streams
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| requestty::Choice((x.display_subtitle_stream(), i == 0))),
+            );
+
+            choices_with_default_ranges[2] =
+                choices_with_default_ranges[1].end..(choices_with_default.len() - 2);
+
+            // println!("{:?}", choices_with_default_ranges);
+
+            eprintln!("Select streams to download:");
+            let mut selected_choices_index = vec![];
+            let mut index = 1;
+
+            for choice in choices_with_default {
+                if let requestty::Separator(seperator) = choice {
+                    eprintln!("{}", seperator.replace('─', "-"));
+                } else {
+                    let (message, selected) = choice.unwrap_choice();
+
+                    if selected {
+                        selected_choices_index.push(index);
+                    }
+
+                    eprintln!(
+                        "{:2}) [{}] {}",
+                        index,
+                        if selected { 'x' } else { ' ' },
+                        message
+                    );
+                    index += 1;
+                }
+            }
+
+            eprintln!("------------------------------");
+
+            if !skip_prompts {
+                eprint!(
+                    "Press enter to proceed with defaults.\n\
+                    Or select streams to download (1, 2, etc.): "
+                );
+                std::io::stderr().flush()?;
+                let mut input = String::new();
+                std::io::stdin().read_line(&mut input)?;
+
+                eprintln!("------------------------------");
+
+                let input = input.trim();
+
+                if !input.is_empty() {
+                    selected_choices_index = input
+                        .split(',')
+                        .filter_map(|x| x.trim().parse::<usize>().ok())
+                        .collect::<Vec<usize>>();
+                }
+            }
+
+            let mut selected_streams = vec![];
+            let mut selected_subtitle_streams = vec![];
+            let mut video_streams_offset = 1;
+            let mut audio_streams_offset = video_streams_offset + video_streams.len();
+            let mut subtitle_streams_offset = audio_streams_offset + audio_streams.len();
+
+            for i in selected_choices_index {
+                if choices_with_default_ranges[0].contains(&i) {
+                    let stream = video_streams.remove(i - video_streams_offset);
+                    eprintln!(
+                        "   {} {}",
+                        "Selected".colorize("bold green"),
+                        stream.display_stream()
+                    );
+                    selected_streams.push(stream);
+                    video_streams_offset += 1;
+                } else if choices_with_default_ranges[1].contains(&i) {
+                    let stream = audio_streams.remove(i - audio_streams_offset);
+                    eprintln!(
+                        "   {} {}",
+                        "Selected".colorize("bold green"),
+                        stream.display_stream()
+                    );
+                    selected_streams.push(stream);
+                    audio_streams_offset += 1;
+                } else if choices_with_default_ranges[2].contains(&i) {
+                    let stream = subtitle_streams.remove(i - subtitle_streams_offset);
+                    eprintln!(
+                        "   {} {}",
+                        "Selected".colorize("bold green"),
+                        stream.display_stream()
+                    );
+                    selected_subtitle_streams.push(stream);
+                    subtitle_streams_offset += 1;
+                }
+            }
+
+            Ok((selected_streams, selected_subtitle_streams))
+        }
+    } else {
+        bail!("playlist doesn't contain pre-selected video quality stream.")
+    }
+}
+
+/// Collapse whitespaces used for aligning stream details.
+fn compact(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,316 @@
+use anyhow::Result;
+use kdam::term::Colorizer;
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+use vsd_core::playlist::{MediaPlaylist, MediaType};
+
+/// Number of segments kept in rolling media playlists.
+const PLAYLIST_SIZE: usize = 10;
+
+/// Local hls playlist of live streams being recorded, so that recording can be watched with a
+/// short delay (eg. from another device on the LAN). Every recorded batch of segments is copied
+/// into directory as a single segment and `live.m3u8` master playlist refers to a rolling media
+/// playlist of each stream. Segments and init segments which are no longer in media playlists
+/// are deleted.
+pub(super) struct Restream {
+    directory: PathBuf,
+    streams: Vec<Mutex<RestreamPlaylist>>,
+}
+
+struct RestreamPlaylist {
+    /// Number of discontinuities which were removed from playlist.
+    discontinuity_sequence: usize,
+    entries: VecDeque<Entry>,
+    extension: String,
+    /// Last written init segment and its file name.
+    init: Option<(Vec<u8>, String)>,
+    /// Number of next segment.
+    number: usize,
+}
+
+struct Entry {
+    discontinuity: bool,
+    duration: f64,
+    init: Option<String>,
+    name: String,
+}
+
+impl Restream {
+    /// Create directory and write master playlist of streams.
+    pub(super) fn new(directory: &Path, streams: &[(MediaPlaylist, String)]) -> Result<Self> {
+        std::fs::create_dir_all(directory)?;
+
+        let mut master = "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-INDEPENDENT-SEGMENTS\n".to_owned();
+        let has_video = streams
+            .iter()
+            .any(|(x, _)| x.media_type == MediaType::Video);
+        let first_audio = streams
+            .iter()
+            .position(|(x, _)| x.media_type == MediaType::Audio);
+
+        for (i, (stream, _)) in streams.iter().enumerate() {
+            if has_video && stream.media_type == MediaType::Audio {
+                master += &format!(
+                    "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"{}\",{}DEFAULT={},AUTOSELECT=YES,URI=\"{}.m3u8\"\n",
+                    stream.language.as_deref().unwrap_or("audio"),
+                    stream
+                        .language
+                        .as_ref()
+                        .map(|x| format!("LANGUAGE=\"{}\",", x))
+                        .unwrap_or_default(),
+                    if first_audio == Some(i) { "YES" } else { "NO" },
+                    i
+                );
+                continue;
+            }
+
+            master += &format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={}",
+                stream.bandwidth.unwrap_or(1)
+            );
+
+            if let Some(codecs) = &stream.codecs {
+                master += &format!(",CODECS=\"{}\"", codecs);
+            }
+
+            if let Some((width, height)) = stream.resolution {
+                master += &format!(",RESOLUTION={}x{}", width, height);
+            }
+
+            if has_video && first_audio.is_some() {
+                master += ",AUDIO=\"audio\"";
+            }
+
+            master += &format!("\n{}.m3u8\n", i);
+        }
+
+        write_atomically(&directory.join("live.m3u8"), master.as_bytes())?;
+
+        Ok(Self {
+            directory: directory.to_owned(),
+            streams: streams
+                .iter()
+                .map(|(stream, _)| {
+                    Mutex::new(RestreamPlaylist {
+                        discontinuity_sequence: 0,
+                        entries: VecDeque::new(),
+                        extension: stream.extension(),
+                        init: None,
+                        number: 0,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// Copy last `size` bytes of recorded file as next segment of stream.
+    pub(super) fn push(&self, stream: usize, path: &str, size: usize, duration: f64) -> Result<()> {
+        let mut data = vec![0; size];
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::End(-(size as i64)))?;
+        file.read_exact(&mut data)?;
+
+        let mut playlist = self.streams[stream].lock().unwrap();
+        let (init, media) = split_init(&data);
+        let mut discontinuity = false;
+
+        if !init.is_empty() && playlist.init.as_ref().map(|x| x.0.as_slice()) != Some(init) {
+            let name = format!("{}.init{}.mp4", stream, playlist.number);
+            write_atomically(&self.directory.join(&name), init)?;
+            discontinuity = playlist.init.is_some();
+            playlist.init = Some((init.to_vec(), name));
+        }
+
+        let name = format!("{}.{}.{}", stream, playlist.number, playlist.extension);
+        write_atomically(&self.directory.join(&name), media)?;
+        playlist.number += 1;
+        let init = playlist.init.as_ref().map(|x| x.1.clone());
+        playlist.entries.push_back(Entry {
+            discontinuity,
+            duration,
+            init,
+            name,
+        });
+
+        while playlist.entries.len() > PLAYLIST_SIZE {
+            let entry = playlist.entries.pop_front().unwrap();
+
+            if entry.discontinuity {
+                playlist.discontinuity_sequence += 1;
+            }
+
+            let _ = std::fs::remove_file(self.directory.join(&entry.name));
+
+            // Entries referring to same init segment are contiguous, so it isn't needed anymore
+            // when next entry refers to a different one.
+            if let Some(init) = &entry.init {
+                if playlist.entries.front().and_then(|x| x.init.as_ref()) != Some(init) {
+                    let _ = std::fs::remove_file(self.directory.join(init));
+                }
+            }
+        }
+
+        self.write_playlist(stream, &playlist, false)
+    }
+
+    /// Mark media playlists as ended, so that players stop refreshing them.
+    pub(super) fn finish(&self) -> Result<()> {
+        for (i, playlist) in self.streams.iter().enumerate() {
+            self.write_playlist(i, &playlist.lock().unwrap(), true)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_playlist(
+        &self,
+        stream: usize,
+        playlist: &RestreamPlaylist,
+        ended: bool,
+    ) -> Result<()> {
+        let target_duration = playlist
+            .entries
+            .iter()
+            .map(|x| x.duration.ceil() as u64)
+            .max()
+            .unwrap_or(1);
+        let mut text = format!(
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:{}\n#EXT-X-DISCONTINUITY-SEQUENCE:{}\n",
+            target_duration,
+            playlist.number - playlist.entries.len(),
+            playlist.discontinuity_sequence,
+        );
+        let mut previous_init = None;
+
+        for (i, entry) in playlist.entries.iter().enumerate() {
+            if entry.discontinuity && i != 0 {
+                text += "#EXT-X-DISCONTINUITY\n";
+            }
+
+            if let Some(init) = &entry.init {
+                if previous_init != Some(init) {
+                    text += &format!("#EXT-X-MAP:URI=\"{}\"\n", init);
+                    previous_init = Some(init);
+                }
+            }
+
+            text += &format!("#EXTINF:{:.3},\n{}\n", entry.duration, entry.name);
+        }
+
+        if ended {
+            text += "#EXT-X-ENDLIST\n";
+        }
+
+        write_atomically(
+            &self.directory.join(format!("{}.m3u8", stream)),
+            text.as_bytes(),
+        )
+    }
+}
+
+/// Split leading `ftyp` and `moov` boxes of fragmented mp4 data from its media fragments.
+/// Data which isn't fragmented mp4 (eg. mpeg-ts) doesn't have an init segment.
+fn split_init(data: &[u8]) -> (&[u8], &[u8]) {
+    let mut pos = 0;
+
+    while pos + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+
+        if !matches!(&data[pos + 4..pos + 8], b"ftyp" | b"moov") || size < 8 {
+            break;
+        }
+
+        pos += size;
+    }
+
+    data.split_at(pos.min(data.len()))
+}
+
+/// Write file using a temporary file, so that players never read a partially written file.
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, data)?;
+    std::fs::rename(temp, path)?;
+    Ok(())
+}
+
+/// Serve files of directory over http on all interfaces, in a background thread.
+pub(super) fn serve(directory: &Path, port: u16) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    eprintln!(
+        "    {} http://0.0.0.0:{}/live.m3u8",
+        "Serving".colorize("bold green"),
+        port
+    );
+
+    let directory = directory.to_owned();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let directory = directory.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = respond(&directory, stream) {
+                    log::debug!("restream request failed ({})", e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn respond(directory: &Path, mut stream: TcpStream) -> Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let path = request
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or("/")
+        .split('?')
+        .next()
+        .unwrap()
+        .trim_start_matches('/');
+    let path = if path.is_empty() { "live.m3u8" } else { path };
+    log::debug!("restream request {}", request.trim_end());
+
+    // Only files directly inside directory are served.
+    let file = Path::new(path)
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+        .then(|| directory.join(path))
+        .and_then(|x| std::fs::read(x).ok());
+
+    let body = match file {
+        Some(body) => body,
+        None => {
+            stream.write_all(
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            )?;
+            return Ok(());
+        }
+    };
+
+    let content_type = match path.rsplit('.').next() {
+        Some("m3u8") => "application/vnd.apple.mpegurl",
+        Some("ts") => "video/mp2t",
+        Some("aac") => "audio/aac",
+        Some("m4a") => "audio/mp4",
+        _ => "video/mp4",
+    };
+
+    stream.write_all(
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            content_type,
+            body.len()
+        )
+        .as_bytes(),
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
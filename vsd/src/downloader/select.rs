@@ -0,0 +1,83 @@
+use anyhow::{bail, Result};
+use kdam::term::Colorizer;
+use vsd_core::playlist::{MediaPlaylist, StreamSelector};
+
+/// Streams selected using `--select-video`, `--select-audio` and `--select-subs` flags.
+/// Prompts are skipped when any of these flags is used.
+#[derive(Clone, Default)]
+pub struct Selection {
+    pub audio: Vec<StreamSelector>,
+    pub subs: Vec<StreamSelector>,
+    pub video: Vec<StreamSelector>,
+}
+
+impl Selection {
+    pub(super) fn is_empty(&self) -> bool {
+        self.audio.is_empty() && self.subs.is_empty() && self.video.is_empty()
+    }
+
+    /// Select streams of every kind, kinds without selectors fall back to default streams
+    /// i.e. video stream matching quality and first audio and subtitle streams.
+    pub(super) fn select(
+        &self,
+        video_streams: Vec<MediaPlaylist>,
+        audio_streams: Vec<MediaPlaylist>,
+        subtitle_streams: Vec<MediaPlaylist>,
+        default_video_stream_index: usize,
+    ) -> Result<(Vec<MediaPlaylist>, Vec<MediaPlaylist>)> {
+        let mut selected_streams = select_kind(
+            "video",
+            &self.video,
+            video_streams,
+            default_video_stream_index,
+        )?;
+        selected_streams.extend(select_kind("audio", &self.audio, audio_streams, 0)?);
+        let selected_subtitle_streams = select_kind("subs", &self.subs, subtitle_streams, 0)?;
+
+        for stream in selected_streams.iter().chain(&selected_subtitle_streams) {
+            eprintln!(
+                "   {} {}",
+                "Selected".colorize("bold green"),
+                stream.display_stream()
+            );
+        }
+
+        Ok((selected_streams, selected_subtitle_streams))
+    }
+}
+
+/// Every selector picks first matching stream in sorted order, except `all` which picks every stream.
+fn select_kind(
+    kind: &str,
+    selectors: &[StreamSelector],
+    streams: Vec<MediaPlaylist>,
+    default: usize,
+) -> Result<Vec<MediaPlaylist>> {
+    if selectors.is_empty() {
+        return Ok(streams.into_iter().skip(default).take(1).collect());
+    }
+
+    let mut indexes = vec![];
+
+    for selector in selectors {
+        match selector {
+            StreamSelector::All => indexes.extend(0..streams.len()),
+            StreamSelector::None => (),
+            _ => match streams
+                .iter()
+                .enumerate()
+                .position(|(i, x)| selector.matches(i + 1, x))
+            {
+                Some(i) => indexes.push(i),
+                None => bail!("no {} stream matches --select-{} {}.", kind, kind, selector),
+            },
+        }
+    }
+
+    Ok(streams
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| indexes.contains(i))
+        .map(|(_, x)| x)
+        .collect())
+}
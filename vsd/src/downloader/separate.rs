@@ -0,0 +1,124 @@
+use crate::{
+    downloader::Stream,
+    ffmpeg::Ffmpeg,
+    logger::warning,
+    mux::{self, Metadata},
+};
+use anyhow::Result;
+use kdam::term::Colorizer;
+use std::path::Path;
+use vsd_core::playlist::MediaType;
+
+/// Save every downloaded stream into its own clean file instead of muxing them together.
+/// Video streams are remuxed to .mp4, audio streams to .m4a and subtitles are kept as they are.
+/// Files are named as `{prefix}.{kind}.{lang}.{ext}`, eg. movie.audio.en.m4a.
+pub(super) fn save_separately(
+    ffmpeg: &Ffmpeg,
+    metadata: &Metadata,
+    prefix: Option<&str>,
+    temp_files: &[Stream],
+) -> Result<()> {
+    let mut paths: Vec<String> = vec![];
+
+    for stream in temp_files {
+        let temp_ext = Path::new(&stream.file_path)
+            .extension()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let (kind, ext) = match stream.media_type {
+            MediaType::Audio => ("audio", "m4a".to_owned()),
+            MediaType::Subtitles => ("subs", temp_ext.clone()),
+            MediaType::Video | MediaType::Undefined => ("video", "mp4".to_owned()),
+        };
+
+        let mut name = vec![];
+
+        if let Some(prefix) = prefix {
+            name.push(prefix.to_owned());
+        }
+
+        name.push(kind.to_owned());
+
+        if let Some(language) = &stream.language {
+            name.push(language.to_owned());
+        }
+
+        if stream.forced {
+            name.push("forced".to_owned());
+        }
+
+        let mut path = format!("{}.{}", name.join("."), ext);
+
+        // Multiple streams of same kind and language.
+        for i in 2.. {
+            if !paths.contains(&path) {
+                break;
+            }
+
+            path = format!("{}.{}.{}", name.join("."), i, ext);
+        }
+
+        paths.push(path.clone());
+
+        if Path::new(&path).exists() {
+            eprintln!("   {} {}", "Deleting".colorize("bold red"), path);
+            std::fs::remove_file(&path)?;
+        }
+
+        if stream.media_type == MediaType::Subtitles {
+            eprintln!(
+                "     {} {} to {}",
+                "Moving".colorize("bold cyan"),
+                stream.file_path,
+                path
+            );
+            std::fs::rename(&stream.file_path, &path)?;
+            continue;
+        }
+
+        eprintln!("  {} {}", "Remuxing".colorize("bold cyan"), path);
+
+        match mux::mux_mp4(&[stream], &path, metadata, None) {
+            Ok(_) => (),
+            Err(e) => {
+                if Path::new(&path).exists() {
+                    std::fs::remove_file(&path)?;
+                }
+
+                if ffmpeg.binary().is_some() {
+                    warning!("built-in mp4 muxer failed ({}), falling back to ffmpeg", e);
+                    ffmpeg.run(vec![
+                        "-i".to_owned(),
+                        stream.file_path.clone(),
+                        "-c".to_owned(),
+                        "copy".to_owned(),
+                        path,
+                    ])?;
+                } else {
+                    // Keep stream in its downloaded container rather than failing.
+                    let path = Path::new(&path)
+                        .with_extension(&temp_ext)
+                        .to_string_lossy()
+                        .to_string();
+                    warning!(
+                        "built-in mp4 muxer failed ({}), saving stream as {}",
+                        e,
+                        path
+                    );
+                    std::fs::rename(&stream.file_path, &path)?;
+                    continue;
+                }
+            }
+        }
+
+        eprintln!(
+            "   {} {}",
+            "Deleting".colorize("bold red"),
+            stream.file_path
+        );
+        std::fs::remove_file(&stream.file_path)?;
+    }
+
+    Ok(())
+}
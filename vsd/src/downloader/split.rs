@@ -0,0 +1,157 @@
+use crate::downloader::SelectedPlaylists;
+use std::path::Path;
+use vsd_core::playlist::{MediaPlaylist, MediaType, SplitOn};
+
+/// Split selected playlists into multiple parts at each boundary of given kind.
+/// Boundaries are taken from first video stream (or first stream if there isn't any),
+/// other streams are split using their own boundaries if they have the same number of boundaries
+/// or else by time.
+pub fn split_playlists(
+    selected_playlists: SelectedPlaylists,
+    split_on: &SplitOn,
+) -> Vec<SelectedPlaylists> {
+    let (video_audio_streams, subtitle_streams) = selected_playlists;
+
+    let reference = video_audio_streams
+        .iter()
+        .find(|x| x.media_type == MediaType::Video)
+        .or(video_audio_streams.first());
+
+    let times = if let Some(reference) = reference {
+        boundary_indexes(reference, split_on)
+            .into_iter()
+            .map(|i| {
+                reference.segments[..i]
+                    .iter()
+                    .map(|x| x.duration as f64)
+                    .sum::<f64>()
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+
+    if times.is_empty() {
+        return vec![(video_audio_streams, subtitle_streams)];
+    }
+
+    let mut parts = (0..=times.len())
+        .map(|_| (vec![], vec![]))
+        .collect::<Vec<SelectedPlaylists>>();
+
+    for stream in video_audio_streams {
+        for (i, part) in split_stream(stream, split_on, &times)
+            .into_iter()
+            .enumerate()
+        {
+            parts[i].0.push(part);
+        }
+    }
+
+    for stream in subtitle_streams {
+        for (i, part) in split_stream(stream, split_on, &times)
+            .into_iter()
+            .enumerate()
+        {
+            parts[i].1.push(part);
+        }
+    }
+
+    parts
+}
+
+/// Add part number before extension, eg. output.mkv -> output.001.mkv
+pub fn numbered_output(output: &str, number: usize) -> String {
+    let path = Path::new(output);
+
+    if let Some(ext) = path.extension() {
+        path.with_extension(format!("{:03}.{}", number, ext.to_string_lossy()))
+            .to_string_lossy()
+            .to_string()
+    } else {
+        format!("{}.{:03}", output, number)
+    }
+}
+
+fn boundary_indexes(stream: &MediaPlaylist, split_on: &SplitOn) -> Vec<usize> {
+    stream
+        .segments
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, x)| x.boundaries.contains(split_on))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn split_stream(
+    mut stream: MediaPlaylist,
+    split_on: &SplitOn,
+    times: &[f64],
+) -> Vec<MediaPlaylist> {
+    let mut indexes = boundary_indexes(&stream, split_on);
+
+    if indexes.len() != times.len() {
+        indexes = times
+            .iter()
+            .map(|time| {
+                let mut elapsed = 0.0;
+
+                stream
+                    .segments
+                    .iter()
+                    .position(|x| {
+                        let reached = elapsed >= *time;
+                        elapsed += x.duration as f64;
+                        reached
+                    })
+                    .unwrap_or(stream.segments.len())
+            })
+            .collect();
+    }
+
+    let mut segments = std::mem::take(&mut stream.segments);
+    let mut parts = vec![];
+
+    for index in indexes.into_iter().rev() {
+        parts.push(segments.split_off(index.min(segments.len())));
+    }
+
+    parts.push(segments);
+    parts.reverse();
+
+    // Init segment and key are only present on segment where they change,
+    // so they are carried over to first segment of next parts.
+    let mut map = None;
+    let mut key = None;
+
+    parts
+        .into_iter()
+        .map(|mut segments| {
+            if let Some(first) = segments.first_mut() {
+                if first.map.is_none() {
+                    first.map = map.clone();
+                }
+
+                if first.key.is_none() {
+                    first.key = key.clone();
+                }
+            }
+
+            for segment in &segments {
+                if segment.map.is_some() {
+                    map = segment.map.clone();
+                }
+
+                if segment.key.is_some() {
+                    key = segment.key.clone();
+                }
+            }
+
+            MediaPlaylist {
+                segments,
+                ..stream.clone()
+            }
+        })
+        .collect()
+}
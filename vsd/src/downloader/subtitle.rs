@@ -1,9 +1,10 @@
-use crate::{downloader::Stream, playlist::MediaPlaylist, utils};
+use crate::{commands::Codec, downloader::Stream};
 use anyhow::{anyhow, bail, Result};
-use kdam::{term::Colorizer, BarExt, Column, RichProgress};
+use kdam::{term::Colorizer, BarExt, RichProgress};
 use reqwest::{blocking::Client, header, Url};
-use std::{fs::File, io::Write, path::PathBuf};
-use vsd_mp4::text::{ttml_text_parser, Mp4TtmlParser, Mp4VttParser};
+use std::{fs::File, io::Write, path::PathBuf, sync::Mutex};
+use vsd_core::playlist::MediaPlaylist;
+use vsd_mp4::text::{ttml_text_parser, vtt_text_parser, Mp4TtmlParser, Mp4VttParser, Subtitles};
 
 enum SubtitleType {
     Mp4Vtt,
@@ -18,10 +19,11 @@ pub fn download_subtitle_stream(
     client: &Client,
     directory: &Option<PathBuf>,
     stream: &MediaPlaylist,
-    pb: &mut RichProgress,
+    subs_codec: &Option<Codec>,
+    pb: &Mutex<RichProgress>,
     temp_files: &mut Vec<Stream>,
 ) -> Result<()> {
-    pb.write(format!(
+    pb.lock().unwrap().write(format!(
         " {} {} stream {}",
         "Processing".colorize("bold green"),
         stream.media_type,
@@ -31,15 +33,14 @@ pub fn download_subtitle_stream(
     let length = stream.segments.len();
 
     if length == 0 {
-        pb.write(format!(
+        log::warn!("skipping stream (no segments)");
+        pb.lock().unwrap().write(format!(
             "    {} skipping stream (no segments)",
             "Warning".colorize("bold yellow"),
         ))?;
         return Ok(());
     }
 
-    pb.pb.total = length;
-
     let mut ext = stream.extension();
     let mut codec = None;
 
@@ -64,6 +65,11 @@ pub fn download_subtitle_stream(
 
     let mut first_run = true;
     let mut subtitles_data = vec![];
+    // Webvtt segments are only parsed when they need to be merged or converted,
+    // otherwise they are written as they are.
+    let mut merge_vtt = subs_codec.is_some() || length > 1;
+    let mut vtt_offset = None;
+    let mut vtt_subtitles = Subtitles::default();
 
     let stream_base_url = base_url
         .clone()
@@ -72,6 +78,7 @@ pub fn download_subtitle_stream(
     for segment in &stream.segments {
         if let Some(map) = &segment.map {
             let url = stream_base_url.join(&map.uri)?;
+            log::debug!("fetching subtitles init segment {}", url);
             let mut request = client.get(url);
 
             if let Some(range) = &map.range {
@@ -84,6 +91,7 @@ pub fn download_subtitle_stream(
         }
 
         let url = stream_base_url.join(&segment.uri)?;
+        log::debug!("fetching subtitles segment {}", url);
         let mut request = client.get(url);
 
         if let Some(range) = &segment.range {
@@ -110,55 +118,84 @@ pub fn download_subtitle_stream(
                 bail!("could'nt determine subtitle codec.");
             }
 
+            if let Some(subs_codec) = subs_codec {
+                if !matches!(codec, Some(SubtitleType::SrtText)) {
+                    ext = match subs_codec {
+                        Codec::Ass => "ass",
+                        Codec::Subrip => "srt",
+                        Codec::Webvtt => "vtt",
+                    }
+                    .to_owned();
+                }
+            }
+
             temp_file = stream
                 .file_path(directory, &ext)
                 .to_string_lossy()
                 .to_string();
             temp_files.push(Stream {
                 file_path: temp_file.clone(),
+                forced: stream.forced,
                 language: stream.language.clone(),
                 media_type: stream.media_type.clone(),
             });
-            pb.write(format!(
+            pb.lock().unwrap().write(format!(
                 "{} stream to {}",
                 "Downloading".colorize("bold green"),
                 temp_file.colorize("cyan")
             ))?;
         }
 
-        pb.replace(
-            0,
-            Column::Text(format!(
-                "[bold blue]{}",
-                utils::format_bytes(subtitles_data.len(), 2).2
-            )),
-        );
-        pb.update(1)?;
+        if let (Some(SubtitleType::VttText), true) = (&codec, merge_vtt) {
+            // Cues are repeated across segments (and live playlist refreshes),
+            // so every segment is parsed and merged into a single cue set.
+            match parse_vtt(&bytes, &mut vtt_offset) {
+                Ok(subtitles) => vtt_subtitles.merge(subtitles),
+                Err(e) => {
+                    log::warn!("couldn't parse vtt subtitles ({})", e);
+                    pb.lock().unwrap().write(format!(
+                        "    {} couldn't parse vtt subtitles ({}), segments are written as they are",
+                        "Warning".colorize("bold yellow"),
+                        e
+                    ))?;
+                    merge_vtt = false;
+
+                    // Unparsed segments cannot be converted into other formats.
+                    if ext != "vtt" {
+                        temp_file = stream
+                            .file_path(directory, "vtt")
+                            .to_string_lossy()
+                            .to_string();
+                        temp_files.last_mut().unwrap().file_path = temp_file.clone();
+                    }
+                }
+            }
+        }
+
+        pb.lock().unwrap().update(1)?;
     }
 
-    match codec {
+    let subtitles = match codec {
         Some(SubtitleType::Mp4Vtt) => {
-            pb.write(format!(
+            pb.lock().unwrap().write(format!(
                 " {} wvtt subtitles",
                 "Extracting".colorize("bold cyan"),
             ))?;
 
             let vtt = Mp4VttParser::parse_init(&subtitles_data)?;
-            let subtitles = vtt.parse_media(&subtitles_data, None)?;
-            File::create(&temp_file)?.write_all(subtitles.as_vtt().as_bytes())?;
+            Some(vtt.parse_media(&subtitles_data, None)?)
         }
         Some(SubtitleType::Mp4Ttml) => {
-            pb.write(format!(
+            pb.lock().unwrap().write(format!(
                 " {} stpp subtitles",
                 "Extracting".colorize("bold cyan"),
             ))?;
 
             let ttml = Mp4TtmlParser::parse_init(&subtitles_data)?;
-            let subtitles = ttml.parse_media(&subtitles_data)?;
-            File::create(&temp_file)?.write_all(subtitles.as_srt().as_bytes())?;
+            Some(ttml.parse_media(&subtitles_data)?)
         }
         Some(SubtitleType::TtmlText) => {
-            pb.write(format!(
+            pb.lock().unwrap().write(format!(
                 " {} ttml+xml subtitles",
                 "Extracting".colorize("bold cyan"),
             ))?;
@@ -172,17 +209,36 @@ pub fn download_subtitle_stream(
                     x,
                 )
             })?;
-            File::create(&temp_file)?.write_all(ttml.into_subtitles().as_srt().as_bytes())?;
+            Some(ttml.into_subtitles())
+        }
+        Some(SubtitleType::VttText) if merge_vtt => {
+            pb.lock().unwrap().write(format!(
+                "    {} vtt subtitles",
+                "Merging".colorize("bold cyan"),
+            ))?;
+
+            vtt_subtitles.sort();
+            Some(vtt_subtitles)
+        }
+        _ => {
+            File::create(&temp_file)?.write_all(&subtitles_data)?;
+            None
         }
-        _ => File::create(&temp_file)?.write_all(&subtitles_data)?,
     };
 
-    pb.write(format!(
+    if let Some(subtitles) = subtitles {
+        let data = match ext.as_str() {
+            "ass" => subtitles.as_ass(),
+            "vtt" => subtitles.as_vtt(),
+            _ => subtitles.as_srt(),
+        };
+        File::create(&temp_file)?.write_all(data.as_bytes())?;
+    }
+
+    pb.lock().unwrap().write(format!(
         " {} stream successfully",
         "Downloaded".colorize("bold green"),
     ))?;
-    eprintln!();
-    pb.reset(Some(0));
     Ok(())
 }
 
@@ -191,12 +247,209 @@ pub fn download_subtitle_streams(
     client: &Client,
     directory: &Option<PathBuf>,
     subtitle_streams: &Vec<MediaPlaylist>,
-    pb: &mut RichProgress,
+    subs_codec: &Option<Codec>,
+    pb: &Mutex<RichProgress>,
     temp_files: &mut Vec<Stream>,
 ) -> Result<()> {
     for stream in subtitle_streams {
-        download_subtitle_stream(base_url.clone(), client, directory, stream, pb, temp_files)?;
+        download_subtitle_stream(
+            base_url.clone(),
+            client,
+            directory,
+            stream,
+            subs_codec,
+            pb,
+            temp_files,
+        )?;
     }
 
     Ok(())
 }
+
+/// Parse a webvtt segment. Cues of hls segments are shifted by their `X-TIMESTAMP-MAP` header,
+/// relative to offset of first segment which is stored in `first_offset`.
+fn parse_vtt(data: &[u8], first_offset: &mut Option<f64>) -> Result<Subtitles> {
+    let text = String::from_utf8_lossy(data);
+    let mut subtitles = vtt_text_parser::parse(&text)?;
+
+    if let Some(offset) = timestamp_map_offset(&text) {
+        let first_offset = *first_offset.get_or_insert(offset);
+        subtitles.shift((offset - first_offset) as f32);
+    }
+
+    Ok(subtitles)
+}
+
+/// Offset in seconds of cue times from `X-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000` header.
+fn timestamp_map_offset(text: &str) -> Option<f64> {
+    let map = text
+        .lines()
+        .take_while(|x| !x.contains("-->"))
+        .find_map(|x| x.trim().strip_prefix("X-TIMESTAMP-MAP="))?;
+    let mut mpegts = None;
+    let mut local = None;
+
+    for part in map.split(',') {
+        match part.trim().split_once(':') {
+            Some(("MPEGTS", x)) => mpegts = x.parse::<u64>().ok(),
+            Some(("LOCAL", x)) => {
+                local = x
+                    .split(':')
+                    .try_fold(0.0, |total, y| y.parse::<f64>().map(|y| total * 60.0 + y))
+                    .ok()
+            }
+            _ => (),
+        }
+    }
+
+    Some(mpegts? as f64 / 90000.0 - local?)
+}
+
+/// Path of file where subtitles of a live stream are written once recording stops.
+pub(super) fn live_subtitles_path(
+    directory: &Option<PathBuf>,
+    stream: &MediaPlaylist,
+    subs_codec: &Option<Codec>,
+) -> String {
+    let ext = match (subs_codec, stream.codecs.as_deref()) {
+        (Some(Codec::Ass), _) => "ass",
+        (Some(Codec::Subrip), _) => "srt",
+        (Some(Codec::Webvtt), _) => "vtt",
+        (None, Some(x)) if x.starts_with("stpp") => "srt",
+        (None, _) => "vtt",
+    };
+
+    stream
+        .file_path(directory, ext)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Subtitles of a live stream, cues of segments are merged as they are recorded.
+pub(super) struct LiveSubtitles {
+    codec: Option<SubtitleType>,
+    /// Last fetched init segment of fragmented mp4 subtitles.
+    init: Vec<u8>,
+    subtitles: Subtitles,
+    /// `X-TIMESTAMP-MAP` offset of first webvtt segment.
+    vtt_offset: Option<f64>,
+}
+
+impl LiveSubtitles {
+    pub(super) fn new(stream: &MediaPlaylist) -> Self {
+        let codec = match stream.codecs.as_deref() {
+            Some("vtt") => Some(SubtitleType::VttText),
+            Some("wvtt") => Some(SubtitleType::Mp4Vtt),
+            Some(x) if x.starts_with("stpp") => Some(SubtitleType::Mp4Ttml),
+            _ => None,
+        };
+
+        Self {
+            codec,
+            init: vec![],
+            subtitles: Subtitles::default(),
+            vtt_offset: None,
+        }
+    }
+
+    /// Use init segment for parsing next fragmented mp4 segments.
+    pub(super) fn init(&mut self, data: Vec<u8>) {
+        self.init = data;
+    }
+
+    /// Merge cues of a segment.
+    pub(super) fn push(&mut self, data: &[u8]) -> Result<()> {
+        if self.codec.is_none() {
+            self.codec = if data.starts_with(b"WEBVTT") {
+                Some(SubtitleType::VttText)
+            } else if data.starts_with(b"<?xml") || data.starts_with(b"<tt") {
+                Some(SubtitleType::TtmlText)
+            } else {
+                bail!("could'nt determine subtitle codec.");
+            };
+        }
+
+        let subtitles = match self.codec {
+            Some(SubtitleType::Mp4Vtt) => {
+                Mp4VttParser::parse_init(&self.init)?.parse_media(data, None)?
+            }
+            Some(SubtitleType::Mp4Ttml) => {
+                Mp4TtmlParser::parse_init(&self.init)?.parse_media(data)?
+            }
+            Some(SubtitleType::TtmlText) => {
+                let xml = String::from_utf8_lossy(data);
+                ttml_text_parser::parse(&xml)
+                    .map_err(|x| anyhow!("couldn't parse xml string as ttml content ({}).", x))?
+                    .into_subtitles()
+            }
+            _ => parse_vtt(data, &mut self.vtt_offset)?,
+        };
+
+        self.subtitles.merge(subtitles);
+        Ok(())
+    }
+
+    /// Write merged cues into file, format is chosen by extension of file.
+    pub(super) fn finish(mut self, path: &str) -> Result<()> {
+        self.subtitles.sort();
+        let data = if path.ends_with(".ass") {
+            self.subtitles.as_ass()
+        } else if path.ends_with(".srt") {
+            self.subtitles.as_srt()
+        } else {
+            self.subtitles.as_vtt()
+        };
+        File::create(path)?.write_all(data.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamp_map_offsets() {
+        assert_eq!(
+            timestamp_map_offset(
+                "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000\n\n00:00:01.000 --> 00:00:02.000\nA\n"
+            ),
+            Some(10.0)
+        );
+        assert_eq!(
+            timestamp_map_offset("WEBVTT\nX-TIMESTAMP-MAP=LOCAL:00:01:00.500,MPEGTS:9000000\n"),
+            Some(39.5)
+        );
+        assert_eq!(
+            timestamp_map_offset("WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nA\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn shifts_segments_relative_to_first_segment() {
+        let mut offset = None;
+        let mut subtitles = Subtitles::default();
+
+        for (mpegts, cue) in [(900000, "A"), (1800000, "B"), (1800000, "B")] {
+            subtitles.merge(
+                parse_vtt(
+                    format!(
+                        "WEBVTT\nX-TIMESTAMP-MAP=MPEGTS:{},LOCAL:00:00:00.000\n\n00:00:00.000 --> 00:00:01.000\n{}\n",
+                        mpegts, cue
+                    )
+                    .as_bytes(),
+                    &mut offset,
+                )
+                .unwrap(),
+            );
+        }
+
+        let cues = subtitles
+            .cues()
+            .iter()
+            .map(|x| (x.start_time, x.payload.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(cues, vec![(0.0, "A"), (10.0, "B")]);
+    }
+}
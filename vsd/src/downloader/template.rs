@@ -0,0 +1,236 @@
+use crate::downloader::{InputMetadata, SelectedPlaylists};
+use anyhow::{bail, Result};
+use vsd_core::{playlist::MediaType, utils};
+
+const VARIABLES: [&str; 10] = [
+    "acodec",
+    "bandwidth",
+    "date",
+    "height",
+    "lang",
+    "resolution",
+    "time",
+    "title",
+    "vcodec",
+    "width",
+];
+
+/// Expand `{variable}` placeholders in output path using values derived from playlist.
+/// Variables whose values are unknown are expanded to an empty string and the separator
+/// following them is dropped, so that `{title}.{lang}.mkv` becomes `title.mkv`.
+/// Literal braces can be escaped as `{{` and `}}`.
+/// Values are sanitized to be valid file names, ascii only when `restrict_filenames` is true.
+pub fn format_output(
+    template: &str,
+    meta: &InputMetadata,
+    selected_playlists: &SelectedPlaylists,
+    restrict_filenames: bool,
+) -> Result<String> {
+    if !template.contains('{') {
+        return Ok(long_path(template.to_owned()));
+    }
+
+    let (video_audio_streams, _) = selected_playlists;
+    let video = video_audio_streams
+        .iter()
+        .find(|x| x.media_type == MediaType::Video || x.media_type == MediaType::Undefined);
+    let audio = video_audio_streams
+        .iter()
+        .find(|x| x.media_type == MediaType::Audio);
+    let now = chrono::Local::now();
+
+    let mut output = String::new();
+    let mut chars = template.chars().peekable();
+    let mut skip_separator = false;
+
+    while let Some(c) = chars.next() {
+        if skip_separator {
+            skip_separator = false;
+
+            if ['.', '_', '-', ' '].contains(&c) && (output.is_empty() || output.ends_with(c)) {
+                continue;
+            }
+        }
+
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                output.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                output.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(x) => name.push(x),
+                        None => bail!("unclosed variable in output template {}", template),
+                    }
+                }
+
+                let value = match name.as_str() {
+                    "acodec" => audio
+                        .or(video)
+                        .and_then(|x| x.codecs.as_ref())
+                        .and_then(|x| codec_name(x, false)),
+                    "bandwidth" => video
+                        .or(audio)
+                        .and_then(|x| x.bandwidth)
+                        .map(|x| format!("{}k", x / 1000)),
+                    "date" => Some(now.format("%Y-%m-%d").to_string()),
+                    "height" => video.and_then(|x| x.resolution).map(|x| x.1.to_string()),
+                    "lang" => audio
+                        .or(video)
+                        .and_then(|x| x.language.as_ref())
+                        .map(|x| x.to_owned()),
+                    "resolution" => video
+                        .and_then(|x| x.resolution)
+                        .map(|x| format!("{}p", x.1)),
+                    "time" => Some(now.format("%H-%M-%S").to_string()),
+                    "title" => meta.media_title(),
+                    "vcodec" => video
+                        .and_then(|x| x.codecs.as_ref())
+                        .and_then(|x| codec_name(x, true)),
+                    "width" => video.and_then(|x| x.resolution).map(|x| x.0.to_string()),
+                    _ => bail!(
+                        "unknown variable {{{}}} in output template (possible values: {})",
+                        name,
+                        VARIABLES.join(", ")
+                    ),
+                };
+
+                match value {
+                    Some(value) => {
+                        let value = utils::sanitize_filename(&value, restrict_filenames);
+
+                        if value.is_empty() {
+                            skip_separator = true;
+                        } else {
+                            output.push_str(&value);
+                        }
+                    }
+                    None => skip_separator = true,
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    Ok(long_path(output))
+}
+
+/// Paths longer than 260 characters can only be opened on windows using `\\?\` prefix.
+#[cfg(windows)]
+fn long_path(path: String) -> String {
+    let path_buf = std::path::PathBuf::from(&path);
+
+    if path.len() < 260 || path.contains("://") || path.starts_with(r"\\") {
+        return path;
+    }
+
+    let absolute = if path_buf.is_absolute() {
+        path_buf
+    } else {
+        match std::env::current_dir() {
+            Ok(x) => x.join(path_buf),
+            Err(_) => return path,
+        }
+    };
+
+    // Verbatim paths are not normalized by windows, collecting components drops `.` and
+    // converts separators to backslashes.
+    let absolute = absolute.components().collect::<std::path::PathBuf>();
+    format!(r"\\?\{}", absolute.to_string_lossy())
+}
+
+#[cfg(not(windows))]
+fn long_path(path: String) -> String {
+    path
+}
+
+/// Friendly codec name from RFC 6381 codecs string.
+fn codec_name(codecs: &str, video: bool) -> Option<String> {
+    codecs
+        .split(',')
+        .map(|x| x.trim().split('.').next().unwrap_or(""))
+        .find_map(|x| match (x, video) {
+            ("avc1" | "avc3", true) => Some("h264".to_owned()),
+            ("hvc1" | "hev1", true) => Some("h265".to_owned()),
+            ("dvh1" | "dvhe", true) => Some("dv".to_owned()),
+            ("vp09" | "vp9", true) => Some("vp9".to_owned()),
+            ("av01", true) => Some("av1".to_owned()),
+            ("mp4a", false) => Some("aac".to_owned()),
+            ("ac-3", false) => Some("ac3".to_owned()),
+            ("ec-3", false) => Some("eac3".to_owned()),
+            ("opus" | "Opus", false) => Some("opus".to_owned()),
+            ("flac" | "fLaC", false) => Some("flac".to_owned()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vsd_core::playlist::MediaPlaylist;
+
+    fn meta() -> InputMetadata {
+        InputMetadata {
+            pl_type: None,
+            text: String::new(),
+            title: Some("My Show".to_owned()),
+            url: "https://example.com/master.m3u8".parse().unwrap(),
+        }
+    }
+
+    fn playlists() -> SelectedPlaylists {
+        let video = MediaPlaylist {
+            codecs: Some("avc1.64001f,mp4a.40.2".to_owned()),
+            media_type: MediaType::Video,
+            resolution: Some((1920, 1080)),
+            ..Default::default()
+        };
+        (vec![video], vec![])
+    }
+
+    fn format(template: &str) -> Result<String> {
+        format_output(template, &meta(), &playlists(), false)
+    }
+
+    #[test]
+    fn expands_variables() {
+        assert_eq!(
+            format("{title}.{resolution}.{vcodec}.{acodec}.mkv").unwrap(),
+            "My Show.1080p.h264.aac.mkv"
+        );
+        assert_eq!(format("{width}x{height}.mp4").unwrap(), "1920x1080.mp4");
+        assert_eq!(format("output.mkv").unwrap(), "output.mkv");
+    }
+
+    #[test]
+    fn drops_separator_after_unknown_values() {
+        assert_eq!(format("{title}.{lang}.mkv").unwrap(), "My Show.mkv");
+        assert_eq!(format("{lang}_{title}.mkv").unwrap(), "My Show.mkv");
+    }
+
+    #[test]
+    fn escapes_braces() {
+        assert_eq!(format("{{{title}}}.mkv").unwrap(), "{My Show}.mkv");
+        assert_eq!(format("{{title}}.mkv").unwrap(), "{title}.mkv");
+    }
+
+    #[test]
+    fn rejects_invalid_variables() {
+        assert!(format("{title.mkv")
+            .unwrap_err()
+            .to_string()
+            .starts_with("unclosed variable"));
+        assert!(format("{foo}.mkv")
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown variable {foo}"));
+    }
+}
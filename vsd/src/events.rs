@@ -0,0 +1,101 @@
+use crate::downloader::StreamSizes;
+use serde_json::{json, Value};
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use vsd_core::{Progress, SegmentEvent};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Start emitting newline delimited json events, on stderr if stdout is used for writing stream.
+pub(crate) fn enable(to_stderr: bool) {
+    TO_STDERR.store(to_stderr, Ordering::SeqCst);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// Emit an event, `fields` should be a json object which are added alongside event name.
+pub(crate) fn emit(event: &str, fields: Value) {
+    if !enabled() {
+        return;
+    }
+
+    let mut line = json!({ "event": event });
+
+    if let (Some(object), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        object.extend(fields);
+    }
+
+    let _lock = LOCK.lock().unwrap();
+
+    if TO_STDERR.load(Ordering::SeqCst) {
+        let _ = writeln!(std::io::stderr(), "{}", line);
+    } else {
+        let mut stdout = std::io::stdout();
+        let _ = writeln!(stdout, "{}", line);
+        let _ = stdout.flush();
+    }
+}
+
+/// Emit a stage change event.
+pub(crate) fn stage(stage: &str) {
+    emit("stage", json!({ "stage": stage }));
+}
+
+/// Progress of a stream emitted as json events.
+pub(crate) struct JsonProgress {
+    pub(crate) downloaded_segments: AtomicUsize,
+    /// Sizes of all streams downloaded together with this stream.
+    pub(crate) sizes: Arc<StreamSizes>,
+    pub(crate) stream: usize,
+    pub(crate) timer: Instant,
+    pub(crate) total_segments: usize,
+}
+
+impl Progress for JsonProgress {
+    fn key(&self, kid: &str, _key: &str) {
+        emit("key", json!({ "kid": kid, "stream": self.stream }));
+    }
+
+    fn retry(&self, url: &str, reason: &str) {
+        emit(
+            "retry",
+            json!({ "reason": reason, "stream": self.stream, "url": url }),
+        );
+    }
+
+    fn segment(&self, event: &SegmentEvent) {
+        let elapsed_time = self.timer.elapsed().as_secs_f64();
+        let speed = if elapsed_time > 0.0 {
+            (event.stored as f64 / elapsed_time) as u64
+        } else {
+            0
+        };
+        let downloaded_segments = self.downloaded_segments.fetch_add(1, Ordering::SeqCst) + 1;
+        let (downloaded_bytes, total_bytes) = self.sizes.update(self.stream, event);
+
+        emit(
+            "segment",
+            json!({
+                "bytes": event.bytes,
+                "downloaded_bytes": downloaded_bytes,
+                "downloaded_segments": downloaded_segments,
+                "index": event.index,
+                "speed": speed,
+                "stream": self.stream,
+                "total_bytes": total_bytes,
+                "total_segments": self.total_segments,
+            }),
+        );
+    }
+}
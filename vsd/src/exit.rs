@@ -0,0 +1,76 @@
+use std::fmt;
+
+/// Exit codes of vsd, so that wrapper scripts can react to different kinds of failures.
+/// Invalid command line arguments exit with code 2 (same as clap).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ExitCode {
+    /// Any other error.
+    Failure = 1,
+    /// Playlist or segments couldn't be fetched.
+    Network = 3,
+    /// Streams are encrypted and cannot be decrypted with supplied keys.
+    Drm = 4,
+    /// Downloaded streams couldn't be muxed (or ffmpeg is missing).
+    Mux = 5,
+    /// Some of the downloads from multiple inputs or `--batch-file` failed.
+    Partial = 6,
+    /// User aborted a prompt or the download.
+    Aborted = 130,
+}
+
+impl ExitCode {
+    /// Exit code of error, determined by the first tagged or known error in its chain.
+    pub(crate) fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if let Some(tagged) = cause.downcast_ref::<Tagged>() {
+                return tagged.code;
+            }
+
+            if cause.is::<reqwest::Error>() || cause.is::<vsd_core::NetworkError>() {
+                return Self::Network;
+            }
+
+            if let Some(requestty::ErrorKind::Aborted | requestty::ErrorKind::Interrupted) =
+                cause.downcast_ref::<requestty::ErrorKind>()
+            {
+                return Self::Aborted;
+            }
+        }
+
+        Self::Failure
+    }
+
+    /// Tag error with this exit code, its message and causes stay the same.
+    pub(crate) fn tag(self, error: anyhow::Error) -> anyhow::Error {
+        anyhow::Error::new(Tagged { code: self, error })
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aborted => "aborted",
+            Self::Drm => "drm",
+            Self::Failure => "failure",
+            Self::Mux => "mux",
+            Self::Network => "network",
+            Self::Partial => "partial",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Tagged {
+    code: ExitCode,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Tagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl std::error::Error for Tagged {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
@@ -0,0 +1,143 @@
+use crate::config::Config;
+use anyhow::{anyhow, bail, Result};
+use kdam::term::Colorizer;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Playlist resolved by a site extractor.
+///
+/// Site extractors are external executables (or scripts with a shebang) which receive a webpage
+/// url as their only argument and print a json object on stdout, eg.
+/// `{"url": "https://cdn.example.com/master.m3u8", "headers": {"Referer": "https://example.com"}, "title": "Episode 1"}`.
+/// `headers` and `title` are optional. An extractor which doesn't support the url should
+/// print nothing and exit successfully, so that next extractor can be tried.
+#[derive(Deserialize)]
+pub(crate) struct Extraction {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) title: Option<String>,
+    pub(crate) url: String,
+}
+
+impl Extraction {
+    /// Headers in same format as `--header` flag values.
+    pub(crate) fn headers(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .flat_map(|(key, value)| [key.to_owned(), value.to_owned()])
+            .collect()
+    }
+}
+
+/// Resolve webpage url using extractors passed with `--extractor` flag followed by executables
+/// inside `~/.config/vsd/extractors` directory (in alphabetical order).
+/// Local files and urls which already point to a playlist are not passed to extractors.
+pub(crate) fn extract(extractors: &[PathBuf], input: &str) -> Result<Option<Extraction>> {
+    let is_webpage = (input.starts_with("http://") || input.starts_with("https://"))
+        && ![".m3u", ".m3u8", ".mpd", ".xml"]
+            .iter()
+            .any(|x| input.split('?').next().unwrap().ends_with(x));
+
+    if !is_webpage {
+        return Ok(None);
+    }
+
+    let mut extractors = extractors.to_vec();
+
+    if let Some(directory) = Config::directory().map(|x| x.join("extractors")) {
+        if directory.is_dir() {
+            let mut paths = std::fs::read_dir(directory)?
+                .flatten()
+                .map(|x| x.path())
+                .filter(|x| is_executable(x))
+                .collect::<Vec<_>>();
+            paths.sort();
+            extractors.extend(paths);
+        }
+    }
+
+    for extractor in extractors {
+        log::debug!(
+            "running extractor {} {}",
+            extractor.to_string_lossy(),
+            input
+        );
+        let output = Command::new(&extractor)
+            .arg(input)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|x| {
+                anyhow!(
+                    "couldn't execute extractor {} ({}).",
+                    extractor.to_string_lossy(),
+                    x
+                )
+            })?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        for line in stderr.lines() {
+            log::debug!("extractor: {}", line);
+        }
+
+        if !output.status.success() {
+            bail!(
+                "extractor {} failed with {} ({}).",
+                extractor.to_string_lossy(),
+                output.status,
+                stderr.lines().last().unwrap_or_default()
+            );
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        if stdout.trim().is_empty() {
+            continue;
+        }
+
+        let extraction = serde_json::from_str::<Extraction>(&stdout).map_err(|x| {
+            anyhow!(
+                "extractor {} printed invalid json ({}).",
+                extractor.to_string_lossy(),
+                x
+            )
+        })?;
+
+        eprintln!(
+            "  {} {} using {}",
+            "Extracted".colorize("bold green"),
+            extraction.url,
+            extractor
+                .file_name()
+                .unwrap_or(extractor.as_os_str())
+                .to_string_lossy()
+        );
+        return Ok(Some(extraction));
+    }
+
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .map(|x| {
+                ["bat", "cmd", "com", "exe"].contains(&x.to_string_lossy().to_lowercase().as_str())
+            })
+            .unwrap_or(false)
+}
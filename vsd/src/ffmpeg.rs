@@ -0,0 +1,123 @@
+use crate::utils;
+use anyhow::{anyhow, bail, Result};
+use kdam::term::Colorizer;
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// ffmpeg invocation settings.
+#[derive(Clone, Default)]
+pub(crate) struct Ffmpeg {
+    args: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl Ffmpeg {
+    /// Extra arguments are split on whitespaces, quotes can be used to keep whitespaces.
+    pub(crate) fn new(path: Option<PathBuf>, args: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            args: args.map(utils::split_args).transpose()?.unwrap_or_default(),
+            path,
+        })
+    }
+
+    /// Path to ffmpeg binary, either the one specified explicitly or found in PATH.
+    pub(crate) fn binary(&self) -> Option<PathBuf> {
+        if let Some(path) = &self.path {
+            return Some(path.to_owned());
+        }
+
+        utils::find_ffmpeg().map(|x| {
+            PathBuf::from(x).join(if cfg!(target_os = "windows") {
+                "ffmpeg.exe"
+            } else {
+                "ffmpeg"
+            })
+        })
+    }
+
+    /// Check that ffmpeg binary exists and can be executed before doing any work which requires it.
+    pub(crate) fn preflight(&self) -> Result<()> {
+        let binary = self.binary().ok_or_else(|| {
+            anyhow!(
+                "ffmpeg couldn't be found, it is required to continue further. Use {} flag to set its path.",
+                "--ffmpeg-path".colorize("bold green")
+            )
+        })?;
+
+        let output = Command::new(&binary)
+            .arg("-version")
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|x| anyhow!("couldn't execute {} ({}).", binary.to_string_lossy(), x))?;
+
+        if !output.status.success() {
+            bail!(
+                "{} -version exited with code {}, is it a valid ffmpeg binary?",
+                binary.to_string_lossy(),
+                output.status.code().unwrap_or(1)
+            );
+        }
+
+        let version = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+
+        if !version.starts_with("ffmpeg version") {
+            bail!("{} is not a valid ffmpeg binary.", binary.to_string_lossy());
+        }
+
+        eprintln!("      {} {}", "Found".colorize("bold green"), version);
+        Ok(())
+    }
+
+    /// Run ffmpeg, extra arguments are inserted just before the last argument (output path).
+    pub(crate) fn run(&self, mut args: Vec<String>) -> Result<()> {
+        let binary = self
+            .binary()
+            .ok_or_else(|| anyhow!("ffmpeg couldn't be found."))?;
+        let output = args.pop();
+        args.extend_from_slice(&self.args);
+        args.extend(output);
+
+        let command = format!(
+            "{} {}",
+            quote(&binary.to_string_lossy()),
+            args.iter().map(|x| quote(x)).collect::<Vec<_>>().join(" ")
+        );
+        log::info!("executing {}", command);
+        eprintln!("  {} {}", "Executing".colorize("bold cyan"), command);
+
+        let output = Command::new(binary)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()?;
+
+        // Output of ffmpeg is only kept in logs for diagnosing failures.
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            log::debug!("ffmpeg: {}", line);
+        }
+
+        if !output.status.success() {
+            bail!(
+                "ffmpeg exited with code {}",
+                output.status.code().unwrap_or(1)
+            )
+        }
+
+        Ok(())
+    }
+}
+
+fn quote(arg: &str) -> String {
+    if arg.contains(' ') {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_owned()
+    }
+}
@@ -1,3 +0,0 @@
-mod playlist;
-
-pub(crate) use playlist::{parse_as_master, push_segments};
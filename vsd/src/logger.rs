@@ -0,0 +1,90 @@
+use anyhow::Result;
+use kdam::term::Colorizer;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{fs::File, io::Write, path::Path, sync::Mutex};
+
+/// Logger which prints records to stderr and writes them to log file.
+/// Errors, warnings and info records are already shown as status messages, so only debug and
+/// trace records are printed to stderr and that too when verbosity is increased.
+struct Logger {
+    file: Option<Mutex<File>>,
+    /// Records of crates other than vsd (eg. reqwest) are only logged with -vvv.
+    other_crates: bool,
+    stderr: LevelFilter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && (self.other_crates || metadata.target().starts_with("vsd"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if record.level() >= Level::Debug && record.level() <= self.stderr {
+            eprintln!(
+                "{} {}",
+                format!("{:>11}", record.level().as_str().to_lowercase()).colorize("bold blue"),
+                record.args()
+            );
+        }
+
+        if let Some(file) = &self.file {
+            let _ = writeln!(
+                file.lock().unwrap(),
+                "{} {:5} {}: {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Initialize logger, `verbose` is number of times -v flag is used.
+/// Log file receives records upto debug level, or trace level with -vv.
+pub(crate) fn init(verbose: u8, log_file: Option<&Path>) -> Result<()> {
+    let stderr = match verbose {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let file = log_file.map(File::create).transpose()?.map(Mutex::new);
+
+    log::set_max_level(match (&file, stderr) {
+        (Some(_), LevelFilter::Trace) => LevelFilter::Trace,
+        (Some(_), _) => LevelFilter::Debug,
+        (None, x) => x,
+    });
+    log::set_boxed_logger(Box::new(Logger {
+        file,
+        other_crates: verbose >= 3,
+        stderr,
+    }))?;
+    Ok(())
+}
+
+/// Print a warning status message and also log it.
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        let message = format!($($arg)*);
+        log::warn!("{}", message);
+        eprintln!(
+            "    {} {}",
+            kdam::term::Colorizer::colorize("Warning", "bold yellow"),
+            message
+        );
+    }};
+}
+
+pub(crate) use warning;
@@ -1,14 +1,21 @@
 mod commands;
+mod config;
 mod cookie;
-mod dash;
 mod downloader;
-mod hls;
-mod merger;
-mod playlist;
+mod events;
+mod exit;
+mod extractor;
+mod ffmpeg;
+mod logger;
+mod mux;
+mod notify;
+mod remote;
 mod utils;
 
-use clap::{ColorChoice, Parser};
+use clap::{ColorChoice, CommandFactory, FromArgMatches};
 use commands::{Args, Commands};
+use config::Config;
+use exit::ExitCode;
 use kdam::{term, term::Colorizer};
 use requestty::symbols;
 use std::{
@@ -17,7 +24,10 @@ use std::{
 };
 
 fn run() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let config = Config::load()?;
+    let args = Args::from_arg_matches(&config.apply(Args::command()).get_matches())
+        .unwrap_or_else(|e| e.exit());
+    logger::init(args.verbose, args.log_file.as_deref())?;
 
     term::init(match args.color {
         ColorChoice::Always => true,
@@ -28,9 +38,16 @@ fn run() -> anyhow::Result<()> {
     match args.command {
         #[cfg(feature = "browser")]
         Commands::Capture(args) => args.execute()?,
+        Commands::Completions(args) => args.execute()?,
         Commands::Extract(args) => args.execute()?,
+        Commands::Manpage(args) => args.execute()?,
         Commands::Merge(args) => args.execute()?,
-        Commands::Save(args) => args.execute()?,
+        Commands::Probe(args) => args.execute()?,
+        Commands::Save(mut args) => {
+            args.client.header.splice(0..0, config.headers());
+            args.execute()?
+        }
+        Commands::SelfUpdate(args) => args.execute()?,
     }
 
     Ok(())
@@ -43,8 +60,14 @@ fn main() {
     symbols::set(symbols);
 
     if let Err(e) = run() {
+        let code = ExitCode::of(&e);
+        log::error!("{:?}", e);
+        events::emit(
+            "error",
+            serde_json::json!({ "code": code as i32, "kind": code.as_str(), "message": e.to_string() }),
+        );
         eprintln!("{}: {}", "error".colorize("bold red"), e);
-        process::exit(1);
+        process::exit(code as i32);
     }
 }
 
@@ -0,0 +1,193 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://www.rfc-editor.org/rfc/rfc8794
+    2. https://www.matroska.org/technical/elements.html
+
+*/
+
+pub(super) const EBML: u32 = 0x1A45DFA3;
+pub(super) const EBML_VERSION: u32 = 0x4286;
+pub(super) const EBML_READ_VERSION: u32 = 0x42F7;
+pub(super) const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+pub(super) const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+pub(super) const DOC_TYPE: u32 = 0x4282;
+pub(super) const DOC_TYPE_VERSION: u32 = 0x4287;
+pub(super) const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+pub(super) const VOID: u32 = 0xEC;
+
+pub(super) const SEGMENT: u32 = 0x18538067;
+pub(super) const SEEK_HEAD: u32 = 0x114D9B74;
+pub(super) const SEEK: u32 = 0x4DBB;
+pub(super) const SEEK_ID: u32 = 0x53AB;
+pub(super) const SEEK_POSITION: u32 = 0x53AC;
+
+pub(super) const INFO: u32 = 0x1549A966;
+pub(super) const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+pub(super) const DURATION: u32 = 0x4489;
+pub(super) const MUXING_APP: u32 = 0x4D80;
+pub(super) const WRITING_APP: u32 = 0x5741;
+pub(super) const TITLE: u32 = 0x7BA9;
+
+pub(super) const TRACKS: u32 = 0x1654AE6B;
+pub(super) const TRACK_ENTRY: u32 = 0xAE;
+pub(super) const TRACK_NUMBER: u32 = 0xD7;
+pub(super) const TRACK_UID: u32 = 0x73C5;
+pub(super) const TRACK_TYPE: u32 = 0x83;
+pub(super) const FLAG_LACING: u32 = 0x9C;
+pub(super) const FLAG_DEFAULT: u32 = 0x88;
+pub(super) const FLAG_FORCED: u32 = 0x55AA;
+pub(super) const LANGUAGE: u32 = 0x22B59C;
+pub(super) const LANGUAGE_BCP47: u32 = 0x22B59D;
+pub(super) const CODEC_ID: u32 = 0x86;
+pub(super) const CODEC_PRIVATE: u32 = 0x63A2;
+pub(super) const VIDEO: u32 = 0xE0;
+pub(super) const PIXEL_WIDTH: u32 = 0xB0;
+pub(super) const PIXEL_HEIGHT: u32 = 0xBA;
+pub(super) const AUDIO: u32 = 0xE1;
+pub(super) const SAMPLING_FREQUENCY: u32 = 0xB5;
+pub(super) const CHANNELS: u32 = 0x9F;
+
+pub(super) const CHAPTERS: u32 = 0x1043A770;
+pub(super) const EDITION_ENTRY: u32 = 0x45B9;
+pub(super) const EDITION_UID: u32 = 0x45BC;
+pub(super) const CHAPTER_ATOM: u32 = 0xB6;
+pub(super) const CHAPTER_UID: u32 = 0x73C4;
+pub(super) const CHAPTER_TIME_START: u32 = 0x91;
+pub(super) const CHAPTER_TIME_END: u32 = 0x92;
+pub(super) const CHAPTER_DISPLAY: u32 = 0x80;
+pub(super) const CHAP_STRING: u32 = 0x85;
+pub(super) const CHAP_LANGUAGE: u32 = 0x437C;
+
+pub(super) const CLUSTER: u32 = 0x1F43B675;
+pub(super) const TIMESTAMP: u32 = 0xE7;
+pub(super) const SIMPLE_BLOCK: u32 = 0xA3;
+pub(super) const BLOCK_GROUP: u32 = 0xA0;
+pub(super) const BLOCK: u32 = 0xA1;
+pub(super) const BLOCK_DURATION: u32 = 0x9B;
+
+pub(super) const CUES: u32 = 0x1C53BB6B;
+pub(super) const CUE_POINT: u32 = 0xBB;
+pub(super) const CUE_TIME: u32 = 0xB3;
+pub(super) const CUE_TRACK_POSITIONS: u32 = 0xB7;
+pub(super) const CUE_TRACK: u32 = 0xF7;
+pub(super) const CUE_CLUSTER_POSITION: u32 = 0xF1;
+
+/// Size value which marks an element of unknown size.
+pub(super) const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+pub(super) fn id(id: u32) -> Vec<u8> {
+    let bytes = id.to_be_bytes();
+    let skip = bytes.iter().take_while(|x| **x == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Encode an element data size as variable size integer.
+pub(super) fn size(size: u64) -> Vec<u8> {
+    for length in 1..=8 {
+        // All bits set to 1 are reserved for unknown size.
+        if size < (1 << (7 * length)) - 1 {
+            let mut bytes = size.to_be_bytes()[(8 - length)..].to_vec();
+            bytes[0] |= 1 << (8 - length);
+            return bytes;
+        }
+    }
+
+    panic!("ebml element size {} is too large", size);
+}
+
+/// Encode an element data size as variable size integer of exactly 8 bytes.
+/// Useful for elements whose sizes are patched later on.
+pub(super) fn size_8(size: u64) -> [u8; 8] {
+    let mut bytes = size.to_be_bytes();
+    bytes[0] = 0x01;
+    bytes
+}
+
+pub(super) fn element(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut bytes = self::id(id);
+    bytes.extend_from_slice(&size(data.len() as u64));
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+pub(super) fn master(id: u32, children: &[Vec<u8>]) -> Vec<u8> {
+    element(id, &children.concat())
+}
+
+pub(super) fn uint(id: u32, value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|x| **x == 0).count().min(7);
+    element(id, &bytes[skip..])
+}
+
+pub(super) fn float(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+pub(super) fn string(id: u32, value: &str) -> Vec<u8> {
+    element(id, value.as_bytes())
+}
+
+/// Void element of exactly `length` bytes (including header), length should be at least 2 bytes.
+pub(super) fn void(length: usize) -> Vec<u8> {
+    let mut bytes = self::id(VOID);
+
+    if length < 9 {
+        bytes.push(0x80 | (length - 2) as u8);
+    } else {
+        bytes.extend_from_slice(&size_8((length - 9) as u64));
+    }
+
+    bytes.resize(length, 0);
+    bytes
+}
+
+/// Track number encoded as variable size integer, used inside block headers.
+pub(super) fn track_number(number: u64) -> Vec<u8> {
+    size(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_sizes() {
+        assert_eq!(size(0), vec![0x80]);
+        assert_eq!(size(126), vec![0xFE]);
+        // Value with all bits set is reserved for unknown size.
+        assert_eq!(size(127), vec![0x40, 0x7F]);
+        assert_eq!(size(16382), vec![0x7F, 0xFE]);
+        assert_eq!(size(16383), vec![0x20, 0x3F, 0xFF]);
+        assert_eq!(
+            size((1 << 56) - 2),
+            vec![0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE]
+        );
+        assert_eq!(size_8(5), [0x01, 0, 0, 0, 0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn encodes_elements() {
+        assert_eq!(id(EBML), vec![0x1A, 0x45, 0xDF, 0xA3]);
+        assert_eq!(id(TRACK_NUMBER), vec![0xD7]);
+        assert_eq!(uint(TRACK_NUMBER, 0), vec![0xD7, 0x81, 0x00]);
+        assert_eq!(uint(TRACK_NUMBER, 256), vec![0xD7, 0x82, 0x01, 0x00]);
+        assert_eq!(string(CODEC_ID, "A_AAC"), b"\x86\x85A_AAC".to_vec());
+        assert_eq!(
+            master(TRACK_ENTRY, &[uint(TRACK_NUMBER, 1)]),
+            vec![0xAE, 0x83, 0xD7, 0x81, 0x01]
+        );
+    }
+
+    #[test]
+    fn encodes_void_elements() {
+        assert_eq!(void(2), vec![0xEC, 0x80]);
+        assert_eq!(void(8), vec![0xEC, 0x86, 0, 0, 0, 0, 0, 0]);
+
+        let void = void(12);
+        assert_eq!(void.len(), 12);
+        assert_eq!(&void[..9], &[0xEC, 0x01, 0, 0, 0, 0, 0, 0, 0x03]);
+    }
+}
@@ -0,0 +1,752 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://github.com/shaka-project/shaka-player/blob/main/lib/util/mp4_box_parsers.js
+    2. https://developer.apple.com/documentation/quicktime-file-format
+    3. ISO/IEC 14496-12 (ISO base media file format)
+
+*/
+
+use super::{input::GrowingFile, to_nanoseconds, Demuxer, Sample, Track, TrackKind};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+/// Returns true if data looks like the start of an mp4 file.
+pub(super) fn is_mp4(data: &[u8]) -> bool {
+    data.len() >= 8
+        && matches!(
+            &data[4..8],
+            b"ftyp" | b"styp" | b"moov" | b"moof" | b"sidx" | b"free"
+        )
+}
+
+/// Streaming demuxer for fragmented mp4 files.
+/// Samples are read fragment by fragment, so whole file is never loaded into memory.
+pub(super) struct Fmp4Reader {
+    defaults: HashMap<u32, TrackDefaults>,
+    next_decode_times: HashMap<u32, u64>,
+    reader: BufReader<GrowingFile>,
+    samples: VecDeque<Sample>,
+    tracks: Vec<Track>,
+}
+
+#[derive(Clone, Default)]
+struct TrackDefaults {
+    sample_duration: u32,
+    sample_size: u32,
+    sample_flags: u32,
+}
+
+impl Fmp4Reader {
+    pub(super) fn open(path: &Path, file: GrowingFile) -> Result<Self> {
+        let mut reader = Self {
+            defaults: HashMap::new(),
+            next_decode_times: HashMap::new(),
+            reader: BufReader::new(file),
+            samples: VecDeque::new(),
+            tracks: vec![],
+        };
+
+        while reader.tracks.is_empty() {
+            let (name, data) = match reader.read_box(false)? {
+                Some(x) => x,
+                None => bail!("moov box not found in {}", path.to_string_lossy()),
+            };
+
+            if name == *b"moov" {
+                reader.parse_moov(&data)?;
+            } else if name == *b"moof" {
+                bail!(
+                    "moof box found before moov box in {}",
+                    path.to_string_lossy()
+                );
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// Read next top level box, including its header.
+    /// Data of boxes other than moov and moof is only read if `full` is true.
+    fn read_box(&mut self, full: bool) -> Result<Option<([u8; 4], Vec<u8>)>> {
+        let mut header = [0; 8];
+
+        match self.reader.read_exact(&mut header) {
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut name = [0; 4];
+        name.copy_from_slice(&header[4..8]);
+        let mut data = header.to_vec();
+
+        // Skip boxes which are never used to avoid reading them into memory.
+        // Samples are read from mdat boxes only when they follow a moof box.
+        let skip = !full && !matches!(&name, b"moov" | b"moof");
+
+        let size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => {
+                // Box extends till end of file, so there is nothing after it.
+                if skip {
+                    return Ok(None);
+                }
+
+                self.reader.read_to_end(&mut data)?;
+                return Ok(Some((name, data)));
+            }
+            1 => {
+                let mut large_size = [0; 8];
+                self.reader.read_exact(&mut large_size)?;
+                data.extend_from_slice(&large_size);
+                u64::from_be_bytes(large_size)
+            }
+            x => x as u64,
+        };
+
+        if size < data.len() as u64 {
+            bail!("invalid mp4 box size {}", size);
+        }
+
+        if skip {
+            self.reader
+                .seek_relative((size - data.len() as u64) as i64)?;
+            return Ok(Some((name, vec![])));
+        }
+
+        // Data is read as it arrives instead of allocating box size upfront,
+        // so that a corrupt size doesn't allocate too much memory.
+        let remaining = size - data.len() as u64;
+
+        if (&mut self.reader).take(remaining).read_to_end(&mut data)? as u64 != remaining {
+            bail!("{} box is truncated", String::from_utf8_lossy(&name));
+        }
+
+        Ok(Some((name, data)))
+    }
+
+    fn parse_moov(&mut self, data: &[u8]) -> Result<()> {
+        for (name, payload) in boxes(payload(data)) {
+            match &name {
+                b"trak" => {
+                    if let Some(track) = parse_trak(payload)? {
+                        self.tracks.push(track);
+                    }
+                }
+                b"mvex" => {
+                    for (name, payload) in boxes(payload) {
+                        if &name == b"trex" {
+                            self.defaults.insert(
+                                read_u32(payload, 4)?,
+                                TrackDefaults {
+                                    sample_duration: read_u32(payload, 12)?,
+                                    sample_size: read_u32(payload, 16)?,
+                                    sample_flags: read_u32(payload, 20)?,
+                                },
+                            );
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if self.tracks.is_empty() {
+            bail!("no supported tracks found inside moov box");
+        }
+
+        Ok(())
+    }
+
+    fn parse_fragment(&mut self, data: &[u8]) -> Result<()> {
+        let moof = boxes(data)
+            .next()
+            .map(|x| x.1)
+            .ok_or_else(|| anyhow!("empty moof box"))?;
+        let mdat_data_start = boxes_with_offsets(data)
+            .find(|x| &x.0 == b"mdat")
+            .map(|x| x.1)
+            .ok_or_else(|| anyhow!("mdat box not found after moof box"))?;
+
+        for (name, traf) in boxes(moof) {
+            if &name != b"traf" {
+                continue;
+            }
+
+            let mut track_id = 0;
+            let mut defaults = TrackDefaults::default();
+            let mut base_decode_time = None;
+            let mut data_offset = mdat_data_start;
+
+            for (name, payload) in boxes(traf) {
+                match &name {
+                    b"tfhd" => {
+                        let flags = read_u32(payload, 0)? & 0xFFFFFF;
+                        track_id = read_u32(payload, 4)?;
+                        defaults = self.defaults.get(&track_id).cloned().unwrap_or_default();
+                        let mut pos = 8;
+
+                        if flags & 0x000001 != 0 {
+                            // Base data offset is absolute in file, which cannot be resolved
+                            // while streaming. Packagers almost always use default-base-is-moof.
+                            pos += 8;
+                        }
+
+                        if flags & 0x000002 != 0 {
+                            pos += 4;
+                        }
+
+                        if flags & 0x000008 != 0 {
+                            defaults.sample_duration = read_u32(payload, pos)?;
+                            pos += 4;
+                        }
+
+                        if flags & 0x000010 != 0 {
+                            defaults.sample_size = read_u32(payload, pos)?;
+                            pos += 4;
+                        }
+
+                        if flags & 0x000020 != 0 {
+                            defaults.sample_flags = read_u32(payload, pos)?;
+                        }
+                    }
+                    b"tfdt" => {
+                        base_decode_time = Some(if payload.first() == Some(&1) {
+                            read_u64(payload, 4)?
+                        } else {
+                            read_u32(payload, 4)? as u64
+                        });
+                    }
+                    b"trun" => {
+                        let track = match self.tracks.iter().find(|x| x.id == track_id) {
+                            Some(x) => x,
+                            None => continue,
+                        };
+                        let timescale = track.timescale;
+                        let is_video = track.kind == TrackKind::Video;
+
+                        let version = payload.first().copied().unwrap_or_default();
+                        let flags = read_u32(payload, 0)? & 0xFFFFFF;
+                        let sample_count = read_u32(payload, 4)?;
+                        let mut pos = 8;
+
+                        if flags & 0x000001 != 0 {
+                            let offset = read_u32(payload, pos)? as i32;
+
+                            if offset < 0 {
+                                bail!("negative trun data offset ({})", offset);
+                            }
+
+                            data_offset = offset as usize;
+                            pos += 4;
+                        }
+
+                        let mut first_sample_flags = None;
+
+                        if flags & 0x000004 != 0 {
+                            first_sample_flags = Some(read_u32(payload, pos)?);
+                            pos += 4;
+                        }
+
+                        // Sample count is checked against size of box and fragment,
+                        // so that a corrupt count doesn't allocate too many samples.
+                        let sample_fields = [0x000100, 0x000200, 0x000400, 0x000800]
+                            .iter()
+                            .filter(|x| flags & *x != 0)
+                            .count();
+
+                        if sample_count as usize > data.len()
+                            || sample_count as usize * sample_fields * 4
+                                > payload.len().saturating_sub(pos)
+                        {
+                            bail!("invalid trun sample count ({})", sample_count);
+                        }
+
+                        let mut decode_time = base_decode_time.take().unwrap_or_else(|| {
+                            *self.next_decode_times.get(&track_id).unwrap_or(&0)
+                        });
+
+                        for i in 0..sample_count {
+                            let mut duration = defaults.sample_duration;
+                            let mut size = defaults.sample_size;
+                            let mut sample_flags = defaults.sample_flags;
+                            let mut composition_offset = 0;
+
+                            if i == 0 {
+                                if let Some(x) = first_sample_flags {
+                                    sample_flags = x;
+                                }
+                            }
+
+                            if flags & 0x000100 != 0 {
+                                duration = read_u32(payload, pos)?;
+                                pos += 4;
+                            }
+
+                            if flags & 0x000200 != 0 {
+                                size = read_u32(payload, pos)?;
+                                pos += 4;
+                            }
+
+                            if flags & 0x000400 != 0 {
+                                sample_flags = read_u32(payload, pos)?;
+                                pos += 4;
+                            }
+
+                            if flags & 0x000800 != 0 {
+                                let value = read_u32(payload, pos)?;
+                                composition_offset = if version == 0 {
+                                    value as i64
+                                } else {
+                                    value as i32 as i64
+                                };
+                                pos += 4;
+                            }
+
+                            let end = data_offset.saturating_add(size as usize);
+
+                            if end > data.len() {
+                                bail!("sample data is out of mdat box bounds");
+                            }
+
+                            self.samples.push_back(Sample {
+                                data: data[data_offset..end].to_vec(),
+                                decode_timestamp: to_nanoseconds(decode_time as i64, timescale),
+                                duration: to_nanoseconds(duration as i64, timescale) as u64,
+                                // sample_is_non_sync_sample flag
+                                keyframe: !is_video || (sample_flags & 0x00010000) == 0,
+                                timestamp: to_nanoseconds(
+                                    decode_time as i64 + composition_offset,
+                                    timescale,
+                                ),
+                                track_id,
+                            });
+
+                            data_offset = end;
+                            decode_time += duration as u64;
+                        }
+
+                        self.next_decode_times.insert(track_id, decode_time);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Demuxer for Fmp4Reader {
+    fn next_sample(&mut self) -> Result<Option<Sample>> {
+        while self.samples.is_empty() {
+            let (name, mut data) = match self.read_box(false)? {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            if name == *b"moof" {
+                // Sample offsets are relative to moof box, so keep everything till mdat together.
+                loop {
+                    match self.read_box(true)? {
+                        Some((name, child)) => {
+                            let is_mdat = name == *b"mdat";
+                            data.extend_from_slice(&child);
+
+                            if is_mdat {
+                                break;
+                            }
+                        }
+                        None => bail!("mdat box not found after moof box"),
+                    }
+                }
+
+                self.parse_fragment(&data)?;
+            }
+        }
+
+        Ok(self.samples.pop_front())
+    }
+
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+}
+
+fn parse_trak(data: &[u8]) -> Result<Option<Track>> {
+    let mut id = 0;
+    let mut mdia = None;
+
+    for (name, payload) in boxes(data) {
+        match &name {
+            b"tkhd" => {
+                id = read_u32(payload, if payload.first() == Some(&1) { 20 } else { 12 })?;
+            }
+            b"mdia" => mdia = Some(payload),
+            _ => (),
+        }
+    }
+
+    let mdia = match mdia {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+
+    let mut kind = None;
+    let mut language = None;
+    let mut timescale = 0;
+    let mut stsd = None;
+
+    for (name, payload) in boxes(mdia) {
+        match &name {
+            b"mdhd" => {
+                let (pos, duration_size) = if payload.first() == Some(&1) {
+                    (20, 8)
+                } else {
+                    (12, 4)
+                };
+                timescale = read_u32(payload, pos)?;
+
+                // Packed ISO-639-2/T language code.
+                let code = read_u16(payload, pos + 4 + duration_size)?;
+                let code = String::from_utf8(vec![
+                    (((code >> 10) & 0x1F) + 0x60) as u8,
+                    (((code >> 5) & 0x1F) + 0x60) as u8,
+                    ((code & 0x1F) + 0x60) as u8,
+                ])
+                .unwrap_or_default();
+
+                if code.chars().all(|x| x.is_ascii_lowercase()) && code != "und" {
+                    language = Some(code);
+                }
+            }
+            b"hdlr" => {
+                kind = match payload.get(8..12) {
+                    Some(b"vide") => Some(TrackKind::Video),
+                    Some(b"soun") => Some(TrackKind::Audio),
+                    _ => None,
+                };
+            }
+            b"minf" => {
+                stsd = boxes(payload)
+                    .find(|x| &x.0 == b"stbl")
+                    .and_then(|x| boxes(x.1).find(|y| &y.0 == b"stsd"))
+                    .map(|x| x.1);
+            }
+            _ => (),
+        }
+    }
+
+    let (kind, stsd) = match (kind, stsd) {
+        (Some(kind), Some(stsd)) => (kind, stsd),
+        _ => return Ok(None),
+    };
+
+    if timescale == 0 {
+        bail!("track {} has invalid timescale", id);
+    }
+
+    // Skip version, flags and entry count.
+    let entries = stsd.get(8..).unwrap_or_default();
+    let (entry_name, entry_start, entry_end) = boxes_with_offsets(entries)
+        .next()
+        .ok_or_else(|| anyhow!("track {} has no sample description", id))?;
+    let entry = &entries[entry_start..entry_end];
+    let sample_entry = entries[..entry_end].to_vec();
+
+    let mut track = Track {
+        channels: None,
+        codec_id: "",
+        codec_private: None,
+        default: false,
+        forced: false,
+        height: None,
+        id,
+        kind,
+        language,
+        sample_entry: Some(sample_entry),
+        sample_rate: None,
+        timescale,
+        width: None,
+    };
+
+    match kind {
+        TrackKind::Video => {
+            if entry.len() < 78 {
+                bail!("invalid visual sample entry");
+            }
+
+            track.width = Some(u16::from_be_bytes([entry[24], entry[25]]) as u64);
+            track.height = Some(u16::from_be_bytes([entry[26], entry[27]]) as u64);
+
+            let (codec_id, config) = match &entry_name {
+                b"avc1" | b"avc3" => ("V_MPEG4/ISO/AVC", b"avcC"),
+                b"hvc1" | b"hev1" => ("V_MPEGH/ISO/HEVC", b"hvcC"),
+                x => bail!(
+                    "{} video codec is not supported",
+                    String::from_utf8_lossy(x)
+                ),
+            };
+
+            track.codec_id = codec_id;
+            track.codec_private = Some(
+                boxes(&entry[78..])
+                    .find(|x| &x.0 == config)
+                    .map(|x| x.1.to_vec())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "{} box not found in sample entry",
+                            String::from_utf8_lossy(config)
+                        )
+                    })?,
+            );
+        }
+        TrackKind::Audio => {
+            if entry.len() < 28 {
+                bail!("invalid audio sample entry");
+            }
+
+            track.channels = Some(u16::from_be_bytes([entry[16], entry[17]]) as u64);
+            track.sample_rate = Some(u16::from_be_bytes([entry[24], entry[25]]) as f64);
+
+            // Quicktime sound sample description versions.
+            let children_start = match u16::from_be_bytes([entry[8], entry[9]]) {
+                1 => 44,
+                2 => 64,
+                _ => 28,
+            };
+
+            match &entry_name {
+                b"mp4a" => {
+                    track.codec_id = "A_AAC";
+                    track.codec_private = boxes(entry.get(children_start..).unwrap_or(&[]))
+                        .find(|x| &x.0 == b"esds")
+                        .and_then(|x| audio_specific_config(x.1));
+
+                    if track.codec_private.is_none() {
+                        bail!("aac audio specific config not found in esds box");
+                    }
+                }
+                b"ac-3" => track.codec_id = "A_AC3",
+                b"ec-3" => track.codec_id = "A_EAC3",
+                x => bail!(
+                    "{} audio codec is not supported",
+                    String::from_utf8_lossy(x)
+                ),
+            }
+        }
+        TrackKind::Subtitles => unreachable!(),
+    }
+
+    Ok(Some(track))
+}
+
+/// Extract decoder specific info from esds box.
+fn audio_specific_config(esds: &[u8]) -> Option<Vec<u8>> {
+    // Skip version and flags.
+    let mut data = esds.get(4..)?;
+
+    // ES_Descriptor (0x03) > DecoderConfigDescriptor (0x04) > DecoderSpecificInfo (0x05)
+    while !data.is_empty() {
+        let tag = data[0];
+        let mut pos = 1;
+        let mut length = 0;
+
+        for _ in 0..4 {
+            let byte = *data.get(pos)?;
+            pos += 1;
+            length = (length << 7) | (byte & 0x7F) as usize;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        match tag {
+            // ES_ID (2) + flags (1), optional fields are not used by packagers.
+            0x03 => data = data.get((pos + 3)..)?,
+            // objectTypeIndication (1) + streamType (1) + bufferSizeDB (3) + maxBitrate (4) + avgBitrate (4)
+            0x04 => data = data.get((pos + 13)..)?,
+            0x05 => return data.get(pos..(pos + length)).map(|x| x.to_vec()),
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+fn payload(data: &[u8]) -> &[u8] {
+    boxes(data).next().map(|x| x.1).unwrap_or(&[])
+}
+
+/// Iterate over child boxes as (name, payload).
+fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    boxes_with_offsets(data).map(move |(name, start, end)| (name, &data[start..end]))
+}
+
+/// Iterate over child boxes as (name, payload start, payload end).
+fn boxes_with_offsets(data: &[u8]) -> impl Iterator<Item = ([u8; 4], usize, usize)> + '_ {
+    let mut pos = 0;
+
+    std::iter::from_fn(move || {
+        if pos + 8 > data.len() {
+            return None;
+        }
+
+        let mut size = be_u32(&data[pos..]) as usize;
+        let mut name = [0; 4];
+        name.copy_from_slice(&data[(pos + 4)..(pos + 8)]);
+        let mut header_size = 8;
+
+        if size == 1 {
+            if pos + 16 > data.len() {
+                return None;
+            }
+
+            size = be_u64(&data[(pos + 8)..]) as usize;
+            header_size = 16;
+        } else if size == 0 {
+            size = data.len() - pos;
+        }
+
+        if size < header_size || size > data.len() - pos {
+            return None;
+        }
+
+        let item = (name, pos + header_size, pos + size);
+        pos += size;
+        Some(item)
+    })
+}
+
+/// Read big endian u16 at position of box payload, truncated payloads are an error.
+fn read_u16(payload: &[u8], pos: usize) -> Result<u16> {
+    payload
+        .get(pos..pos + 2)
+        .map(|x| u16::from_be_bytes([x[0], x[1]]))
+        .ok_or_else(|| anyhow!("box is truncated"))
+}
+
+/// Read big endian u32 at position of box payload, truncated payloads are an error.
+fn read_u32(payload: &[u8], pos: usize) -> Result<u32> {
+    payload
+        .get(pos..pos + 4)
+        .map(be_u32)
+        .ok_or_else(|| anyhow!("box is truncated"))
+}
+
+/// Read big endian u64 at position of box payload, truncated payloads are an error.
+fn read_u64(payload: &[u8], pos: usize) -> Result<u64> {
+    payload
+        .get(pos..pos + 8)
+        .map(be_u64)
+        .ok_or_else(|| anyhow!("box is truncated"))
+}
+
+fn be_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn be_u64(data: &[u8]) -> u64 {
+    u64::from_be_bytes([
+        data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(name);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn iterates_child_boxes() {
+        let mut data = mp4_box(b"free", &[1, 2]);
+        // Large size box.
+        data.extend_from_slice(&1_u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&19_u64.to_be_bytes());
+        data.extend_from_slice(&[3, 4, 5]);
+        assert_eq!(
+            boxes(&data).collect::<Vec<_>>(),
+            vec![(*b"free", &[1, 2][..]), (*b"mdat", &[3, 4, 5][..])]
+        );
+
+        // Size extending till end of data.
+        let mut data = 0_u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&[6, 7]);
+        assert_eq!(
+            boxes(&data).collect::<Vec<_>>(),
+            vec![(*b"mdat", &[6, 7][..])]
+        );
+    }
+
+    #[test]
+    fn stops_at_invalid_boxes() {
+        let mut data = mp4_box(b"free", &[1]);
+        data.extend_from_slice(&100_u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        assert_eq!(boxes(&data).count(), 1);
+
+        let mut data = 4_u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"free");
+        assert_eq!(boxes(&data).count(), 0);
+
+        let mut data = 1_u32.to_be_bytes().to_vec();
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(boxes(&data).count(), 0);
+    }
+
+    #[test]
+    fn reads_integers() {
+        let data = [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2, 0];
+        assert_eq!(read_u16(&data, 2).unwrap(), 256);
+        assert_eq!(read_u32(&data, 0).unwrap(), 256);
+        assert_eq!(read_u64(&data, 4).unwrap(), 512);
+        assert!(read_u16(&data, 11).is_err());
+        assert!(read_u32(&data, 10).is_err());
+        assert!(read_u64(&data, 5).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_trak() {
+        let trak = mp4_box(b"tkhd", &[0, 0, 0, 0, 0, 0]);
+        assert!(parse_trak(&trak).is_err());
+
+        let mut trak = mp4_box(b"tkhd", &[0; 20]);
+        trak.extend_from_slice(&mp4_box(b"mdia", &mp4_box(b"mdhd", &[1, 0, 0, 0])));
+        assert!(parse_trak(&trak).is_err());
+
+        let mut mdia = mp4_box(b"mdhd", &[0; 24]);
+        mdia[8 + 15] = 1; // timescale
+        mdia.extend_from_slice(&mp4_box(b"hdlr", &[0, 0, 0, 0, 0, 0, 0, 0, b'v', b'i']));
+        let mut trak = mp4_box(b"tkhd", &[0; 20]);
+        trak.extend_from_slice(&mp4_box(b"mdia", &mdia));
+        assert!(parse_trak(&trak).unwrap().is_none());
+    }
+
+    #[test]
+    fn extracts_audio_specific_config() {
+        let esds = [
+            0, 0, 0, 0, // version and flags
+            0x03, 0x80, 0x80, 0x80, 0x19, 0, 1, 0, // ES_Descriptor
+            0x04, 0x11, 0x40, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, // DecoderConfigDescriptor
+            0x05, 0x02, 0x11, 0x90, // DecoderSpecificInfo
+        ];
+        assert_eq!(audio_specific_config(&esds), Some(vec![0x11, 0x90]));
+        assert_eq!(audio_specific_config(&esds[..30]), None);
+    }
+}
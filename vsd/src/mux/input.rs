@@ -0,0 +1,262 @@
+use super::{
+    fmp4::{self, Fmp4Reader},
+    ts::{self, TsReader},
+    Demuxer, Sample, Track, TrackKind,
+};
+use crate::downloader::Stream;
+use anyhow::{bail, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use vsd_core::playlist::MediaType;
+
+/// Input file which can be read while it is still being written by downloader.
+/// Reaching its end waits for more data until `downloaded` becomes true.
+pub(super) struct GrowingFile {
+    downloaded: Option<Arc<AtomicBool>>,
+    file: File,
+}
+
+impl GrowingFile {
+    fn open(path: &Path, downloaded: Option<Arc<AtomicBool>>) -> Result<Self> {
+        Ok(Self {
+            downloaded,
+            file: File::open(path)?,
+        })
+    }
+}
+
+impl Read for GrowingFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            // Flag is checked before reading, so that data written just before it was set isn't missed.
+            let downloaded = self
+                .downloaded
+                .as_ref()
+                .map(|x| x.load(Ordering::SeqCst))
+                .unwrap_or(true);
+            let size = self.file.read(buf)?;
+
+            if size > 0 || downloaded || buf.is_empty() {
+                return Ok(size);
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+impl Seek for GrowingFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+use vsd_mp4::text::vtt_text_parser;
+
+enum Source {
+    Media {
+        demuxer: Box<dyn Demuxer>,
+        indexes: HashMap<u32, usize>,
+    },
+    Text {
+        index: usize,
+        samples: VecDeque<Sample>,
+    },
+}
+
+impl Source {
+    /// Next sample along with index of its track in output file.
+    fn next(&mut self) -> Result<Option<(usize, Sample)>> {
+        match self {
+            Self::Media { demuxer, indexes } => {
+                while let Some(sample) = demuxer.next_sample()? {
+                    if let Some(index) = indexes.get(&sample.track_id) {
+                        return Ok(Some((*index, sample)));
+                    }
+                }
+
+                Ok(None)
+            }
+            Self::Text { index, samples } => Ok(samples.pop_front().map(|x| (*index, x))),
+        }
+    }
+}
+
+/// Downloaded streams opened together for muxing.
+/// Samples of all streams are read interleaved by their decode timestamps.
+pub(super) struct Inputs {
+    heads: Vec<Option<(usize, Sample)>>,
+    offset: i64,
+    sources: Vec<Source>,
+    pub(super) tracks: Vec<Track>,
+}
+
+impl Inputs {
+    /// Open fragmented mp4 or mpeg-ts video/audio streams and webvtt/subrip subtitle streams.
+    /// Subtitle streams are only allowed if `subtitles` is true.
+    /// Video/audio streams are followed as they grow until `downloaded` becomes true, if it is set.
+    pub(super) fn open(
+        streams: &[&Stream],
+        subtitles: bool,
+        downloaded: Option<Arc<AtomicBool>>,
+    ) -> Result<Self> {
+        let mut sources = vec![];
+        let mut tracks = vec![];
+
+        for stream in streams {
+            let path = Path::new(&stream.file_path);
+
+            if stream.media_type == MediaType::Subtitles {
+                let ext = path
+                    .extension()
+                    .map(|x| x.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                if !subtitles || (ext != "vtt" && ext != "srt") {
+                    bail!("{} subtitles cannot be muxed natively", ext);
+                }
+
+                let subtitles = vtt_text_parser::parse(&std::fs::read_to_string(path)?)?;
+                let samples = subtitles
+                    .cues()
+                    .iter()
+                    .map(|x| {
+                        let timestamp = (x.start_time as f64 * 1_000_000_000.0) as i64;
+
+                        Sample {
+                            data: x.payload.as_bytes().to_vec(),
+                            decode_timestamp: timestamp,
+                            duration: ((x.end_time - x.start_time).max(0.0) as f64
+                                * 1_000_000_000.0) as u64,
+                            keyframe: true,
+                            timestamp,
+                            track_id: 0,
+                        }
+                    })
+                    .collect();
+
+                tracks.push(Track {
+                    channels: None,
+                    codec_id: "S_TEXT/UTF8",
+                    codec_private: None,
+                    default: false,
+                    forced: stream.forced,
+                    height: None,
+                    id: 0,
+                    kind: TrackKind::Subtitles,
+                    language: stream.language.clone(),
+                    sample_entry: None,
+                    sample_rate: None,
+                    timescale: 1000,
+                    width: None,
+                });
+                sources.push(Source::Text {
+                    index: tracks.len() - 1,
+                    samples,
+                });
+                continue;
+            }
+
+            let demuxer = open_demuxer(path, downloaded.clone())?;
+            let mut indexes = HashMap::new();
+
+            for mut track in demuxer.tracks().iter().cloned() {
+                if stream.language.is_some() {
+                    track.language = stream.language.clone();
+                }
+
+                indexes.insert(track.id, tracks.len());
+                tracks.push(track);
+            }
+
+            sources.push(Source::Media { demuxer, indexes });
+        }
+
+        // First track of each kind is marked as default, except forced subtitles.
+        for kind in [TrackKind::Video, TrackKind::Audio, TrackKind::Subtitles] {
+            if let Some(track) = tracks.iter_mut().find(|x| x.kind == kind && !x.forced) {
+                track.default = true;
+            }
+        }
+
+        let heads = sources
+            .iter_mut()
+            .map(|x| x.next())
+            .collect::<Result<Vec<_>>>()?;
+
+        // Video and audio timestamps are shifted so that output starts from zero.
+        let offset = heads
+            .iter()
+            .zip(&sources)
+            .filter(|(_, source)| matches!(source, Source::Media { .. }))
+            .filter_map(|(head, _)| head.as_ref().map(|x| x.1.decode_timestamp))
+            .min()
+            .unwrap_or(0);
+
+        Ok(Self {
+            heads,
+            offset,
+            sources,
+            tracks,
+        })
+    }
+
+    /// Next sample in decode order along with index of its track.
+    pub(super) fn next(&mut self) -> Result<Option<(usize, Sample)>> {
+        let offset = self.offset;
+        let sources = &self.sources;
+
+        let index = self
+            .heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, head)| head.as_ref().map(|x| (i, x)))
+            .min_by_key(|(i, (_, sample))| match sources[*i] {
+                Source::Media { .. } => sample.decode_timestamp - offset,
+                Source::Text { .. } => sample.decode_timestamp,
+            })
+            .map(|x| x.0);
+
+        let index = match index {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let (track, mut sample) = self.heads[index].take().unwrap();
+        self.heads[index] = self.sources[index].next()?;
+
+        if let Source::Media { .. } = self.sources[index] {
+            sample.decode_timestamp -= offset;
+            sample.timestamp -= offset;
+        }
+
+        Ok(Some((track, sample)))
+    }
+}
+
+fn open_demuxer(path: &Path, downloaded: Option<Arc<AtomicBool>>) -> Result<Box<dyn Demuxer>> {
+    let mut header = vec![];
+    GrowingFile::open(path, downloaded.clone())?
+        .take(ts::PACKET_SIZE as u64 * 2)
+        .read_to_end(&mut header)?;
+    let file = GrowingFile::open(path, downloaded)?;
+
+    if fmp4::is_mp4(&header) {
+        Ok(Box::new(Fmp4Reader::open(path, file)?))
+    } else if ts::is_ts(&header) {
+        Ok(Box::new(TsReader::open(path, file)?))
+    } else {
+        bail!(
+            "{} is neither a fragmented mp4 nor a mpeg-ts file",
+            path.to_string_lossy()
+        );
+    }
+}
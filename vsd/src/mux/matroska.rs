@@ -0,0 +1,298 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://www.matroska.org/technical/elements.html
+    2. https://www.matroska.org/technical/basics.html
+    3. https://www.matroska.org/technical/codec_specs.html
+
+*/
+
+use super::{ebml, input::Inputs, language, Metadata, Track, TrackKind};
+use crate::downloader::Stream;
+use anyhow::Result;
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+/// Bytes reserved at the start of segment for seek head, which is written at the end.
+const SEEK_HEAD_SIZE: usize = 128;
+
+/// Mux fragmented mp4 or mpeg-ts video/audio streams and webvtt/subrip subtitle streams into
+/// a matroska file, without requiring ffmpeg. An error is returned for inputs which cannot be
+/// muxed natively, in which case callers should fall back to ffmpeg.
+/// Input streams are followed as they grow until `downloaded` becomes true, if it is set,
+/// so that output can be written (and played) while streams are still being downloaded.
+pub(crate) fn mux_matroska(
+    streams: &[&Stream],
+    output: &str,
+    metadata: &Metadata,
+    downloaded: Option<Arc<AtomicBool>>,
+) -> Result<()> {
+    let chapters = &metadata.chapters;
+    let mut inputs = Inputs::open(streams, true, downloaded)?;
+    let tracks = inputs.tracks.clone();
+
+    let has_video = tracks.iter().any(|x| x.kind == TrackKind::Video);
+    let cue_track = tracks
+        .iter()
+        .position(|x| x.kind == TrackKind::Video)
+        .unwrap_or(0) as u64
+        + 1;
+
+    let mut file = BufWriter::new(File::create(output)?);
+
+    file.write_all(&ebml::master(
+        ebml::EBML,
+        &[
+            ebml::uint(ebml::EBML_VERSION, 1),
+            ebml::uint(ebml::EBML_READ_VERSION, 1),
+            ebml::uint(ebml::EBML_MAX_ID_LENGTH, 4),
+            ebml::uint(ebml::EBML_MAX_SIZE_LENGTH, 8),
+            ebml::string(ebml::DOC_TYPE, "matroska"),
+            ebml::uint(ebml::DOC_TYPE_VERSION, 4),
+            ebml::uint(ebml::DOC_TYPE_READ_VERSION, 2),
+        ],
+    ))?;
+
+    file.write_all(&ebml::id(ebml::SEGMENT))?;
+    let segment_size_position = file.stream_position()?;
+    file.write_all(&ebml::UNKNOWN_SIZE)?;
+    let segment_start = file.stream_position()?;
+
+    file.write_all(&ebml::void(SEEK_HEAD_SIZE))?;
+
+    let app = Metadata::app();
+    let mut info = vec![ebml::uint(ebml::TIMESTAMP_SCALE, 1_000_000)];
+
+    if let Some(title) = &metadata.title {
+        info.push(ebml::string(ebml::TITLE, title));
+    }
+
+    info.extend_from_slice(&[
+        ebml::string(ebml::MUXING_APP, &app),
+        ebml::string(ebml::WRITING_APP, &app),
+        ebml::float(ebml::DURATION, 0.0),
+    ]);
+
+    let info_position = file.stream_position()? - segment_start;
+    file.write_all(&ebml::master(ebml::INFO, &info))?;
+    // Duration is the last child of info element.
+    let duration_position = file.stream_position()? - 8;
+
+    let tracks_position = file.stream_position()? - segment_start;
+    file.write_all(&ebml::master(
+        ebml::TRACKS,
+        &tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| track_entry(i as u64 + 1, track))
+            .collect::<Vec<_>>(),
+    ))?;
+
+    let chapters_position = file.stream_position()? - segment_start;
+
+    if !chapters.is_empty() {
+        file.write_all(&ebml::master(
+            ebml::CHAPTERS,
+            &[ebml::master(
+                ebml::EDITION_ENTRY,
+                &[ebml::uint(ebml::EDITION_UID, 1)]
+                    .into_iter()
+                    .chain(chapters.iter().enumerate().map(|(i, chapter)| {
+                        ebml::master(
+                            ebml::CHAPTER_ATOM,
+                            &[
+                                ebml::uint(ebml::CHAPTER_UID, i as u64 + 1),
+                                ebml::uint(
+                                    ebml::CHAPTER_TIME_START,
+                                    (chapter.start * 1_000_000_000.0) as u64,
+                                ),
+                                ebml::uint(
+                                    ebml::CHAPTER_TIME_END,
+                                    (chapter.end * 1_000_000_000.0) as u64,
+                                ),
+                                ebml::master(
+                                    ebml::CHAPTER_DISPLAY,
+                                    &[
+                                        ebml::string(ebml::CHAP_STRING, &chapter.title),
+                                        ebml::string(ebml::CHAP_LANGUAGE, "und"),
+                                    ],
+                                ),
+                            ],
+                        )
+                    }))
+                    .collect::<Vec<_>>(),
+            )],
+        ))?;
+    }
+
+    let mut cluster = vec![];
+    let mut cluster_timestamp = 0;
+    let mut cue_points = vec![];
+    let mut duration = 0;
+
+    while let Some((index, sample)) = inputs.next()? {
+        let number = index as u64 + 1;
+        let timestamp = sample.timestamp.max(0) as u64 / 1_000_000;
+        let sample_duration = sample.duration / 1_000_000;
+        duration = duration.max(timestamp + sample_duration);
+
+        let is_video = tracks[index].kind == TrackKind::Video;
+        let elapsed = timestamp as i64 - cluster_timestamp as i64;
+        let new_cluster = cluster.is_empty()
+            || (has_video && is_video && sample.keyframe && elapsed >= 1000)
+            || (!has_video && elapsed >= 5000)
+            || !(i16::MIN as i64..=i16::MAX as i64).contains(&elapsed);
+
+        if new_cluster {
+            if !cluster.is_empty() {
+                file.write_all(&ebml::element(ebml::CLUSTER, &cluster))?;
+            }
+
+            cluster_timestamp = timestamp;
+            cluster = ebml::uint(ebml::TIMESTAMP, cluster_timestamp);
+
+            if !has_video || (is_video && sample.keyframe) {
+                cue_points.push(ebml::master(
+                    ebml::CUE_POINT,
+                    &[
+                        ebml::uint(ebml::CUE_TIME, cluster_timestamp),
+                        ebml::master(
+                            ebml::CUE_TRACK_POSITIONS,
+                            &[
+                                ebml::uint(ebml::CUE_TRACK, cue_track),
+                                ebml::uint(
+                                    ebml::CUE_CLUSTER_POSITION,
+                                    file.stream_position()? - segment_start,
+                                ),
+                            ],
+                        ),
+                    ],
+                ));
+            }
+        }
+
+        let mut block = ebml::track_number(number);
+        block.extend_from_slice(
+            &((timestamp as i64 - cluster_timestamp as i64) as i16).to_be_bytes(),
+        );
+
+        if tracks[index].kind == TrackKind::Subtitles {
+            block.push(0x00);
+            block.extend_from_slice(&sample.data);
+            cluster.extend_from_slice(&ebml::master(
+                ebml::BLOCK_GROUP,
+                &[
+                    ebml::element(ebml::BLOCK, &block),
+                    ebml::uint(ebml::BLOCK_DURATION, sample_duration),
+                ],
+            ));
+        } else {
+            block.push(if sample.keyframe { 0x80 } else { 0x00 });
+            block.extend_from_slice(&sample.data);
+            cluster.extend_from_slice(&ebml::element(ebml::SIMPLE_BLOCK, &block));
+        }
+    }
+
+    if !cluster.is_empty() {
+        file.write_all(&ebml::element(ebml::CLUSTER, &cluster))?;
+    }
+
+    let cues_position = file.stream_position()? - segment_start;
+    file.write_all(&ebml::master(ebml::CUES, &cue_points))?;
+    let segment_end = file.stream_position()?;
+
+    // Patch values which were unknown while writing.
+    let mut seek_entries = vec![
+        (ebml::INFO, info_position),
+        (ebml::TRACKS, tracks_position),
+        (ebml::CUES, cues_position),
+    ];
+
+    if !chapters.is_empty() {
+        seek_entries.push((ebml::CHAPTERS, chapters_position));
+    }
+
+    let seek_head = ebml::master(
+        ebml::SEEK_HEAD,
+        &seek_entries
+            .iter()
+            .map(|(id, position)| {
+                ebml::master(
+                    ebml::SEEK,
+                    &[
+                        ebml::element(ebml::SEEK_ID, &ebml::id(*id)),
+                        ebml::uint(ebml::SEEK_POSITION, *position),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+
+    file.seek(SeekFrom::Start(segment_size_position))?;
+    file.write_all(&ebml::size_8(segment_end - segment_start))?;
+    file.seek(SeekFrom::Start(segment_start))?;
+    file.write_all(&seek_head)?;
+    file.write_all(&ebml::void(SEEK_HEAD_SIZE - seek_head.len()))?;
+    file.seek(SeekFrom::Start(duration_position))?;
+    file.write_all(&(duration as f64).to_be_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+fn track_entry(number: u64, track: &Track) -> Vec<u8> {
+    let mut children = vec![
+        ebml::uint(ebml::TRACK_NUMBER, number),
+        ebml::uint(ebml::TRACK_UID, number),
+        ebml::uint(
+            ebml::TRACK_TYPE,
+            match track.kind {
+                TrackKind::Video => 1,
+                TrackKind::Audio => 2,
+                TrackKind::Subtitles => 0x11,
+            },
+        ),
+        ebml::uint(ebml::FLAG_LACING, 0),
+        ebml::uint(ebml::FLAG_DEFAULT, track.default as u64),
+        ebml::uint(ebml::FLAG_FORCED, track.forced as u64),
+        ebml::string(ebml::CODEC_ID, track.codec_id),
+    ];
+
+    if let Some(codec_private) = &track.codec_private {
+        children.push(ebml::element(ebml::CODEC_PRIVATE, codec_private));
+    }
+
+    if let Some(language) = &track.language {
+        // Legacy element is also written for players which doesn't support BCP 47 tags.
+        if let Some(code) = language::iso639_2b(language) {
+            children.push(ebml::string(ebml::LANGUAGE, &code));
+        }
+
+        children.push(ebml::string(ebml::LANGUAGE_BCP47, language));
+    }
+
+    if let (Some(width), Some(height)) = (track.width, track.height) {
+        children.push(ebml::master(
+            ebml::VIDEO,
+            &[
+                ebml::uint(ebml::PIXEL_WIDTH, width),
+                ebml::uint(ebml::PIXEL_HEIGHT, height),
+            ],
+        ));
+    }
+
+    if let (Some(sample_rate), Some(channels)) = (track.sample_rate, track.channels) {
+        children.push(ebml::master(
+            ebml::AUDIO,
+            &[
+                ebml::float(ebml::SAMPLING_FREQUENCY, sample_rate),
+                ebml::uint(ebml::CHANNELS, channels),
+            ],
+        ));
+    }
+
+    ebml::master(ebml::TRACK_ENTRY, &children)
+}
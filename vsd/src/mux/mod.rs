@@ -0,0 +1,135 @@
+mod ebml;
+mod fmp4;
+mod input;
+mod language;
+mod matroska;
+mod mp4;
+mod ts;
+
+pub(crate) use language::iso639_2b;
+pub(crate) use matroska::mux_matroska;
+pub(crate) use mp4::mux_mp4;
+
+use anyhow::Result;
+use vsd_core::playlist::Segment;
+
+/// Container level metadata written to output file.
+#[derive(Clone, Default)]
+pub(crate) struct Metadata {
+    pub(crate) chapters: Vec<Chapter>,
+    pub(crate) title: Option<String>,
+}
+
+impl Metadata {
+    /// Writing application tag.
+    pub(crate) fn app() -> String {
+        format!("vsd {}", env!("CARGO_PKG_VERSION"))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Chapter {
+    /// End time in seconds.
+    pub(crate) end: f64,
+    /// Start time in seconds.
+    pub(crate) start: f64,
+    pub(crate) title: String,
+}
+
+/// Build chapters from chapter marks of segments.
+/// No chapters are returned if segments doesn't have any chapter marks.
+pub(crate) fn chapters(segments: &[Segment]) -> Vec<Chapter> {
+    if segments.iter().all(|x| x.chapter.is_none()) {
+        return vec![];
+    }
+
+    let mut chapters: Vec<Chapter> = vec![];
+    let mut time = 0.0;
+
+    for (i, segment) in segments.iter().enumerate() {
+        if i == 0 || segment.chapter.is_some() {
+            if let Some(previous) = chapters.last_mut() {
+                // Skip empty chapters formed by consecutive marks.
+                if previous.start == time {
+                    chapters.pop();
+                } else {
+                    previous.end = time;
+                }
+            }
+
+            chapters.push(Chapter {
+                end: time,
+                start: time,
+                title: segment.chapter.clone().unwrap_or_default(),
+            });
+        }
+
+        time += segment.duration as f64;
+    }
+
+    if let Some(previous) = chapters.last_mut() {
+        previous.end = time;
+    }
+
+    for (i, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = format!("Chapter {}", i + 1);
+        }
+    }
+
+    chapters
+}
+
+#[derive(Clone)]
+struct Track {
+    channels: Option<u64>,
+    codec_id: &'static str,
+    codec_private: Option<Vec<u8>>,
+    default: bool,
+    forced: bool,
+    height: Option<u64>,
+    id: u32,
+    kind: TrackKind,
+    language: Option<String>,
+    /// Sample entry box (avc1, mp4a etc.) copied as is from mp4 inputs.
+    sample_entry: Option<Vec<u8>>,
+    sample_rate: Option<f64>,
+    timescale: u32,
+    width: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TrackKind {
+    Audio,
+    Subtitles,
+    Video,
+}
+
+struct Sample {
+    data: Vec<u8>,
+    /// Decode timestamp in nanoseconds.
+    decode_timestamp: i64,
+    /// Duration in nanoseconds.
+    duration: u64,
+    keyframe: bool,
+    /// Presentation timestamp in nanoseconds.
+    timestamp: i64,
+    track_id: u32,
+}
+
+/// Demuxers which read samples from a downloaded stream.
+trait Demuxer {
+    /// Read next sample in decode order of any track.
+    fn next_sample(&mut self) -> Result<Option<Sample>>;
+    fn tracks(&self) -> &[Track];
+}
+
+/// Convert time in `timescale` units to nanoseconds.
+fn to_nanoseconds(time: i64, timescale: u32) -> i64 {
+    (time as i128 * 1_000_000_000 / timescale as i128) as i64
+}
+
+/// Convert time in nanoseconds to `timescale` units.
+fn from_nanoseconds(time: i64, timescale: u32) -> i64 {
+    (time as i128 * timescale as i128 + 500_000_000).div_euclid(1_000_000_000) as i64
+}
@@ -0,0 +1,522 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 14496-12 (ISO base media file format)
+    2. ISO/IEC 14496-14 (MP4 file format)
+    3. ISO/IEC 14496-15 (AVC file format)
+    4. https://developer.apple.com/documentation/quicktime-file-format
+
+*/
+
+use super::{from_nanoseconds, input::Inputs, language, Metadata, Sample, Track, TrackKind};
+use crate::downloader::Stream;
+use anyhow::{bail, Result};
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+/// Timescale of movie header, used for movie and fragment durations.
+const MOVIE_TIMESCALE: u32 = 1000;
+const MATRIX: [u32; 9] = [0x00010000, 0, 0, 0, 0x00010000, 0, 0, 0, 0x40000000];
+
+/// Mux fragmented mp4 or mpeg-ts video/audio streams into a fragmented mp4 file,
+/// without requiring ffmpeg. An error is returned for inputs which cannot be muxed natively
+/// (for example subtitle streams), in which case callers should fall back to ffmpeg.
+/// Input streams are followed as they grow until `downloaded` becomes true, if it is set,
+/// so that output can be written (and played) while streams are still being downloaded.
+pub(crate) fn mux_mp4(
+    streams: &[&Stream],
+    output: &str,
+    metadata: &Metadata,
+    downloaded: Option<Arc<AtomicBool>>,
+) -> Result<()> {
+    let mut inputs = Inputs::open(streams, false, downloaded)?;
+    let tracks = inputs.tracks.clone();
+    let sample_entries = tracks
+        .iter()
+        .map(sample_entry)
+        .collect::<Result<Vec<_>>>()?;
+
+    let has_video = tracks.iter().any(|x| x.kind == TrackKind::Video);
+    let reference_track = tracks
+        .iter()
+        .position(|x| x.kind == TrackKind::Video)
+        .unwrap_or(0);
+
+    let mut file = BufWriter::new(File::create(output)?);
+
+    file.write_all(&mp4_box(
+        b"ftyp",
+        &[
+            b"isom".as_slice(),
+            &0x200u32.to_be_bytes(),
+            b"isom",
+            b"iso6",
+            b"iso2",
+            b"avc1",
+            b"mp41",
+        ]
+        .concat(),
+    ))?;
+
+    let mvex = mp4_box(
+        b"mvex",
+        &[full_box(b"mehd", 1, 0, &0u64.to_be_bytes())]
+            .into_iter()
+            .chain(tracks.iter().enumerate().map(|(i, _)| {
+                full_box(
+                    b"trex",
+                    0,
+                    0,
+                    &[
+                        (i as u32 + 1).to_be_bytes(), // track_ID
+                        1u32.to_be_bytes(),           // default_sample_description_index
+                        0u32.to_be_bytes(),           // default_sample_duration
+                        0u32.to_be_bytes(),           // default_sample_size
+                        0u32.to_be_bytes(),           // default_sample_flags
+                    ]
+                    .concat(),
+                )
+            }))
+            .collect::<Vec<_>>()
+            .concat(),
+    );
+
+    let mut moov = vec![mvhd(tracks.len() as u32 + 1)];
+
+    for (i, track) in tracks.iter().enumerate() {
+        moov.push(trak(i as u32 + 1, track, &sample_entries[i]));
+    }
+
+    // Fragment duration of mehd box, which is the first child of mvex box.
+    let mehd_position =
+        file.stream_position()? + 8 + moov.iter().map(|x| x.len() as u64).sum::<u64>() + 8 + 12;
+    moov.push(mvex);
+    moov.push(udta(metadata));
+    file.write_all(&mp4_box(b"moov", &moov.concat()))?;
+
+    let mut writer = FragmentWriter {
+        file,
+        random_access: vec![],
+        reference_track,
+        sequence_number: 1,
+        tracks: &tracks,
+    };
+    let mut fragment: Vec<Vec<Sample>> = (0..tracks.len()).map(|_| Vec::new()).collect();
+    let mut fragment_start = 0;
+    let mut duration = 0;
+
+    while let Some((index, sample)) = inputs.next()? {
+        let elapsed = sample.decode_timestamp - fragment_start;
+        let is_video = tracks[index].kind == TrackKind::Video;
+        let new_fragment = (has_video && is_video && sample.keyframe && elapsed >= 1_000_000_000)
+            || (!has_video && elapsed >= 2_000_000_000);
+
+        if new_fragment {
+            writer.write(&mut fragment)?;
+        }
+
+        if fragment.iter().all(|x| x.is_empty()) {
+            fragment_start = sample.decode_timestamp;
+        }
+
+        duration = duration.max(sample.decode_timestamp + sample.duration as i64);
+        fragment[index].push(sample);
+    }
+
+    writer.write(&mut fragment)?;
+    let random_access = mfra(reference_track as u32 + 1, &writer.random_access);
+    let mut file = writer.file;
+    file.write_all(&random_access)?;
+
+    // Patch values which were unknown while writing.
+    file.seek(SeekFrom::Start(mehd_position))?;
+    file.write_all(&(from_nanoseconds(duration, MOVIE_TIMESCALE).max(0) as u64).to_be_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+struct FragmentWriter<'a> {
+    file: BufWriter<File>,
+    /// Decode time, moof offset and traf number of first sample of reference track in every fragment.
+    random_access: Vec<(i64, u64, u8)>,
+    reference_track: usize,
+    sequence_number: u32,
+    tracks: &'a [Track],
+}
+
+impl FragmentWriter<'_> {
+    /// Write samples as a movie fragment and clear them.
+    fn write(&mut self, fragment: &mut [Vec<Sample>]) -> Result<()> {
+        if fragment.iter().all(|x| x.is_empty()) {
+            return Ok(());
+        }
+
+        if let Some(sample) = fragment[self.reference_track].first() {
+            let traf_number = fragment[..self.reference_track]
+                .iter()
+                .filter(|x| !x.is_empty())
+                .count()
+                + 1;
+
+            self.random_access.push((
+                from_nanoseconds(
+                    sample.decode_timestamp,
+                    self.tracks[self.reference_track].timescale,
+                ),
+                self.file.stream_position()?,
+                traf_number as u8,
+            ));
+        }
+
+        self.file
+            .write_all(&moof_mdat(self.sequence_number, self.tracks, fragment))?;
+        self.sequence_number += 1;
+        fragment.iter_mut().for_each(|x| x.clear());
+        Ok(())
+    }
+}
+
+fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + 8);
+    data.extend_from_slice(&(payload.len() as u32 + 8).to_be_bytes());
+    data.extend_from_slice(name);
+    data.extend_from_slice(payload);
+    data
+}
+
+fn full_box(name: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = ((version as u32) << 24 | flags).to_be_bytes().to_vec();
+    data.extend_from_slice(payload);
+    mp4_box(name, &data)
+}
+
+fn matrix() -> Vec<u8> {
+    MATRIX.iter().flat_map(|x| x.to_be_bytes()).collect()
+}
+
+fn mvhd(next_track_id: u32) -> Vec<u8> {
+    let mut data = vec![];
+    data.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+    data.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+    data.extend_from_slice(&MOVIE_TIMESCALE.to_be_bytes());
+    data.extend_from_slice(&0u32.to_be_bytes()); // duration
+    data.extend_from_slice(&0x00010000u32.to_be_bytes()); // rate
+    data.extend_from_slice(&0x0100u16.to_be_bytes()); // volume
+    data.extend_from_slice(&[0; 10]); // reserved
+    data.extend_from_slice(&matrix());
+    data.extend_from_slice(&[0; 24]); // pre_defined
+    data.extend_from_slice(&next_track_id.to_be_bytes());
+    full_box(b"mvhd", 0, 0, &data)
+}
+
+fn trak(track_id: u32, track: &Track, sample_entry: &[u8]) -> Vec<u8> {
+    let is_video = track.kind == TrackKind::Video;
+
+    let mut tkhd = vec![];
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+    tkhd.extend_from_slice(&track_id.to_be_bytes());
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // reserved
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // duration
+    tkhd.extend_from_slice(&[0; 8]); // reserved
+    tkhd.extend_from_slice(&0u16.to_be_bytes()); // layer
+    tkhd.extend_from_slice(&(!is_video as u16).to_be_bytes()); // alternate_group
+    tkhd.extend_from_slice(&(if is_video { 0u16 } else { 0x0100 }).to_be_bytes()); // volume
+    tkhd.extend_from_slice(&0u16.to_be_bytes()); // reserved
+    tkhd.extend_from_slice(&matrix());
+    tkhd.extend_from_slice(&((track.width.unwrap_or(0) as u32) << 16).to_be_bytes());
+    tkhd.extend_from_slice(&((track.height.unwrap_or(0) as u32) << 16).to_be_bytes());
+
+    // track_enabled and track_in_movie flags.
+    let tkhd = full_box(b"tkhd", 0, if track.default { 3 } else { 2 }, &tkhd);
+
+    let language = track
+        .language
+        .as_ref()
+        .and_then(|x| language::iso639_2t(x))
+        .unwrap_or_else(|| "und".to_owned());
+    let language = language.bytes().fold(0u16, |packed, x| {
+        (packed << 5) | (x.wrapping_sub(0x60) & 0x1F) as u16
+    });
+
+    let mut mdhd = vec![];
+    mdhd.extend_from_slice(&0u32.to_be_bytes()); // creation_time
+    mdhd.extend_from_slice(&0u32.to_be_bytes()); // modification_time
+    mdhd.extend_from_slice(&track.timescale.to_be_bytes());
+    mdhd.extend_from_slice(&0u32.to_be_bytes()); // duration
+    mdhd.extend_from_slice(&language.to_be_bytes());
+    mdhd.extend_from_slice(&0u16.to_be_bytes()); // pre_defined
+
+    let (handler_type, name, media_header) = if is_video {
+        (b"vide", "VideoHandler", full_box(b"vmhd", 0, 1, &[0; 8]))
+    } else {
+        (b"soun", "SoundHandler", full_box(b"smhd", 0, 0, &[0; 4]))
+    };
+
+    let mut hdlr = vec![0; 4]; // pre_defined
+    hdlr.extend_from_slice(handler_type);
+    hdlr.extend_from_slice(&[0; 12]); // reserved
+    hdlr.extend_from_slice(name.as_bytes());
+    hdlr.push(0);
+
+    let dinf = mp4_box(
+        b"dinf",
+        &full_box(
+            b"dref",
+            0,
+            0,
+            &[1u32.to_be_bytes().as_slice(), &full_box(b"url ", 0, 1, &[])].concat(),
+        ),
+    );
+
+    // Sample tables are empty, samples are described by fragments.
+    let stbl = mp4_box(
+        b"stbl",
+        &[
+            full_box(
+                b"stsd",
+                0,
+                0,
+                &[1u32.to_be_bytes().as_slice(), sample_entry].concat(),
+            ),
+            full_box(b"stts", 0, 0, &0u32.to_be_bytes()),
+            full_box(b"stsc", 0, 0, &0u32.to_be_bytes()),
+            full_box(b"stsz", 0, 0, &[0; 8]),
+            full_box(b"stco", 0, 0, &0u32.to_be_bytes()),
+        ]
+        .concat(),
+    );
+
+    let mdia = mp4_box(
+        b"mdia",
+        &[
+            full_box(b"mdhd", 0, 0, &mdhd),
+            full_box(b"hdlr", 0, 0, &hdlr),
+            mp4_box(b"minf", &[media_header, dinf, stbl].concat()),
+        ]
+        .concat(),
+    );
+
+    mp4_box(b"trak", &[tkhd, mdia].concat())
+}
+
+/// Sample entry of track, either copied from input or built from its codec private data.
+fn sample_entry(track: &Track) -> Result<Vec<u8>> {
+    if let Some(sample_entry) = &track.sample_entry {
+        return Ok(sample_entry.to_owned());
+    }
+
+    let codec_private = track.codec_private.clone().unwrap_or_default();
+    let mut data = vec![0; 6]; // reserved
+    data.extend_from_slice(&1u16.to_be_bytes()); // data_reference_index
+
+    match track.codec_id {
+        "V_MPEG4/ISO/AVC" | "V_MPEGH/ISO/HEVC" => {
+            data.extend_from_slice(&[0; 16]); // pre_defined and reserved
+            data.extend_from_slice(&(track.width.unwrap_or(0) as u16).to_be_bytes());
+            data.extend_from_slice(&(track.height.unwrap_or(0) as u16).to_be_bytes());
+            data.extend_from_slice(&0x00480000u32.to_be_bytes()); // horizresolution
+            data.extend_from_slice(&0x00480000u32.to_be_bytes()); // vertresolution
+            data.extend_from_slice(&0u32.to_be_bytes()); // reserved
+            data.extend_from_slice(&1u16.to_be_bytes()); // frame_count
+            data.extend_from_slice(&[0; 32]); // compressorname
+            data.extend_from_slice(&0x0018u16.to_be_bytes()); // depth
+            data.extend_from_slice(&(-1i16).to_be_bytes()); // pre_defined
+
+            if track.codec_id == "V_MPEG4/ISO/AVC" {
+                data.extend_from_slice(&mp4_box(b"avcC", &codec_private));
+                Ok(mp4_box(b"avc1", &data))
+            } else {
+                data.extend_from_slice(&mp4_box(b"hvcC", &codec_private));
+                Ok(mp4_box(b"hvc1", &data))
+            }
+        }
+        "A_AAC" => {
+            data.extend_from_slice(&[0; 8]); // reserved
+            data.extend_from_slice(&(track.channels.unwrap_or(2) as u16).to_be_bytes());
+            data.extend_from_slice(&16u16.to_be_bytes()); // samplesize
+            data.extend_from_slice(&[0; 4]); // pre_defined and reserved
+            data.extend_from_slice(&((track.timescale.min(0xFFFF)) << 16).to_be_bytes());
+
+            // ES_Descriptor > DecoderConfigDescriptor > DecoderSpecificInfo, SLConfigDescriptor
+            let mut decoder_config = vec![0x40, 0x15]; // mpeg-4 audio, audio stream
+            decoder_config.extend_from_slice(&[0; 3]); // bufferSizeDB
+            decoder_config.extend_from_slice(&0u32.to_be_bytes()); // maxBitrate
+            decoder_config.extend_from_slice(&0u32.to_be_bytes()); // avgBitrate
+            decoder_config.extend_from_slice(&descriptor(0x05, &codec_private));
+
+            let mut es = vec![0, 0, 0]; // ES_ID and flags
+            es.extend_from_slice(&descriptor(0x04, &decoder_config));
+            es.extend_from_slice(&descriptor(0x06, &[0x02]));
+
+            data.extend_from_slice(&full_box(b"esds", 0, 0, &descriptor(0x03, &es)));
+            Ok(mp4_box(b"mp4a", &data))
+        }
+        x => bail!("{} codec cannot be muxed into mp4 natively", x),
+    }
+}
+
+/// MPEG-4 descriptor with its size written in 4 bytes.
+fn descriptor(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let size = payload.len() as u32;
+    let mut data = vec![
+        tag,
+        0x80 | ((size >> 21) & 0x7F) as u8,
+        0x80 | ((size >> 14) & 0x7F) as u8,
+        0x80 | ((size >> 7) & 0x7F) as u8,
+        (size & 0x7F) as u8,
+    ];
+    data.extend_from_slice(payload);
+    data
+}
+
+/// User data with title, encoder tags and nero chapters.
+fn udta(metadata: &Metadata) -> Vec<u8> {
+    let mut children = vec![];
+    let mut tags = vec![];
+
+    if let Some(title) = &metadata.title {
+        tags.push(tag(b"\xA9nam", title));
+    }
+
+    tags.push(tag(b"\xA9too", &Metadata::app()));
+
+    let mut hdlr = vec![0; 4]; // pre_defined
+    hdlr.extend_from_slice(b"mdir");
+    hdlr.extend_from_slice(b"appl");
+    hdlr.extend_from_slice(&[0; 9]); // reserved and empty name
+
+    children.push(full_box(
+        b"meta",
+        0,
+        0,
+        &[
+            full_box(b"hdlr", 0, 0, &hdlr),
+            mp4_box(b"ilst", &tags.concat()),
+        ]
+        .concat(),
+    ));
+
+    if !metadata.chapters.is_empty() {
+        let mut chpl = vec![0; 4]; // reserved
+        chpl.push(metadata.chapters.len().min(255) as u8);
+
+        for chapter in metadata.chapters.iter().take(255) {
+            // Start time in 100 nanoseconds units.
+            chpl.extend_from_slice(&((chapter.start * 10_000_000.0) as u64).to_be_bytes());
+            let title = &chapter.title.as_bytes()[..chapter.title.len().min(255)];
+            chpl.push(title.len() as u8);
+            chpl.extend_from_slice(title);
+        }
+
+        children.push(full_box(b"chpl", 1, 0, &chpl));
+    }
+
+    mp4_box(b"udta", &children.concat())
+}
+
+fn tag(name: &[u8; 4], value: &str) -> Vec<u8> {
+    let mut data = 1u32.to_be_bytes().to_vec(); // utf-8 type
+    data.extend_from_slice(&0u32.to_be_bytes()); // locale
+    data.extend_from_slice(value.as_bytes());
+    mp4_box(name, &mp4_box(b"data", &data))
+}
+
+/// Movie fragment containing samples of every track, followed by media data.
+fn moof_mdat(sequence_number: u32, tracks: &[Track], fragment: &[Vec<Sample>]) -> Vec<u8> {
+    let build = |data_offsets: &[u32]| {
+        let mut children = vec![full_box(b"mfhd", 0, 0, &sequence_number.to_be_bytes())];
+
+        for (i, samples) in fragment.iter().enumerate() {
+            if !samples.is_empty() {
+                children.push(traf(i as u32 + 1, &tracks[i], samples, data_offsets[i]));
+            }
+        }
+
+        mp4_box(b"moof", &children.concat())
+    };
+
+    // Data offsets are relative to moof box, whose size doesn't depend on them.
+    let moof_size = build(&vec![0; fragment.len()]).len() as u32;
+    let mut data_offsets = vec![];
+    let mut data_offset = moof_size + 8;
+
+    for samples in fragment {
+        data_offsets.push(data_offset);
+        data_offset += samples.iter().map(|x| x.data.len() as u32).sum::<u32>();
+    }
+
+    let mut data = build(&data_offsets);
+    let mdat_size = data_offset - moof_size;
+    data.extend_from_slice(&mdat_size.to_be_bytes());
+    data.extend_from_slice(b"mdat");
+
+    for samples in fragment {
+        for sample in samples {
+            data.extend_from_slice(&sample.data);
+        }
+    }
+
+    data
+}
+
+fn traf(track_id: u32, track: &Track, samples: &[Sample], data_offset: u32) -> Vec<u8> {
+    let timescale = track.timescale;
+    let is_video = track.kind == TrackKind::Video;
+    let base_decode_time = from_nanoseconds(samples[0].decode_timestamp, timescale).max(0);
+
+    // default-base-is-moof
+    let tfhd = full_box(b"tfhd", 0, 0x020000, &track_id.to_be_bytes());
+    let tfdt = full_box(b"tfdt", 1, 0, &(base_decode_time as u64).to_be_bytes());
+
+    let mut trun = vec![];
+    trun.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+    trun.extend_from_slice(&data_offset.to_be_bytes());
+
+    for (i, sample) in samples.iter().enumerate() {
+        // Durations are derived from decode times when possible to avoid rounding drift.
+        let decode_time = from_nanoseconds(sample.decode_timestamp, timescale);
+        let end_time = match samples.get(i + 1) {
+            Some(next) => from_nanoseconds(next.decode_timestamp, timescale),
+            None => from_nanoseconds(sample.decode_timestamp + sample.duration as i64, timescale),
+        };
+        let composition_offset = from_nanoseconds(sample.timestamp, timescale) - decode_time;
+
+        let flags: u32 = if !is_video || sample.keyframe {
+            0x02000000 // sample_depends_on = 2
+        } else {
+            0x01010000 // sample_depends_on = 1, sample_is_non_sync_sample
+        };
+
+        trun.extend_from_slice(&((end_time - decode_time).max(0) as u32).to_be_bytes());
+        trun.extend_from_slice(&(sample.data.len() as u32).to_be_bytes());
+        trun.extend_from_slice(&flags.to_be_bytes());
+        trun.extend_from_slice(&(composition_offset as i32).to_be_bytes());
+    }
+
+    // data-offset, sample-duration, sample-size, sample-flags, sample-composition-time-offsets
+    let trun = full_box(b"trun", 1, 0x000F01, &trun);
+    mp4_box(b"traf", &[tfhd, tfdt, trun].concat())
+}
+
+/// Movie fragment random access box pointing to first sample of every fragment.
+fn mfra(track_id: u32, random_access: &[(i64, u64, u8)]) -> Vec<u8> {
+    let mut tfra = vec![];
+    tfra.extend_from_slice(&track_id.to_be_bytes());
+    tfra.extend_from_slice(&0u32.to_be_bytes()); // 1 byte traf, trun and sample numbers
+    tfra.extend_from_slice(&(random_access.len() as u32).to_be_bytes());
+
+    for (time, moof_offset, traf_number) in random_access {
+        tfra.extend_from_slice(&((*time).max(0) as u64).to_be_bytes());
+        tfra.extend_from_slice(&moof_offset.to_be_bytes());
+        tfra.extend_from_slice(&[*traf_number, 1, 1]);
+    }
+
+    let tfra = full_box(b"tfra", 1, 0, &tfra);
+    let mfro = full_box(b"mfro", 0, 0, &(tfra.len() as u32 + 8 + 16).to_be_bytes());
+    mp4_box(b"mfra", &[tfra, mfro].concat())
+}
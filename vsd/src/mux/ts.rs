@@ -0,0 +1,970 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 13818-1 (MPEG-2 transport stream)
+    2. ISO/IEC 14496-10 (H.264 sequence parameter set)
+    3. ISO/IEC 14496-15 (AVC decoder configuration record)
+    4. https://wiki.multimedia.cx/index.php/ADTS
+
+*/
+
+use super::{input::GrowingFile, Demuxer, Sample, Track, TrackKind};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{BufReader, ErrorKind, Read},
+    path::Path,
+};
+
+pub(super) const PACKET_SIZE: usize = 188;
+
+/// Samples read ahead while waiting for samples of other tracks,
+/// after which samples are returned even if they aren't in decode order.
+const MAX_QUEUED_SAMPLES: usize = 1024;
+/// PTS and DTS are 33 bit values which wrap around after ~26.5 hours.
+const TIMESTAMP_WRAP: i64 = 1 << 33;
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// Returns true if data looks like the start of an mpeg-ts file.
+pub(super) fn is_ts(data: &[u8]) -> bool {
+    data.len() >= PACKET_SIZE
+        && data[0] == 0x47
+        && (data.len() < PACKET_SIZE * 2 || data[PACKET_SIZE] == 0x47)
+}
+
+/// Streaming demuxer for mpeg-ts files containing H.264 video and AAC (ADTS) audio.
+/// Video samples are converted to length prefixed NAL units and audio samples to raw AAC frames,
+/// so that they can be stored as is inside mp4 and matroska containers.
+pub(super) struct TsReader {
+    eof: bool,
+    pmt_pid: Option<u16>,
+    reader: BufReader<GrowingFile>,
+    samples: VecDeque<Sample>,
+    streams: HashMap<u16, Elementary>,
+    tracks: Vec<Track>,
+}
+
+impl TsReader {
+    pub(super) fn open(path: &Path, file: GrowingFile) -> Result<Self> {
+        let mut reader = Self {
+            eof: false,
+            pmt_pid: None,
+            reader: BufReader::new(file),
+            samples: VecDeque::new(),
+            streams: HashMap::new(),
+            tracks: vec![],
+        };
+
+        // Read ahead until decoder configuration of every stream is known.
+        while !reader.eof
+            && (reader.streams.is_empty() || reader.tracks.len() < reader.streams.len())
+        {
+            reader.read_packet()?;
+        }
+
+        let pids = reader
+            .tracks
+            .iter()
+            .map(|x| x.id as u16)
+            .collect::<Vec<_>>();
+        reader.streams.retain(|pid, _| pids.contains(pid));
+
+        if reader.tracks.is_empty() {
+            bail!(
+                "no supported video or audio streams found in {}",
+                path.to_string_lossy()
+            );
+        }
+
+        reader
+            .tracks
+            .sort_by_key(|x| (x.kind != TrackKind::Video, x.id));
+        Ok(reader)
+    }
+
+    fn read_packet(&mut self) -> Result<()> {
+        let mut packet = [0; PACKET_SIZE];
+
+        match self.reader.read_exact(&mut packet) {
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                // Flush partially read data of every stream.
+                self.eof = true;
+
+                for (pid, stream) in &mut self.streams {
+                    let pes = std::mem::take(&mut stream.pes);
+                    self.samples.extend(stream.push_pes(*pid, &pes)?);
+                    self.samples.extend(stream.pending.take());
+                }
+
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        if packet[0] != 0x47 {
+            bail!("mpeg-ts sync byte not found, file is corrupted");
+        }
+
+        let payload_unit_start = packet[1] & 0x40 != 0;
+        let pid = (((packet[1] & 0x1F) as u16) << 8) | packet[2] as u16;
+        let adaptation_field_control = (packet[3] >> 4) & 0x03;
+        let mut pos = 4;
+
+        if adaptation_field_control & 0x02 != 0 {
+            pos += 1 + packet[4] as usize;
+        }
+
+        if adaptation_field_control & 0x01 == 0 || pos >= PACKET_SIZE {
+            return Ok(());
+        }
+
+        let payload = &packet[pos..];
+
+        if pid == 0 {
+            if payload_unit_start && self.pmt_pid.is_none() {
+                self.pmt_pid = parse_pat(section(payload)?)?;
+            }
+        } else if Some(pid) == self.pmt_pid {
+            if payload_unit_start && self.streams.is_empty() {
+                self.streams = parse_pmt(section(payload)?)?;
+            }
+        } else if let Some(stream) = self.streams.get_mut(&pid) {
+            if payload_unit_start && !stream.pes.is_empty() {
+                let pes = std::mem::take(&mut stream.pes);
+                self.samples.extend(stream.push_pes(pid, &pes)?);
+
+                if !self.tracks.iter().any(|x| x.id == pid as u32) {
+                    if let Some(track) = stream.track(pid)? {
+                        self.tracks.push(track);
+                    }
+                }
+            }
+
+            stream.pes.extend_from_slice(payload);
+        }
+
+        Ok(())
+    }
+}
+
+impl Demuxer for TsReader {
+    fn next_sample(&mut self) -> Result<Option<Sample>> {
+        // Wait till every track has a sample queued, so that the earliest one can be returned.
+        while !self.eof
+            && self.samples.len() < MAX_QUEUED_SAMPLES
+            && !self
+                .tracks
+                .iter()
+                .all(|x| self.samples.iter().any(|y| y.track_id == x.id))
+        {
+            self.read_packet()?;
+        }
+
+        let index = self
+            .samples
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, x)| x.decode_timestamp)
+            .map(|x| x.0);
+
+        Ok(index.and_then(|x| self.samples.remove(x)))
+    }
+
+    fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Codec {
+    Aac,
+    H264,
+}
+
+/// State of an elementary stream.
+struct Elementary {
+    codec: Codec,
+    language: Option<String>,
+    /// Last decode time in 90 kHz units, used for unwrapping timestamps.
+    last_time: Option<i64>,
+    /// Partially received PES packet.
+    pes: Vec<u8>,
+
+    // AAC
+    /// 2 bytes audio specific config along with channels and sample rate.
+    aac_config: Option<(Vec<u8>, u64, u32)>,
+    /// Incomplete ADTS frame at the end of previous PES packet.
+    leftover: Vec<u8>,
+    next_timestamp: i64,
+
+    // H.264
+    last_duration: u64,
+    /// Video sample waiting for next sample to know its duration.
+    pending: Option<Sample>,
+    pps: Option<Vec<u8>>,
+    sps: Option<Vec<u8>>,
+}
+
+impl Elementary {
+    fn new(codec: Codec, language: Option<String>) -> Self {
+        Self {
+            aac_config: None,
+            codec,
+            language,
+            last_duration: 1_000_000_000 / 30,
+            last_time: None,
+            leftover: vec![],
+            next_timestamp: 0,
+            pending: None,
+            pes: vec![],
+            pps: None,
+            sps: None,
+        }
+    }
+
+    /// Track of stream, available once its decoder configuration is known.
+    fn track(&self, pid: u16) -> Result<Option<Track>> {
+        let mut track = Track {
+            channels: None,
+            codec_id: "",
+            codec_private: None,
+            default: false,
+            forced: false,
+            height: None,
+            id: pid as u32,
+            kind: TrackKind::Audio,
+            language: self.language.clone(),
+            sample_entry: None,
+            sample_rate: None,
+            timescale: 90000,
+            width: None,
+        };
+
+        match self.codec {
+            Codec::Aac => {
+                let (config, channels, sample_rate) = match &self.aac_config {
+                    Some(x) => x,
+                    None => return Ok(None),
+                };
+
+                track.channels = Some(*channels);
+                track.codec_id = "A_AAC";
+                track.codec_private = Some(config.to_owned());
+                track.sample_rate = Some(*sample_rate as f64);
+                track.timescale = *sample_rate;
+            }
+            Codec::H264 => {
+                let (sps, pps) = match (&self.sps, &self.pps) {
+                    (Some(sps), Some(pps)) => (sps, pps),
+                    _ => return Ok(None),
+                };
+
+                let (width, height) = sps_resolution(sps)?;
+                track.codec_id = "V_MPEG4/ISO/AVC";
+                track.codec_private = Some(avc_decoder_configuration(sps, pps));
+                track.height = Some(height);
+                track.kind = TrackKind::Video;
+                track.width = Some(width);
+            }
+        }
+
+        Ok(Some(track))
+    }
+
+    /// Convert a complete PES packet into samples.
+    fn push_pes(&mut self, pid: u16, pes: &[u8]) -> Result<Vec<Sample>> {
+        let (pts, dts, payload) = match parse_pes(pes) {
+            Some(x) => x,
+            None => return Ok(vec![]),
+        };
+
+        let pts = pts.map(|x| self.unwrap_timestamp(x));
+        let dts = dts.map(|x| self.unwrap_timestamp(x));
+
+        if let Some(dts) = dts {
+            self.last_time = Some(dts);
+        }
+
+        match self.codec {
+            Codec::Aac => self.push_adts(pid, pts, payload),
+            Codec::H264 => self.push_annex_b(pid, pts, dts, payload),
+        }
+    }
+
+    fn push_adts(&mut self, pid: u16, pts: Option<i64>, payload: &[u8]) -> Result<Vec<Sample>> {
+        let mut data = std::mem::take(&mut self.leftover);
+        let leftover_size = data.len();
+        data.extend_from_slice(payload);
+
+        let mut samples = vec![];
+        let mut pts = pts;
+        let mut pos = 0;
+
+        while pos + 7 <= data.len() {
+            let header = &data[pos..];
+
+            if header[0] != 0xFF || header[1] & 0xF0 != 0xF0 {
+                // Resync to next syncword.
+                pos += 1;
+                continue;
+            }
+
+            let header_size = if header[1] & 0x01 == 0 { 9 } else { 7 };
+            let frame_size = (((header[3] & 0x03) as usize) << 11)
+                | ((header[4] as usize) << 3)
+                | ((header[5] as usize) >> 5);
+
+            if frame_size < header_size {
+                pos += 1;
+                continue;
+            }
+
+            if pos + frame_size > data.len() {
+                break;
+            }
+
+            let object_type = ((header[2] >> 6) & 0x03) + 1;
+            let sample_rate_index = (header[2] >> 2) & 0x0F;
+            let channels = ((header[2] & 0x01) << 2) | (header[3] >> 6);
+            let sample_rate = *AAC_SAMPLE_RATES
+                .get(sample_rate_index as usize)
+                .ok_or_else(|| anyhow!("invalid aac sample rate index {}", sample_rate_index))?;
+
+            if self.aac_config.is_none() {
+                self.aac_config = Some((
+                    vec![
+                        (object_type << 3) | (sample_rate_index >> 1),
+                        ((sample_rate_index & 0x01) << 7) | (channels << 3),
+                    ],
+                    channels as u64,
+                    sample_rate,
+                ));
+            }
+
+            // First frame starting inside this PES packet is the one which its PTS belongs to.
+            if pos >= leftover_size {
+                if let Some(pts) = pts.take() {
+                    self.next_timestamp = to_nanoseconds(pts);
+                }
+            }
+
+            let duration = 1024 * 1_000_000_000 / sample_rate as u64;
+
+            samples.push(Sample {
+                data: data[(pos + header_size)..(pos + frame_size)].to_vec(),
+                decode_timestamp: self.next_timestamp,
+                duration,
+                keyframe: true,
+                timestamp: self.next_timestamp,
+                track_id: pid as u32,
+            });
+
+            self.next_timestamp += duration as i64;
+            pos += frame_size;
+        }
+
+        self.leftover = data[pos..].to_vec();
+        Ok(samples)
+    }
+
+    fn push_annex_b(
+        &mut self,
+        pid: u16,
+        pts: Option<i64>,
+        dts: Option<i64>,
+        payload: &[u8],
+    ) -> Result<Vec<Sample>> {
+        let mut data = vec![];
+        let mut keyframe = false;
+
+        for nal in nal_units(payload) {
+            match nal[0] & 0x1F {
+                5 => keyframe = true,
+                7 => {
+                    if self.sps.is_none() {
+                        self.sps = Some(nal.to_vec());
+                    }
+                    continue;
+                }
+                8 => {
+                    if self.pps.is_none() {
+                        self.pps = Some(nal.to_vec());
+                    }
+                    continue;
+                }
+                // Access unit delimiter and filler data.
+                9 | 12 => continue,
+                _ => (),
+            }
+
+            data.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+            data.extend_from_slice(nal);
+        }
+
+        let mut samples = vec![];
+
+        let (pts, dts) = match (pts, dts) {
+            (Some(pts), Some(dts)) => (pts, dts),
+            _ => {
+                // Access unit is split across PES packets.
+                if let Some(pending) = &mut self.pending {
+                    pending.data.extend_from_slice(&data);
+                    pending.keyframe |= keyframe;
+                }
+
+                return Ok(samples);
+            }
+        };
+
+        // Samples before first parameter sets cannot be decoded.
+        if data.is_empty() || self.sps.is_none() || self.pps.is_none() {
+            return Ok(samples);
+        }
+
+        let decode_timestamp = to_nanoseconds(dts);
+
+        if let Some(mut pending) = self.pending.take() {
+            if decode_timestamp > pending.decode_timestamp {
+                self.last_duration = (decode_timestamp - pending.decode_timestamp) as u64;
+            }
+
+            pending.duration = self.last_duration;
+            samples.push(pending);
+        }
+
+        self.pending = Some(Sample {
+            data,
+            decode_timestamp,
+            duration: self.last_duration,
+            keyframe,
+            timestamp: to_nanoseconds(pts),
+            track_id: pid as u32,
+        });
+
+        Ok(samples)
+    }
+
+    /// Choose the wrapped value of timestamp which is closest to last decode time.
+    fn unwrap_timestamp(&self, time: i64) -> i64 {
+        let last_time = match self.last_time {
+            Some(x) => x,
+            None => return time,
+        };
+
+        let mut time = time + last_time.div_euclid(TIMESTAMP_WRAP) * TIMESTAMP_WRAP;
+
+        if time - last_time > TIMESTAMP_WRAP / 2 {
+            time -= TIMESTAMP_WRAP;
+        } else if last_time - time > TIMESTAMP_WRAP / 2 {
+            time += TIMESTAMP_WRAP;
+        }
+
+        time
+    }
+}
+
+fn to_nanoseconds(time: i64) -> i64 {
+    super::to_nanoseconds(time, 90000)
+}
+
+/// PSI section following the pointer field.
+fn section(payload: &[u8]) -> Result<&[u8]> {
+    let section = payload
+        .get((1 + payload[0] as usize)..)
+        .ok_or_else(|| anyhow!("invalid mpeg-ts pointer field"))?;
+
+    if section.len() < 3 {
+        bail!("mpeg-ts section is truncated");
+    }
+
+    let end = 3 + ((((section[1] & 0x0F) as usize) << 8) | section[2] as usize);
+
+    // Sections spanning multiple packets are not used for PAT and PMT in practice.
+    section
+        .get(..end)
+        .ok_or_else(|| anyhow!("mpeg-ts section is truncated"))
+}
+
+/// PID of first program's PMT.
+fn parse_pat(section: &[u8]) -> Result<Option<u16>> {
+    if section[0] != 0x00 {
+        return Ok(None);
+    }
+
+    // Skip header and CRC.
+    let programs = section
+        .get(8..(section.len().saturating_sub(4)))
+        .unwrap_or_default();
+
+    Ok(programs
+        .chunks_exact(4)
+        .find(|x| u16::from_be_bytes([x[0], x[1]]) != 0)
+        .map(|x| (((x[2] & 0x1F) as u16) << 8) | x[3] as u16))
+}
+
+/// Supported elementary streams by their PIDs.
+fn parse_pmt(section: &[u8]) -> Result<HashMap<u16, Elementary>> {
+    let mut streams = HashMap::new();
+
+    if section[0] != 0x02 || section.len() < 16 {
+        return Ok(streams);
+    }
+
+    let program_info_length = (((section[10] & 0x0F) as usize) << 8) | section[11] as usize;
+    let end = section.len() - 4;
+    let mut pos = 12 + program_info_length;
+
+    while pos + 5 <= end {
+        let stream_type = section[pos];
+        let pid = (((section[pos + 1] & 0x1F) as u16) << 8) | section[pos + 2] as u16;
+        let info_length = (((section[pos + 3] & 0x0F) as usize) << 8) | section[pos + 4] as usize;
+        let info = section
+            .get((pos + 5)..(pos + 5 + info_length))
+            .ok_or_else(|| anyhow!("mpeg-ts program map table is truncated"))?;
+        pos += 5 + info_length;
+
+        let codec = match stream_type {
+            0x0F => Codec::Aac,
+            0x1B => Codec::H264,
+            0x01 | 0x02 => bail!("mpeg-2 video streams are not supported"),
+            0x03 | 0x04 => bail!("mpeg audio streams are not supported"),
+            0x11 => bail!("aac latm audio streams are not supported"),
+            0x24 => bail!("h265 video streams are not supported"),
+            0x81 | 0x87 => bail!("ac-3 and e-ac-3 audio streams are not supported"),
+            0xC1 | 0xC2 | 0xCF | 0xDB => bail!("sample-aes encrypted streams are not supported"),
+            // Metadata (ID3, SCTE-35 etc.) streams.
+            _ => continue,
+        };
+
+        streams.insert(pid, Elementary::new(codec, iso639_language(info)));
+    }
+
+    Ok(streams)
+}
+
+/// Language from ISO 639 language descriptor.
+fn iso639_language(mut descriptors: &[u8]) -> Option<String> {
+    while descriptors.len() >= 2 {
+        let tag = descriptors[0];
+        let length = descriptors[1] as usize;
+        let data = descriptors.get(2..(2 + length))?;
+
+        if tag == 0x0A && length >= 3 {
+            let language = String::from_utf8_lossy(&data[..3]).to_lowercase();
+
+            if language.chars().all(|x| x.is_ascii_lowercase()) && language != "und" {
+                return Some(language);
+            }
+        }
+
+        descriptors = &descriptors[(2 + length)..];
+    }
+
+    None
+}
+
+/// Returns PTS, DTS (in 90 kHz units) and payload of a PES packet.
+/// DTS is same as PTS when not present.
+fn parse_pes(pes: &[u8]) -> Option<(Option<i64>, Option<i64>, &[u8])> {
+    if pes.len() < 9 || pes[..3] != [0, 0, 1] {
+        return None;
+    }
+
+    let flags = pes[7];
+    let header_end = 9 + pes[8] as usize;
+
+    if header_end > pes.len() {
+        return None;
+    }
+
+    let pts = if flags & 0x80 != 0 && header_end >= 14 {
+        Some(pes_timestamp(&pes[9..]))
+    } else {
+        None
+    };
+
+    let dts = if flags & 0xC0 == 0xC0 && header_end >= 19 {
+        Some(pes_timestamp(&pes[14..]))
+    } else {
+        pts
+    };
+
+    Some((pts, dts, &pes[header_end..]))
+}
+
+fn pes_timestamp(data: &[u8]) -> i64 {
+    (((data[0] as i64 >> 1) & 0x07) << 30)
+        | ((data[1] as i64) << 22)
+        | ((data[2] as i64 >> 1) << 15)
+        | ((data[3] as i64) << 7)
+        | (data[4] as i64 >> 1)
+}
+
+/// Split Annex B byte stream into NAL units.
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = vec![];
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 2 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                units.push(&data[start..i]);
+            }
+
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    if let Some(start) = start {
+        units.push(&data[start..]);
+    }
+
+    units
+        .into_iter()
+        .map(|x| {
+            // Leading zero of 4 byte start codes.
+            let end = x.iter().rposition(|x| *x != 0).map(|x| x + 1).unwrap_or(0);
+            &x[..end]
+        })
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+fn avc_decoder_configuration(sps: &[u8], pps: &[u8]) -> Vec<u8> {
+    let mut config = vec![
+        1,
+        sps.get(1).copied().unwrap_or_default(),
+        sps.get(2).copied().unwrap_or_default(),
+        sps.get(3).copied().unwrap_or_default(),
+        // 4 bytes NAL unit length
+        0xFF,
+        // 1 sequence parameter set
+        0xE1,
+    ];
+    config.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+    config.extend_from_slice(sps);
+    config.push(1);
+    config.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+    config.extend_from_slice(pps);
+    config
+}
+
+/// Width and height of cropped frame from sequence parameter set.
+fn sps_resolution(sps: &[u8]) -> Result<(u64, u64)> {
+    let mut reader = BitReader::new(sps.get(1..).unwrap_or_default());
+    let profile_idc = reader.bits(8)?;
+    reader.bits(16)?; // constraint_set_flags, level_idc
+    reader.ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+
+    if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile_idc) {
+        chroma_format_idc = reader.ue()?;
+
+        if chroma_format_idc == 3 {
+            separate_colour_plane = reader.bit()? == 1;
+        }
+
+        reader.ue()?; // bit_depth_luma_minus8
+        reader.ue()?; // bit_depth_chroma_minus8
+        reader.bit()?; // qpprime_y_zero_transform_bypass_flag
+
+        // seq_scaling_matrix_present_flag
+        if reader.bit()? == 1 {
+            for i in 0..(if chroma_format_idc == 3 { 12 } else { 8 }) {
+                // seq_scaling_list_present_flag
+                if reader.bit()? == 1 {
+                    let size = if i < 6 { 16 } else { 64 };
+                    let mut last_scale = 8;
+                    let mut next_scale = 8;
+
+                    for _ in 0..size {
+                        if next_scale != 0 {
+                            next_scale = (last_scale + reader.se()? + 256) % 256;
+                        }
+
+                        if next_scale != 0 {
+                            last_scale = next_scale;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    reader.ue()?; // log2_max_frame_num_minus4
+
+    match reader.ue()? {
+        0 => {
+            reader.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            reader.bit()?; // delta_pic_order_always_zero_flag
+            reader.se()?; // offset_for_non_ref_pic
+            reader.se()?; // offset_for_top_to_bottom_field
+
+            for _ in 0..reader.ue()? {
+                reader.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => (),
+    }
+
+    reader.ue()?; // max_num_ref_frames
+    reader.bit()?; // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = reader.ue()? as u64 + 1;
+    let height_in_map_units = reader.ue()? as u64 + 1;
+    let frame_mbs_only = reader.bit()? as u64;
+
+    if frame_mbs_only == 0 {
+        reader.bit()?; // mb_adaptive_frame_field_flag
+    }
+
+    reader.bit()?; // direct_8x8_inference_flag
+
+    let mut width = width_in_mbs * 16;
+    let mut height = (2 - frame_mbs_only) * height_in_map_units * 16;
+
+    // frame_cropping_flag
+    if reader.bit()? == 1 {
+        let (crop_unit_x, crop_unit_y) = match (chroma_format_idc, separate_colour_plane) {
+            (0, _) | (3, true) => (1, 2 - frame_mbs_only),
+            (1, _) => (2, 2 * (2 - frame_mbs_only)),
+            (2, _) => (2, 2 - frame_mbs_only),
+            _ => (1, 2 - frame_mbs_only),
+        };
+
+        let left = reader.ue()? as u64;
+        let right = reader.ue()? as u64;
+        let top = reader.ue()? as u64;
+        let bottom = reader.ue()? as u64;
+        width = width.saturating_sub((left + right) * crop_unit_x);
+        height = height.saturating_sub((top + bottom) * crop_unit_y);
+    }
+
+    Ok((width, height))
+}
+
+/// Bit reader over RBSP, emulation prevention bytes are removed beforehand.
+struct BitReader {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl BitReader {
+    fn new(data: &[u8]) -> Self {
+        let mut rbsp = Vec::with_capacity(data.len());
+        let mut zeros = 0;
+
+        for byte in data {
+            // 00 00 03 is an escaped 00 00.
+            if zeros >= 2 && *byte == 0x03 {
+                zeros = 0;
+                continue;
+            }
+
+            zeros = if *byte == 0 { zeros + 1 } else { 0 };
+            rbsp.push(*byte);
+        }
+
+        Self {
+            data: rbsp,
+            position: 0,
+        }
+    }
+
+    fn bit(&mut self) -> Result<u32> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or_else(|| anyhow!("h264 sequence parameter set is truncated"))?;
+        let bit = (byte >> (7 - self.position % 8)) & 0x01;
+        self.position += 1;
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+
+        for _ in 0..count {
+            value = (value << 1) | self.bit()?;
+        }
+
+        Ok(value)
+    }
+
+    /// Unsigned exp-golomb code.
+    fn ue(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+
+        while self.bit()? == 0 {
+            leading_zeros += 1;
+
+            if leading_zeros > 31 {
+                bail!("invalid exp-golomb code in h264 sequence parameter set");
+            }
+        }
+
+        Ok(((1u64 << leading_zeros) - 1 + self.bits(leading_zeros)? as u64) as u32)
+    }
+
+    /// Signed exp-golomb code.
+    fn se(&mut self) -> Result<i32> {
+        let value = self.ue()? as i64;
+
+        Ok(if value % 2 == 0 {
+            -(value / 2)
+        } else {
+            (value + 1) / 2
+        } as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// PES packet with PTS (and DTS) in 90 kHz units.
+    fn pes(pts: Option<i64>, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 1, 0xE0, 0, 0, 0x80];
+
+        match pts {
+            Some(pts) => {
+                data.extend_from_slice(&[0x80, 5]);
+                data.extend_from_slice(&[
+                    0x21 | ((pts >> 29) & 0x0E) as u8,
+                    (pts >> 22) as u8,
+                    ((pts >> 14) & 0xFE) as u8 | 1,
+                    (pts >> 7) as u8,
+                    ((pts << 1) & 0xFE) as u8 | 1,
+                ]);
+            }
+            None => data.extend_from_slice(&[0, 0]),
+        }
+
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// ADTS frame of AAC LC stereo audio at 48 kHz.
+    fn adts(payload: &[u8]) -> Vec<u8> {
+        let size = payload.len() + 7;
+        let mut data = vec![
+            0xFF,
+            0xF1,
+            0x4C,
+            0x80 | (size >> 11) as u8,
+            (size >> 3) as u8,
+            ((size & 0x07) << 5) as u8 | 0x1F,
+            0xFC,
+        ];
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn parses_pes_timestamps() {
+        let data = pes(Some(8_589_934_591), &[1, 2]);
+        assert_eq!(
+            parse_pes(&data),
+            Some((Some(8_589_934_591), Some(8_589_934_591), &[1, 2][..]))
+        );
+        assert_eq!(parse_pes(&pes(None, &[3])), Some((None, None, &[3][..])));
+        assert_eq!(parse_pes(&[0, 0, 2, 0xE0, 0, 0, 0x80, 0, 0]), None);
+    }
+
+    #[test]
+    fn reassembles_adts_frames_across_pes_packets() {
+        let mut stream = Elementary::new(Codec::Aac, None);
+        let first = adts(&[1; 10]);
+        let second = adts(&[2; 4]);
+
+        let samples = stream
+            .push_pes(256, &pes(Some(90000), &first[..10]))
+            .unwrap();
+        assert!(samples.is_empty());
+
+        let mut payload = first[10..].to_vec();
+        payload.extend_from_slice(&second);
+        let samples = stream.push_pes(256, &pes(Some(91920), &payload)).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].data, vec![1; 10]);
+        assert_eq!(samples[1].data, vec![2; 4]);
+        // PTS belongs to first frame starting inside PES packet.
+        assert_eq!(samples[1].timestamp, to_nanoseconds(91920));
+        assert_eq!(samples[1].duration, 1024 * 1_000_000_000 / 48000);
+
+        let track = stream.track(256).unwrap().unwrap();
+        assert_eq!(track.codec_private, Some(vec![0x11, 0x90]));
+        assert_eq!(track.channels, Some(2));
+        assert_eq!(track.sample_rate, Some(48000.0));
+    }
+
+    #[test]
+    fn reassembles_access_units_across_pes_packets() {
+        let mut stream = Elementary::new(Codec::H264, None);
+
+        let samples = stream
+            .push_pes(
+                256,
+                &pes(
+                    Some(3000),
+                    &[0, 0, 0, 1, 0x67, 1, 0, 0, 1, 0x68, 2, 0, 0, 1, 0x65, 3],
+                ),
+            )
+            .unwrap();
+        assert!(samples.is_empty());
+
+        // Continuation of access unit without timestamps.
+        let samples = stream
+            .push_pes(256, &pes(None, &[0, 0, 1, 0x65, 4]))
+            .unwrap();
+        assert!(samples.is_empty());
+
+        let samples = stream
+            .push_pes(256, &pes(Some(6000), &[0, 0, 1, 0x41, 5]))
+            .unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(
+            samples[0].data,
+            vec![0, 0, 0, 2, 0x65, 3, 0, 0, 0, 2, 0x65, 4]
+        );
+        assert_eq!(samples[0].decode_timestamp, to_nanoseconds(3000));
+        assert_eq!(samples[0].duration, to_nanoseconds(3000) as u64);
+        assert!(samples[0].keyframe);
+        assert_eq!(stream.sps, Some(vec![0x67, 1]));
+        assert_eq!(stream.pps, Some(vec![0x68, 2]));
+
+        let pending = stream.pending.unwrap();
+        assert_eq!(pending.data, vec![0, 0, 0, 2, 0x41, 5]);
+        assert!(!pending.keyframe);
+    }
+
+    #[test]
+    fn unwraps_timestamps() {
+        let mut stream = Elementary::new(Codec::Aac, None);
+        stream.last_time = Some(TIMESTAMP_WRAP - 100);
+        assert_eq!(stream.unwrap_timestamp(50), TIMESTAMP_WRAP + 50);
+        assert_eq!(
+            stream.unwrap_timestamp(TIMESTAMP_WRAP - 200),
+            TIMESTAMP_WRAP - 200
+        );
+    }
+
+    #[test]
+    fn splits_nal_units() {
+        assert_eq!(
+            nal_units(&[0, 0, 0, 1, 9, 0xF0, 0, 0, 1, 0x67, 1, 0, 0, 0, 1, 0x68]),
+            vec![&[9, 0xF0][..], &[0x67, 1][..], &[0x68][..]]
+        );
+    }
+}
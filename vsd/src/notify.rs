@@ -0,0 +1,111 @@
+use crate::{exit::ExitCode, logger::warning, utils};
+use anyhow::{bail, Result};
+use kdam::term::Colorizer;
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use std::{process::Command, time::Duration};
+
+/// File written by a download, remote outputs don't have a local size once uploaded.
+pub(crate) struct Output {
+    pub(crate) path: String,
+    pub(crate) size: Option<u64>,
+}
+
+/// Notifications sent once a download completes or fails, so that long unattended downloads
+/// can alert the user.
+pub(crate) struct Notifier {
+    /// Command with `{input}`, `{output}` and `{status}` placeholders.
+    pub(crate) exec: Option<String>,
+    pub(crate) webhook: Option<String>,
+}
+
+impl Notifier {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.exec.is_none() && self.webhook.is_none()
+    }
+
+    /// Send notifications, failures are only warned about so that result of download is kept.
+    pub(crate) fn notify(
+        &self,
+        input: &str,
+        outputs: &[Output],
+        duration: Duration,
+        result: &Result<()>,
+    ) {
+        if self.is_empty() {
+            return;
+        }
+
+        let code = result.as_ref().err().map(ExitCode::of);
+        let payload = json!({
+            "code": code.map(|x| x as i32).unwrap_or(0),
+            "duration": duration.as_secs_f64(),
+            "error": result.as_ref().err().map(|x| x.to_string()),
+            "input": input,
+            "output": outputs.first().map(|x| &x.path),
+            "outputs": outputs.iter().map(|x| &x.path).collect::<Vec<_>>(),
+            "size": outputs.iter().filter_map(|x| x.size).sum::<u64>(),
+            "status": if result.is_ok() { "success" } else { "failure" },
+        });
+
+        if let Some(webhook) = &self.webhook {
+            if let Err(e) = send_webhook(webhook, &payload) {
+                warning!("couldn't send webhook notification ({})", e);
+            }
+        }
+
+        if let Some(exec) = &self.exec {
+            if let Err(e) = run_command(exec, &payload) {
+                warning!("couldn't run --exec command ({})", e);
+            }
+        }
+    }
+}
+
+fn send_webhook(url: &str, payload: &Value) -> Result<()> {
+    log::debug!("sending webhook notification to {}", url);
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?
+        .post(url)
+        .json(payload)
+        .send()?
+        .error_for_status()?;
+    eprintln!("   {} {}", "Notified".colorize("bold green"), url);
+    Ok(())
+}
+
+/// Run command after expanding placeholders in each of its arguments, so that paths with
+/// whitespaces stay as a single argument. Payload is also passed as `VSD_NOTIFICATION`
+/// environment variable.
+fn run_command(command: &str, payload: &Value) -> Result<()> {
+    let value = |key: &str| match &payload[key] {
+        Value::String(x) => x.to_owned(),
+        Value::Null => String::new(),
+        x => x.to_string(),
+    };
+    let args = utils::split_args(command)?
+        .into_iter()
+        .map(|x| {
+            x.replace("{input}", &value("input"))
+                .replace("{output}", &value("output"))
+                .replace("{status}", &value("status"))
+        })
+        .collect::<Vec<_>>();
+
+    if args.is_empty() {
+        bail!("command is empty.");
+    }
+
+    log::info!("executing {}", args.join(" "));
+    let status = Command::new(&args[0])
+        .args(&args[1..])
+        .env("VSD_NOTIFICATION", payload.to_string())
+        .status()?;
+
+    if !status.success() {
+        bail!("{} exited with {}.", args[0], status);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,470 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-header-based-auth.html
+    2. https://docs.aws.amazon.com/AmazonS3/latest/userguide/mpuoverview.html
+    3. https://www.rfc-editor.org/rfc/rfc4918 (WebDAV)
+
+*/
+
+use anyhow::{anyhow, bail, Result};
+use hmac::{Hmac, Mac};
+use kdam::term::Colorizer;
+use reqwest::{
+    blocking::{Body, Client, RequestBuilder, Response},
+    Method, Url,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+};
+
+/// Files larger than this are uploaded to s3 in parts of this size.
+const S3_PART_SIZE: usize = 64 * 1024 * 1024;
+
+/// Remote storage where output file is uploaded once it is completely written.
+pub(crate) enum Remote {
+    S3 {
+        bucket: String,
+        key: String,
+    },
+    WebDav {
+        password: Option<String>,
+        url: Url,
+        username: Option<String>,
+    },
+}
+
+impl Remote {
+    /// Parse `s3://bucket/key` and `webdav://[user:password@]host/path` output locations.
+    /// `webdav://` uses https, `webdav+http://` can be used for plain http servers.
+    /// None is returned for local output paths.
+    pub(crate) fn parse(output: &str) -> Result<Option<Self>> {
+        if let Some(location) = output.strip_prefix("s3://") {
+            let (bucket, key) = location
+                .split_once('/')
+                .ok_or_else(|| anyhow!("s3 output should be of form s3://bucket/key"))?;
+
+            if bucket.is_empty() || key.is_empty() || key.ends_with('/') {
+                bail!("s3 output should be of form s3://bucket/key");
+            }
+
+            return Ok(Some(Self::S3 {
+                bucket: bucket.to_owned(),
+                key: key.to_owned(),
+            }));
+        }
+
+        let url = if let Some(location) = output.strip_prefix("webdav://") {
+            format!("https://{}", location)
+        } else if let Some(location) = output.strip_prefix("webdav+http://") {
+            format!("http://{}", location)
+        } else {
+            return Ok(None);
+        };
+
+        let mut url = url
+            .parse::<Url>()
+            .map_err(|x| anyhow!("invalid webdav output {} ({}).", output, x))?;
+
+        if url.path().ends_with('/') {
+            bail!("webdav output should end with a file name.");
+        }
+
+        let username = Some(decode(url.username())).filter(|x| !x.is_empty());
+        let password = url.password().map(decode);
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+
+        Ok(Some(Self::WebDav {
+            password,
+            url,
+            username,
+        }))
+    }
+
+    /// File name of remote object, which is used as local output path before uploading.
+    pub(crate) fn file_name(&self) -> String {
+        let path = match self {
+            Self::S3 { key, .. } => key.to_owned(),
+            Self::WebDav { url, .. } => decode(url.path()),
+        };
+
+        path.rsplit('/').next().unwrap_or_default().to_owned()
+    }
+
+    /// Check that credentials required for uploading are available before doing any work.
+    pub(crate) fn preflight(&self) -> Result<()> {
+        if let Self::S3 { .. } = self {
+            S3::from_env()?;
+        }
+
+        Ok(())
+    }
+
+    /// Upload local file to remote storage.
+    pub(crate) fn upload(&self, path: &str) -> Result<()> {
+        // Uploads can take a long time, so default timeout of blocking client cannot be used.
+        let client = Client::builder().timeout(None).build()?;
+
+        eprintln!(
+            "  {} {} to {}",
+            "Uploading".colorize("bold cyan"),
+            path,
+            self
+        );
+
+        match self {
+            Self::S3 { bucket, key } => S3::from_env()?.upload(&client, bucket, key, path),
+            Self::WebDav {
+                password,
+                url,
+                username,
+            } => {
+                let auth = |request: RequestBuilder| match username {
+                    Some(username) => request.basic_auth(username, password.as_ref()),
+                    None => request,
+                };
+
+                // Create parent collections, failures are ignored as they might already exist.
+                let segments = url.path_segments().map(|x| x.collect::<Vec<_>>());
+
+                if let Some(segments) = segments {
+                    let mut collection = url.clone();
+
+                    for i in 1..segments.len() {
+                        collection.set_path(&format!("/{}/", segments[..i].join("/")));
+                        let _ =
+                            auth(client.request(Method::from_bytes(b"MKCOL")?, collection.clone()))
+                                .send();
+                    }
+                }
+
+                let file = File::open(path)?;
+                let size = file.metadata()?.len();
+                check(
+                    auth(client.put(url.clone()))
+                        .body(Body::sized(file, size))
+                        .send()?,
+                )?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::S3 { bucket, key } => write!(f, "s3://{}/{}", bucket, key),
+            Self::WebDav { url, .. } => write!(f, "{}", url),
+        }
+    }
+}
+
+/// S3 (or S3 compatible) storage credentials read from standard AWS environment variables.
+struct S3 {
+    access_key_id: String,
+    /// Custom endpoint for S3 compatible storages, path style urls are used with it.
+    endpoint: Option<Url>,
+    region: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl S3 {
+    fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|x| !x.is_empty());
+
+        Ok(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID").ok_or_else(|| {
+                anyhow!("AWS_ACCESS_KEY_ID environment variable is required for s3 output.")
+            })?,
+            endpoint: var("AWS_ENDPOINT_URL")
+                .map(|x| x.parse::<Url>())
+                .transpose()
+                .map_err(|x| anyhow!("invalid AWS_ENDPOINT_URL ({}).", x))?,
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_owned()),
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY").ok_or_else(|| {
+                anyhow!("AWS_SECRET_ACCESS_KEY environment variable is required for s3 output.")
+            })?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    fn upload(&self, client: &Client, bucket: &str, key: &str, path: &str) -> Result<()> {
+        let url = self.url(bucket, key)?;
+        let size = std::fs::metadata(path)?.len() as usize;
+        let mut reader = BufReader::new(File::open(path)?);
+
+        if size <= S3_PART_SIZE {
+            let mut data = Vec::with_capacity(size);
+            reader.read_to_end(&mut data)?;
+            self.send(client, Method::PUT, &url, &[], data)?;
+            return Ok(());
+        }
+
+        let response = self.send(client, Method::POST, &url, &[("uploads", "")], vec![])?;
+        let upload_id = xml_value(&response.text()?, "UploadId")
+            .ok_or_else(|| anyhow!("s3 didn't return an upload id for multipart upload."))?;
+
+        let result = (|| -> Result<()> {
+            let mut etags = vec![];
+            let mut data = vec![0; S3_PART_SIZE];
+
+            loop {
+                let mut length = 0;
+
+                while length < S3_PART_SIZE {
+                    match reader.read(&mut data[length..])? {
+                        0 => break,
+                        x => length += x,
+                    }
+                }
+
+                if length == 0 {
+                    break;
+                }
+
+                let part_number = (etags.len() + 1).to_string();
+                let response = self.send(
+                    client,
+                    Method::PUT,
+                    &url,
+                    &[("partNumber", &part_number), ("uploadId", &upload_id)],
+                    data[..length].to_vec(),
+                )?;
+                etags.push(
+                    response
+                        .headers()
+                        .get("etag")
+                        .and_then(|x| x.to_str().ok())
+                        .ok_or_else(|| anyhow!("s3 didn't return an etag for uploaded part."))?
+                        .to_owned(),
+                );
+            }
+
+            let body = format!(
+                "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+                etags
+                    .iter()
+                    .enumerate()
+                    .map(|(i, etag)| format!(
+                        "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                        i + 1,
+                        etag
+                    ))
+                    .collect::<String>()
+            );
+            let response = self.send(
+                client,
+                Method::POST,
+                &url,
+                &[("uploadId", &upload_id)],
+                body.into_bytes(),
+            )?;
+
+            // Complete request can fail even after responding with 200 status code.
+            let text = response.text()?;
+
+            if text.contains("<Error>") {
+                bail!(
+                    "s3 multipart upload failed ({}).",
+                    xml_value(&text, "Message").unwrap_or(text)
+                );
+            }
+
+            Ok(())
+        })();
+
+        if result.is_err() {
+            let _ = self.send(
+                client,
+                Method::DELETE,
+                &url,
+                &[("uploadId", &upload_id)],
+                vec![],
+            );
+        }
+
+        result
+    }
+
+    fn url(&self, bucket: &str, key: &str) -> Result<Url> {
+        let key = encode(key, false);
+
+        Ok(match &self.endpoint {
+            Some(endpoint) => endpoint.join(&format!(
+                "{}/{}/{}",
+                endpoint.path().trim_end_matches('/'),
+                bucket,
+                key
+            ))?,
+            None => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                bucket, self.region, key
+            )
+            .parse::<Url>()?,
+        })
+    }
+
+    /// Send a request signed with AWS signature version 4.
+    fn send(
+        &self,
+        client: &Client,
+        method: Method,
+        url: &Url,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<Response> {
+        let now = chrono::Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let time = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+
+        let mut query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", encode(k, true), encode(v, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query.join("&");
+
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", time.clone()),
+        ];
+
+        if let Some(session_token) = &self.session_token {
+            headers.push(("x-amz-security-token", session_token.to_owned()));
+        }
+
+        let signed_headers = headers.iter().map(|x| x.0).collect::<Vec<_>>().join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            url.path(),
+            query,
+            headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}\n", k, v.trim()))
+                .collect::<String>(),
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            time,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = hmac(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+
+        for data in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac(&key, data.as_bytes());
+        }
+
+        let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+        let mut request_url = url.clone();
+        request_url.set_query(if query.is_empty() { None } else { Some(&query) });
+
+        let mut request = client
+            .request(method, request_url)
+            .header(
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key_id, scope, signed_headers, signature
+                ),
+            )
+            .body(body);
+
+        // Host header is set by client itself.
+        for (name, value) in headers.into_iter().skip(1) {
+            request = request.header(name, value);
+        }
+
+        check(request.send()?)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn check(response: Response) -> Result<Response> {
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().unwrap_or_default();
+        bail!(
+            "upload failed with status {} ({}).",
+            status,
+            xml_value(&text, "Message").unwrap_or(text)
+        );
+    }
+
+    Ok(response)
+}
+
+/// Value of first `<name>` xml element.
+fn xml_value(xml: &str, name: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].to_owned())
+}
+
+/// Percent encode everything except unreserved characters (and slashes if `slash` is false).
+fn encode(value: &str, slash: bool) -> String {
+    value
+        .bytes()
+        .map(|x| match x {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (x as char).to_string()
+            }
+            b'/' if !slash => "/".to_owned(),
+            _ => format!("%{:02X}", x),
+        })
+        .collect()
+}
+
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(x) = std::str::from_utf8(&bytes[(i + 1)..(i + 3)])
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+            {
+                decoded.push(x);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
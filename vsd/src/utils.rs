@@ -1,31 +1,8 @@
-use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Result};
 use base64::Engine;
 use std::{env, path::Path};
 
-type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
-
-pub(super) fn format_bytes(bytesval: usize, precision: usize) -> (String, String, String) {
-    let mut val = bytesval as f32;
-
-    for unit in ["bytes", "KiB", "MiB", "GiB", "TiB"] {
-        if val < 1024.0 {
-            return (
-                format!("{:.precision$}", val, precision = precision),
-                unit.to_owned(),
-                format!("{:.precision$} {}", val, unit, precision = precision),
-            );
-        }
-
-        val /= 1024.0;
-    }
-
-    (
-        format!("{:.precision$}", bytesval, precision = precision),
-        "".to_owned(),
-        format!("{:.precision$}", bytesval, precision = precision),
-    )
-}
+pub(super) use vsd_core::utils::format_bytes;
 
 pub(super) fn format_download_bytes(downloaded: usize, total: usize) -> String {
     let downloaded = format_bytes(downloaded, 2);
@@ -42,6 +19,17 @@ pub(super) fn format_download_bytes(downloaded: usize, total: usize) -> String {
     }
 }
 
+/// Format seconds as HH:MM:SS.
+pub(super) fn format_duration(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
+
 pub(super) fn decode_base64<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>> {
     base64::engine::general_purpose::STANDARD
         .decode(input)
@@ -52,38 +40,6 @@ pub(super) fn decode_base64<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>> {
 //     base64::engine::general_purpose::STANDARD.encode(input)
 // }
 
-pub(super) fn decrypt_aes_128_cbc(
-    input: &mut [u8],
-    key: &[u8],
-    iv: Option<&Vec<u8>>,
-) -> Result<Vec<u8>> {
-    let key_length = key.len();
-
-    if key_length != 16 {
-        bail!("invalid key size i.e. {} but expected size 16.", key_length);
-    }
-
-    let mut key_c = [0_u8; 16];
-    key_c.copy_from_slice(key);
-
-    let mut iv_c = [0_u8; 16];
-
-    if let Some(iv) = iv {
-        let iv_length = key.len();
-
-        if iv_length != 16 {
-            bail!("invalid iv size i.e. {} but expected size 16.", iv_length);
-        }
-
-        iv_c.copy_from_slice(iv);
-    }
-
-    Aes128CbcDec::new(&key_c.into(), &iv_c.into())
-        .decrypt_padded_mut::<Pkcs7>(input)
-        .map(|x| x.to_vec())
-        .map_err(|x| anyhow!("{}", x))
-}
-
 pub(super) fn find_ffmpeg() -> Option<String> {
     Some(
         env::var("PATH")
@@ -105,3 +61,41 @@ pub(super) fn find_ffmpeg() -> Option<String> {
             .to_owned(),
     )
 }
+
+/// Split arguments on whitespaces, single or double quotes can be used to keep whitespaces.
+pub(super) fn split_args(args: &str) -> Result<Vec<String>> {
+    let mut split_args = vec![];
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+
+    for c in args.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (x, Some(y)) if x == y => quote = None,
+            (x, None) if x.is_whitespace() => {
+                if in_arg {
+                    split_args.push(std::mem::take(&mut arg));
+                    in_arg = false;
+                }
+            }
+            (x, _) => {
+                arg.push(x);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        bail!("unclosed quote in arguments {}", args);
+    }
+
+    if in_arg {
+        split_args.push(arg);
+    }
+
+    Ok(split_args)
+}